@@ -0,0 +1,343 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! ABI registry used to turn raw calldata, return data and logs into
+//! human-readable text.
+
+use std::collections::HashMap;
+
+use ethabi::{self, Token};
+use ethcore::log_entry::LogEntry;
+use ethcore_bytes::ToPretty;
+use ethereum_types::{Address, U256};
+use keccak_hash::keccak;
+
+use signatures::{self, SignatureDb};
+
+/// Canonical signature of a function or event, e.g. `transfer(address,uint256)`.
+pub fn signature<'a, I>(name: &str, kinds: I) -> String
+where
+    I: IntoIterator<Item = &'a ethabi::ParamType>,
+{
+    let kinds = kinds
+        .into_iter()
+        .map(|kind| format!("{}", kind))
+        .collect::<Vec<_>>();
+    format!("{}({})", name, kinds.join(","))
+}
+
+/// Functions of `contract` sorted by signature.
+///
+/// `ethabi` keeps them in a hash map, which iterates in a different order in
+/// every process, so anything picking functions from a seed has to sort them first.
+pub fn sorted_functions(contract: &ethabi::Contract) -> Vec<&ethabi::Function> {
+    let mut functions: Vec<_> = contract
+        .functions()
+        .map(|function| (signature(&function.name, function.inputs.iter().map(|param| &param.kind)), function))
+        .collect();
+    functions.sort_by(|a, b| a.0.cmp(&b.0));
+    functions.into_iter().map(|(_, function)| function).collect()
+}
+
+/// Four byte selector of the function with given canonical `signature`.
+pub fn selector(signature: &str) -> [u8; 4] {
+    let mut selector = [0u8; 4];
+    selector.copy_from_slice(&keccak(signature.as_bytes())[..4]);
+    selector
+}
+
+#[test]
+fn test_selector() {
+    assert_eq!(
+        selector(&signature(
+            "transfer",
+            &[ethabi::ParamType::Address, ethabi::ParamType::Uint(256)]
+        )),
+        [0xa9, 0x05, 0x9c, 0xbb]
+    );
+}
+
+/// Log decoded with a registered ABI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedLog {
+    /// Address of the contract which emitted the log.
+    pub address: Address,
+    /// Event name.
+    pub event: String,
+    /// Named event arguments.
+    pub params: Vec<ethabi::LogParam>,
+}
+
+/// ABIs and human-readable labels of known addresses.
+///
+/// Selectors and topics of contracts without a registered ABI are resolved
+/// with the signature database.
+#[derive(Debug, Default, Clone)]
+pub struct AbiRegistry {
+    contracts: HashMap<Address, ethabi::Contract>,
+    labels: HashMap<Address, String>,
+    signatures: SignatureDb,
+}
+
+impl AbiRegistry {
+    /// Registers the ABI of the contract deployed at `address`.
+    pub fn register(&mut self, address: Address, abi: ethabi::Contract) {
+        self.signatures.add_abi(&abi);
+        self.contracts.insert(address, abi);
+    }
+
+    /// Sets a human-readable label of `address`.
+    pub fn set_label<S: Into<String>>(&mut self, address: Address, label: S) {
+        self.labels.insert(address, label.into());
+    }
+
+    /// Adds signatures used to resolve selectors of unknown contracts.
+    pub fn add_signatures(&mut self, signatures: SignatureDb) {
+        self.signatures.extend(signatures);
+    }
+
+    pub fn signatures(&self) -> &SignatureDb {
+        &self.signatures
+    }
+
+    pub fn label(&self, address: &Address) -> Option<&str> {
+        self.labels.get(address).map(|label| label.as_str())
+    }
+
+    /// All labelled addresses.
+    pub fn labels(&self) -> &HashMap<Address, String> {
+        &self.labels
+    }
+
+    pub fn contract(&self, address: &Address) -> Option<&ethabi::Contract> {
+        self.contracts.get(address)
+    }
+
+    /// Returns the function of the contract at `address` selected by `input`.
+    pub fn function(&self, address: &Address, input: &[u8]) -> Option<&ethabi::Function> {
+        if input.len() < 4 {
+            return None;
+        }
+
+        self.contract(address).and_then(|contract| {
+            contract.functions().find(|function| {
+                let kinds = function.inputs.iter().map(|param| &param.kind);
+                selector(&signature(&function.name, kinds))[..] == input[..4]
+            })
+        })
+    }
+
+    /// `Label(0x..)` for labelled contracts, `label` for other labelled
+    /// addresses and the full hex address otherwise.
+    pub fn format_address(&self, address: &Address) -> String {
+        match (self.label(address), self.contract(address)) {
+            (Some(label), Some(_)) => format!("{}({:?})", label, address),
+            (Some(label), None) => label.into(),
+            (None, _) => format!("{:?}", address),
+        }
+    }
+
+    pub fn format_token(&self, token: &Token) -> String {
+        match *token {
+            Token::Address(ref address) => self.format_address(address),
+            Token::Uint(ref value) => format_uint(value),
+            Token::Int(ref value) => format_int(value),
+            Token::Bool(value) => value.to_string(),
+            Token::String(ref value) => format!("{:?}", value),
+            Token::Bytes(ref bytes) | Token::FixedBytes(ref bytes) => format!("0x{}", bytes.to_hex()),
+            Token::Array(ref tokens) | Token::FixedArray(ref tokens) => {
+                format!("[{}]", self.format_tokens(tokens))
+            }
+        }
+    }
+
+    fn format_tokens(&self, tokens: &[Token]) -> String {
+        tokens
+            .iter()
+            .map(|token| self.format_token(token))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn format_params(&self, params: &[ethabi::Param], tokens: &[Token]) -> String {
+        params
+            .iter()
+            .zip(tokens)
+            .map(|(param, token)| {
+                if param.name.is_empty() {
+                    self.format_token(token)
+                } else {
+                    format!("{}={}", param.name, self.format_token(token))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Name of the function called with `input`, e.g. `Token(0x..)::transfer`.
+    pub fn format_function(&self, address: &Address, input: &[u8]) -> String {
+        let name = match self.function(address, input) {
+            Some(function) => function.name.clone(),
+            None => match self.signatures.function(input) {
+                Some(signature) => match signatures::parse(signature) {
+                    Some((name, _)) => name.into(),
+                    None => signature.into(),
+                },
+                None if input.len() >= 4 => format!("0x{}", input[..4].to_hex()),
+                None => "fallback".into(),
+            },
+        };
+        format!("{}::{}", self.format_address(address), name)
+    }
+
+    /// Call with decoded arguments, e.g. `Token(0x..)::transfer(to=alice, amount=1e18)`.
+    pub fn format_call(&self, address: &Address, input: &[u8]) -> String {
+        let decoded = self.function(address, input).and_then(|function| {
+            let kinds = function.inputs.iter().map(|param| param.kind.clone()).collect::<Vec<_>>();
+            ethabi::decode(&kinds, &input[4..])
+                .ok()
+                .map(|tokens| self.format_params(&function.inputs, &tokens))
+        });
+
+        let decoded = decoded.or_else(|| {
+            self.signatures
+                .function(input)
+                .and_then(signatures::parse)
+                .and_then(|(_, kinds)| ethabi::decode(&kinds, &input[4..]).ok())
+                .map(|tokens| self.format_tokens(&tokens))
+        });
+
+        match decoded {
+            Some(args) => format!("{}({})", self.format_function(address, input), args),
+            None if input.len() > 4 => format!(
+                "{}(0x{})",
+                self.format_function(address, input),
+                input[4..].to_hex()
+            ),
+            None => format!("{}()", self.format_function(address, input)),
+        }
+    }
+
+    /// Return data of a call decoded with the function outputs.
+    pub fn format_output(&self, address: &Address, input: &[u8], output: &[u8]) -> String {
+        let decoded = self.function(address, input).and_then(|function| {
+            let kinds = function.outputs.iter().map(|param| param.kind.clone()).collect::<Vec<_>>();
+            ethabi::decode(&kinds, output).ok().map(|tokens| match tokens.len() {
+                0 => "()".into(),
+                1 => self.format_token(&tokens[0]),
+                _ => format!("({})", self.format_params(&function.outputs, &tokens)),
+            })
+        });
+
+        decoded.unwrap_or_else(|| format!("0x{}", output.to_hex()))
+    }
+
+    /// Decodes a log emitted by a contract with registered ABI.
+    pub fn decode_log(&self, log: &LogEntry) -> Option<DecodedLog> {
+        let topic = match log.topics.first() {
+            Some(topic) => *topic,
+            None => return None,
+        };
+
+        self.contract(&log.address).and_then(|contract| {
+            contract
+                .events()
+                .filter(|event| !event.anonymous && event.signature() == topic)
+                .filter_map(|event| {
+                    let raw = ethabi::RawLog::from((log.topics.clone(), log.data.clone()));
+                    event.parse_log(raw).ok().map(|parsed| DecodedLog {
+                        address: log.address,
+                        event: event.name.clone(),
+                        params: parsed.params,
+                    })
+                })
+                .next()
+        })
+    }
+
+    /// Log with decoded arguments, e.g. `Token(0x..)::Transfer(from=alice, to=bob, value=1e18)`.
+    pub fn format_log(&self, log: &LogEntry) -> String {
+        match self.decode_log(log) {
+            Some(decoded) => {
+                let params = decoded
+                    .params
+                    .iter()
+                    .map(|param| format!("{}={}", param.name, self.format_token(&param.value)))
+                    .collect::<Vec<_>>();
+                format!(
+                    "{}::{}({})",
+                    self.format_address(&log.address),
+                    decoded.event,
+                    params.join(", ")
+                )
+            }
+            None => {
+                let event = log.topics
+                    .first()
+                    .and_then(|topic| self.signatures.event(topic))
+                    .unwrap_or("log");
+                let topics = log.topics
+                    .iter()
+                    .map(|topic| format!("{:?}", topic))
+                    .collect::<Vec<_>>();
+                format!(
+                    "{}::{}(topics=[{}], data=0x{})",
+                    self.format_address(&log.address),
+                    event,
+                    topics.join(", "),
+                    log.data.to_hex()
+                )
+            }
+        }
+    }
+}
+
+/// Formats an unsigned integer, round values in scientific notation (`1e18`).
+pub fn format_uint(value: &U256) -> String {
+    let digits = format!("{}", value);
+    let significant = digits.trim_right_matches('0');
+    let zeros = digits.len() - significant.len();
+    if zeros >= 6 && !significant.is_empty() {
+        format!("{}e{}", significant, zeros)
+    } else {
+        digits
+    }
+}
+
+/// Formats a two's complement signed integer.
+pub fn format_int(value: &U256) -> String {
+    if value.bit(255) {
+        let abs = (!*value).overflowing_add(1.into()).0;
+        format!("-{}", format_uint(&abs))
+    } else {
+        format_uint(value)
+    }
+}
+
+#[test]
+fn test_format_uint() {
+    assert_eq!(format_uint(&0.into()), "0");
+    assert_eq!(format_uint(&1000.into()), "1000");
+    assert_eq!(format_uint(&::wei::from_ether(1)), "1e18");
+    assert_eq!(format_uint(&::wei::from_gwei(25)), "25e9");
+}
+
+#[test]
+fn test_format_int() {
+    let minus_five = (!U256::from(5)).overflowing_add(1.into()).0;
+    assert_eq!(format_int(&minus_five), "-5");
+    assert_eq!(format_int(&5.into()), "5");
+}
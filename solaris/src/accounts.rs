@@ -0,0 +1,142 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Deterministic test accounts with real keys.
+//!
+//! The secret of the account with index `i` is `keccak256("{seed}/{i}")`,
+//! hashed again while it's not a valid secret, so the same seed always gives
+//! the same accounts. Transactions sent from these accounts by `Evm` are
+//! ECDSA-signed, so `ecrecover` and `tx.origin` see real signatures.
+//!
+//! ```ignore
+//! let mut evm = solaris::evm();
+//! evm.with_accounts(accounts::DEFAULT_SEED, 3);
+//! let alice = evm.accounts()[0].address();
+//! evm.with_sender(alice).deploy(&code)?;
+//! ```
+
+use ethereum_types::{Address, H256};
+use ethkey::{KeyPair, Public, Secret};
+use keccak_hash::keccak;
+
+use error;
+use signing::{self, Domain, Eip712Types, Signature, Value};
+
+/// Seed of the accounts created by `Evm::with_accounts` unless given another one.
+pub const DEFAULT_SEED: &str = "solaris";
+
+/// Names given to the first accounts, the rest is named `account{index}`.
+pub const NAMES: &[&str] = &[
+    "alice", "bob", "carol", "dave", "eve", "frank", "grace", "heidi", "ivan", "judy",
+];
+
+/// Account with a known key pair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestAccount {
+    name: String,
+    key_pair: KeyPair,
+}
+
+impl TestAccount {
+    pub fn new<S: Into<String>>(name: S, key_pair: KeyPair) -> Self {
+        TestAccount {
+            name: name.into(),
+            key_pair,
+        }
+    }
+
+    /// Label of the account in traces and decoded logs.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn address(&self) -> Address {
+        self.key_pair.address()
+    }
+
+    pub fn secret(&self) -> &Secret {
+        self.key_pair.secret()
+    }
+
+    pub fn public(&self) -> &Public {
+        self.key_pair.public()
+    }
+
+    pub fn key_pair(&self) -> &KeyPair {
+        &self.key_pair
+    }
+
+    /// Signs `hash` as is.
+    pub fn sign(&self, hash: &H256) -> Signature {
+        signing::sign(self.secret(), hash)
+    }
+
+    /// Signs `message` like the `eth_sign` RPC.
+    pub fn eth_sign(&self, message: &[u8]) -> Signature {
+        signing::eth_sign(self.secret(), message)
+    }
+
+    /// Signs `value` of struct type `primary` in `domain`, like `eth_signTypedData`.
+    pub fn sign_typed_data(
+        &self,
+        types: &Eip712Types,
+        domain: &Domain,
+        primary: &str,
+        value: &Value,
+    ) -> error::Result<Signature> {
+        Ok(self.sign(&types.signing_hash(domain, primary, value)?))
+    }
+}
+
+/// Derives the key pair of the account with `index`.
+pub fn derive(seed: &str, index: usize) -> KeyPair {
+    let mut secret = keccak(format!("{}/{}", seed, index).as_bytes());
+    loop {
+        if let Ok(key_pair) = Secret::from_unsafe_slice(&secret[..]).and_then(KeyPair::from_secret) {
+            return key_pair;
+        }
+        secret = keccak(&secret[..]);
+    }
+}
+
+/// Name of the account with `index`.
+pub fn name(index: usize) -> String {
+    NAMES
+        .get(index)
+        .map_or_else(|| format!("account{}", index), |name| (*name).to_owned())
+}
+
+/// Derives `count` named accounts.
+pub fn generate(seed: &str, count: usize) -> Vec<TestAccount> {
+    (0..count)
+        .map(|index| TestAccount::new(name(index), derive(seed, index)))
+        .collect()
+}
+
+#[test]
+fn test_generate() {
+    let accounts = generate(DEFAULT_SEED, 12);
+    assert_eq!(accounts, generate(DEFAULT_SEED, 12));
+    assert_eq!(accounts[0].name(), "alice");
+    assert_eq!(accounts[11].name(), "account11");
+
+    let mut addresses: Vec<Address> = accounts.iter().map(TestAccount::address).collect();
+    addresses.sort();
+    addresses.dedup();
+    assert_eq!(addresses.len(), 12);
+
+    assert_ne!(generate("other", 1)[0].address(), accounts[0].address());
+}
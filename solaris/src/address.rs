@@ -0,0 +1,94 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Addresses of contracts created with `CREATE` and `CREATE2`.
+//!
+//! Predicting addresses allows deploying contracts which reference each other,
+//! see also `Evm::next_deploy_address`.
+
+use ethcore::executive::contract_address;
+use ethereum_types::{Address, H256, U256};
+use keccak_hash::keccak;
+use vm::CreateContractAddress;
+
+/// Address of the contract created by `sender` with `nonce`,
+/// either with a transaction or with `CREATE`.
+pub fn create_address(sender: &Address, nonce: &U256) -> Address {
+    contract_address(CreateContractAddress::FromSenderAndNonce, sender, nonce, &[]).0
+}
+
+/// Address of the contract created by `deployer` with `CREATE2`,
+/// `keccak256(0xff ++ deployer ++ salt ++ keccak256(init_code))[12..]`.
+pub fn create2_address(deployer: &Address, salt: &H256, init_code: &[u8]) -> Address {
+    let mut buffer = [0u8; 1 + 20 + 32 + 32];
+    buffer[0] = 0xff;
+    buffer[1..21].copy_from_slice(&deployer[..]);
+    buffer[21..53].copy_from_slice(&salt[..]);
+    buffer[53..].copy_from_slice(&keccak(init_code)[..]);
+    Address::from_slice(&keccak(&buffer[..])[12..])
+}
+
+#[test]
+fn test_create_address() {
+    let sender: Address = "6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0".parse().unwrap();
+    assert_eq!(
+        create_address(&sender, &0.into()),
+        "cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d".parse().unwrap()
+    );
+    assert_eq!(
+        create_address(&sender, &1.into()),
+        "343c43a37d37dff08ae8c4a11544c718abb4fcf8".parse().unwrap()
+    );
+}
+
+#[test]
+fn test_create2_address() {
+    // examples from EIP-1014
+    let cases = [
+        (
+            "0000000000000000000000000000000000000000",
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            &[0x00][..],
+            "4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38",
+        ),
+        (
+            "deadbeef00000000000000000000000000000000",
+            "000000000000000000000000feed000000000000000000000000000000000000",
+            &[0x00][..],
+            "d04116cdd17bebe565eb2422f2497e06cc1c9833",
+        ),
+        (
+            "00000000000000000000000000000000deadbeef",
+            "00000000000000000000000000000000000000000000000000000000cafebabe",
+            &[0xde, 0xad, 0xbe, 0xef][..],
+            "60f3f640a8508fc6a86d45df051962668e1e8ac7",
+        ),
+        (
+            "0000000000000000000000000000000000000000",
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            &[][..],
+            "e33c0c7f7df4809055c3eba6c09cfe4baf1bd9e0",
+        ),
+    ];
+    for &(deployer, salt, init_code, expected) in cases.iter() {
+        let deployer: Address = deployer.parse().unwrap();
+        let salt: H256 = salt.parse().unwrap();
+        assert_eq!(
+            create2_address(&deployer, &salt, init_code),
+            expected.parse::<Address>().unwrap()
+        );
+    }
+}
@@ -0,0 +1,287 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Live state of an `Evm`.
+//!
+//! The state trie is kept in a journaled database which never prunes nodes.
+//! Every change is committed and journaled right away, so any state root the
+//! `Evm` went through can be opened again: that's how snapshots are reverted
+//! to and how the state of past blocks is read.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use ethcore;
+use ethcore::client::EvmTestClient;
+use ethcore::executive::Executive;
+use ethcore::log_entry::LogEntry;
+use ethcore::state::{ApplyOutcome, CleanupMode, State, Substate};
+use ethcore::state_db::StateDB;
+use ethcore::trace::{Tracer, VMTracer};
+use ethcore_evm::{Factory, FinalizationResult};
+use ethcore_transaction::SignedTransaction;
+use ethereum_types::{Address, H256, U256};
+use vm;
+
+use cheatcodes;
+use executive;
+use state;
+
+const STATE: &str = "State failure.";
+
+pub struct Backend {
+    /// The current state, only taken while it's committed.
+    state: Option<State<StateDB>>,
+    /// Database without pending changes, other state roots are opened with it.
+    db: StateDB,
+    /// Era the next commit is journaled under.
+    era: u64,
+}
+
+impl fmt::Debug for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Backend")
+            .field("root", &self.root())
+            .field("era", &self.era)
+            .finish()
+    }
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::new()
+    }
+}
+
+impl Backend {
+    /// Genesis state of the foundation chain.
+    pub fn new() -> Self {
+        let client = EvmTestClient::new(&*::FOUNDATION).expect("Valid spec given; qed");
+        let (root, db) = client.state().clone().drop();
+        Backend {
+            state: Some(open(db.boxed_clone(), root)),
+            db,
+            era: 0,
+        }
+    }
+
+    pub fn state(&self) -> &State<StateDB> {
+        self.state.as_ref().expect("State is only taken while committed; qed")
+    }
+
+    fn state_mut(&mut self) -> &mut State<StateDB> {
+        self.state.as_mut().expect("State is only taken while committed; qed")
+    }
+
+    pub fn root(&self) -> H256 {
+        *self.state().root()
+    }
+
+    /// Opens the state with `root`, which has to be a root this backend went through.
+    pub fn at(&self, root: H256) -> State<StateDB> {
+        open(self.db.boxed_clone(), root)
+    }
+
+    /// Goes back, or forth, to the state with `root`, see `at`.
+    pub fn reset(&mut self, root: H256) {
+        self.state = Some(self.at(root));
+    }
+
+    /// Commits the changes and writes the new trie nodes to the database.
+    fn commit(&mut self) {
+        let mut state = self.state.take().expect("State is only taken while committed; qed");
+        state.commit().expect(STATE);
+        let (root, mut db) = state.drop();
+
+        let backing = db.journal_db().backing().clone();
+        let mut batch = backing.transaction();
+        db.journal_under(&mut batch, self.era, &root).expect(STATE);
+        backing.write(batch).expect(STATE);
+        self.era += 1;
+
+        // a fresh copy, the dropped state left its account cache behind
+        self.db = db.boxed_clone();
+        self.state = Some(open(self.db.boxed_clone(), root));
+    }
+
+    /// Executes `transaction` and commits its changes.
+    ///
+    /// Fails without changing the state if the transaction is invalid,
+    /// including when it's replay-protected for another chain than `chain_id`.
+    pub fn transact<T, V>(
+        &mut self,
+        env_info: &vm::EnvInfo,
+        chain_id: u64,
+        transaction: &SignedTransaction,
+        tracer: T,
+        vm_tracer: V,
+    ) -> Result<ApplyOutcome<T::Output, V::Output>, ethcore::error::Error>
+    where
+        T: Tracer,
+        V: VMTracer,
+    {
+        transaction.verify_basic(true, Some(chain_id), false)?;
+
+        let root = self.root();
+        let machine = ::FOUNDATION.engine.machine();
+        let result = self.state_mut().apply_with_tracing(env_info, machine, transaction, tracer, vm_tracer);
+        match result {
+            Ok(outcome) => {
+                self.commit();
+                Ok(outcome)
+            }
+            Err(error) => {
+                self.reset(root);
+                Err(error)
+            }
+        }
+    }
+
+    /// Executes a message call and discards its changes, returns its result and logs.
+    pub fn call<T, V>(
+        &mut self,
+        env_info: &vm::EnvInfo,
+        params: vm::ActionParams,
+        tracer: &mut T,
+        vm_tracer: &mut V,
+    ) -> vm::Result<(FinalizationResult, Vec<LogEntry>)>
+    where
+        T: Tracer,
+        V: VMTracer,
+    {
+        let machine = ::FOUNDATION.engine.machine();
+        let schedule = ::FOUNDATION.engine.schedule(env_info.number);
+        let state = self.state_mut();
+        state.checkpoint();
+        let mut substate = Substate::new();
+        let result = {
+            let mut executive = Executive::new(state, env_info, machine, &schedule);
+            executive.call(params, &mut substate, tracer, vm_tracer)
+        };
+        state.revert_to_checkpoint();
+        result.map(|result| (result, substate.logs))
+    }
+
+    /// Executes `transaction` like `transact`, with calls to the cheatcode address
+    /// handled natively, see `executive`.
+    ///
+    /// The transaction runs in the block environment of `cheats`, which is
+    /// changed by the cheatcodes it calls.
+    pub fn transact_with_cheatcodes<T, V>(
+        &mut self,
+        cheats: &mut cheatcodes::Cheats,
+        chain_id: u64,
+        transaction: &SignedTransaction,
+        tracer: T,
+        vm_tracer: V,
+    ) -> Result<ApplyOutcome<T::Output, V::Output>, ethcore::error::Error>
+    where
+        T: Tracer,
+        V: VMTracer,
+    {
+        transaction.verify_basic(true, Some(chain_id), false)?;
+
+        let root = self.root();
+        let machine = ::FOUNDATION.engine.machine();
+        let schedule = ::FOUNDATION.engine.schedule(cheats.env_info.number);
+        let factory = Factory::default();
+        let result = executive::Executive::new(self.state_mut(), cheats, machine, &schedule, &factory)
+            .transact(transaction, tracer, vm_tracer);
+        match result {
+            Ok(outcome) => {
+                self.commit();
+                Ok(outcome)
+            }
+            Err(error) => {
+                self.reset(root);
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Executes a message call like `call`, with calls to the cheatcode address
+    /// handled natively, see `executive`.
+    pub fn call_with_cheatcodes<T, V>(
+        &mut self,
+        cheats: &mut cheatcodes::Cheats,
+        params: vm::ActionParams,
+        tracer: &mut T,
+        vm_tracer: &mut V,
+    ) -> vm::Result<(FinalizationResult, Vec<LogEntry>)>
+    where
+        T: Tracer,
+        V: VMTracer,
+    {
+        let machine = ::FOUNDATION.engine.machine();
+        let schedule = ::FOUNDATION.engine.schedule(cheats.env_info.number);
+        let factory = Factory::default();
+        let state = self.state_mut();
+        state.checkpoint();
+        let mut substate = Substate::new();
+        let result = executive::Executive::new(state, cheats, machine, &schedule, &factory)
+            .call(params, &mut substate, tracer, vm_tracer);
+        state.revert_to_checkpoint();
+        result.map(|result| (result, substate.logs))
+    }
+
+    /// Replaces the code of `address`, creating the account if needed.
+    pub fn set_code(&mut self, address: &Address, code: Vec<u8>) {
+        self.state_mut().reset_code(address, code).expect(STATE);
+        self.commit();
+    }
+
+    /// Sets the balance of `address`, creating the account if needed.
+    pub fn set_balance(&mut self, address: &Address, balance: U256) {
+        set_balance(self.state_mut(), address, balance).expect(STATE);
+        self.commit();
+    }
+
+    /// Sets a storage slot of `address`, creating the account if needed.
+    pub fn set_storage(&mut self, address: &Address, key: H256, value: H256) {
+        self.state_mut().set_storage(address, key, value).expect(STATE);
+        self.commit();
+    }
+
+    /// Replaces `accounts` entirely, including their storage.
+    pub fn insert_accounts(&mut self, accounts: &BTreeMap<Address, state::Account>) {
+        self.state_mut().populate_from(state::to_pod(accounts));
+        self.commit();
+    }
+
+    /// Removes `addresses` from the state.
+    pub fn remove_accounts(&mut self, addresses: &[Address]) {
+        for address in addresses {
+            self.state_mut().kill_account(address);
+        }
+        self.commit();
+    }
+}
+
+/// Sets the balance of `address` in `state`, creating the account if needed.
+pub fn set_balance(state: &mut State<StateDB>, address: &Address, balance: U256) -> vm::Result<()> {
+    let current = state.balance(address)?;
+    if balance >= current {
+        state.add_balance(address, &(balance - current), CleanupMode::ForceCreate)?;
+    } else {
+        state.sub_balance(address, &(current - balance), &mut CleanupMode::NoEmpty)?;
+    }
+    Ok(())
+}
+
+fn open(db: StateDB, root: H256) -> State<StateDB> {
+    let start_nonce = ::FOUNDATION.engine.account_start_nonce(0);
+    State::from_existing(db, root, start_nonce, Default::default()).expect(STATE)
+}
@@ -0,0 +1,261 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Balance changes of chosen addresses.
+//!
+//! A `BalanceTracker` remembers the ether and ERC20 token balances at the time
+//! it was created and compares them with the current ones. Gas fees paid by
+//! the tracked addresses for the transactions executed in the meantime are
+//! reported separately, so payable logic can be checked without computing them.
+//!
+//! ```ignore
+//! let mut tracker = evm.track_balances(&[alice, contract]);
+//! tracker.track_token(&mut evm, token, &[alice])?;
+//! evm.with_sender(alice).transact(contract.functions().withdraw(amount))?;
+//! tracker.assert_change(&evm, alice, Delta::increase(amount));
+//! tracker.assert_change(&evm, contract, Delta::decrease(amount));
+//! tracker.assert_token_change(&mut evm, token, alice, Delta::decrease(fee));
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use ethabi::{self, ParamType, Token};
+use ethereum_types::{Address, U256};
+
+use error;
+use evm::Evm;
+
+/// Signed change of a balance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delta {
+    Increase(U256),
+    Decrease(U256),
+}
+
+impl Delta {
+    pub fn increase<T: Into<U256>>(value: T) -> Self {
+        Delta::Increase(value.into())
+    }
+
+    /// Decrease by `value`, `Increase(0)` if it's zero so that equal changes compare equal.
+    pub fn decrease<T: Into<U256>>(value: T) -> Self {
+        let value = value.into();
+        if value.is_zero() {
+            Delta::Increase(value)
+        } else {
+            Delta::Decrease(value)
+        }
+    }
+
+    /// Change from `before` to `after`.
+    pub fn between(before: U256, after: U256) -> Self {
+        if after >= before {
+            Delta::Increase(after - before)
+        } else {
+            Delta::Decrease(before - after)
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Delta::Increase(0.into())
+    }
+}
+
+impl Default for Delta {
+    fn default() -> Self {
+        Delta::Increase(0.into())
+    }
+}
+
+impl fmt::Display for Delta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Delta::Increase(ref value) => write!(f, "+{}", value),
+            Delta::Decrease(ref value) => write!(f, "-{}", value),
+        }
+    }
+}
+
+/// Ether balance change of a tracked address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceChange {
+    pub address: Address,
+    pub before: U256,
+    pub after: U256,
+    /// Gas fees paid by the address for the transactions executed since tracking started.
+    pub fees: U256,
+}
+
+impl BalanceChange {
+    /// Change of the balance, including the gas fees.
+    pub fn delta(&self) -> Delta {
+        Delta::between(self.before, self.after)
+    }
+
+    /// Change of the balance as if no gas fees were paid.
+    pub fn delta_without_fees(&self) -> Delta {
+        Delta::between(self.before, self.after.saturating_add(self.fees))
+    }
+}
+
+/// Balances to compare against, see `Evm::track_balances`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceTracker {
+    balances: BTreeMap<Address, U256>,
+    /// Token balances by token and holder.
+    tokens: BTreeMap<(Address, Address), U256>,
+    /// Number of transactions executed before tracking started.
+    transactions: usize,
+}
+
+impl BalanceTracker {
+    /// Starts tracking the ether balances of `addresses`.
+    pub fn new(evm: &Evm, addresses: &[Address]) -> Self {
+        BalanceTracker {
+            balances: addresses
+                .iter()
+                .map(|address| (*address, evm.balance(address)))
+                .collect(),
+            tokens: BTreeMap::new(),
+            transactions: evm.transactions().len(),
+        }
+    }
+
+    /// Starts tracking the balances of `holders` in the ERC20 `token` as well.
+    pub fn track_token(&mut self, evm: &mut Evm, token: Address, holders: &[Address]) -> error::Result<&mut Self> {
+        for holder in holders {
+            let balance = token_balance(evm, token, *holder)?;
+            self.tokens.insert((token, *holder), balance);
+        }
+        Ok(self)
+    }
+
+    /// Ether balance changes of all tracked addresses.
+    pub fn changes(&self, evm: &Evm) -> Vec<BalanceChange> {
+        self.balances
+            .keys()
+            .filter_map(|address| self.change(evm, address))
+            .collect()
+    }
+
+    /// Ether balance change of `address`, `None` if it's not tracked.
+    pub fn change(&self, evm: &Evm, address: &Address) -> Option<BalanceChange> {
+        let before = *self.balances.get(address)?;
+        let fees = evm.transactions()
+            .iter()
+            .skip(self.transactions)
+            .filter(|transaction| transaction.from == *address)
+            .fold(U256::zero(), |fees, transaction| {
+                fees.saturating_add(transaction.receipt.gas_used.saturating_mul(transaction.gas_price))
+            });
+        Some(BalanceChange {
+            address: *address,
+            before,
+            after: evm.balance(address),
+            fees,
+        })
+    }
+
+    /// Change of the balance of `holder` in `token`, `None` if it's not tracked.
+    pub fn token_change(&self, evm: &mut Evm, token: Address, holder: Address) -> error::Result<Option<Delta>> {
+        match self.tokens.get(&(token, holder)) {
+            Some(before) => Ok(Some(Delta::between(*before, token_balance(evm, token, holder)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Panics unless the ether balance of `address` changed by `expected`, not counting gas fees.
+    pub fn assert_change(&self, evm: &Evm, address: Address, expected: Delta) {
+        let change = self.tracked(evm, &address);
+        if change.delta_without_fees() != expected {
+            panic!(
+                "Balance of {:?} changed by {} (fees {}), expected {}",
+                address,
+                change.delta_without_fees(),
+                change.fees,
+                expected
+            );
+        }
+    }
+
+    /// Panics unless the ether balance of `address` changed by `expected`, gas fees included.
+    pub fn assert_change_with_fees(&self, evm: &Evm, address: Address, expected: Delta) {
+        let change = self.tracked(evm, &address);
+        if change.delta() != expected {
+            panic!(
+                "Balance of {:?} changed by {} including fees {}, expected {}",
+                address,
+                change.delta(),
+                change.fees,
+                expected
+            );
+        }
+    }
+
+    /// Panics unless the balance of `holder` in `token` changed by `expected`.
+    pub fn assert_token_change(&self, evm: &mut Evm, token: Address, holder: Address, expected: Delta) {
+        let delta = self.token_change(evm, token, holder)
+            .unwrap_or_else(|e| panic!("Unable to query the balance of {:?} in {:?}: {}", holder, token, e))
+            .unwrap_or_else(|| panic!("Balance of {:?} in {:?} is not tracked", holder, token));
+        if delta != expected {
+            panic!(
+                "Balance of {:?} in {:?} changed by {}, expected {}",
+                holder, token, delta, expected
+            );
+        }
+    }
+
+    fn tracked(&self, evm: &Evm, address: &Address) -> BalanceChange {
+        self.change(evm, address)
+            .unwrap_or_else(|| panic!("Balance of {:?} is not tracked", address))
+    }
+}
+
+/// Selector of `balanceOf(address)`.
+const BALANCE_OF: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+
+/// Balance of `holder` returned by `balanceOf(address)` of `token`.
+pub fn token_balance(evm: &mut Evm, token: Address, holder: Address) -> error::Result<U256> {
+    let mut data = BALANCE_OF.to_vec();
+    data.extend(ethabi::encode(&[Token::Address(holder)]));
+    let output = evm.static_call_to(token, data)?;
+    match ethabi::decode(&[ParamType::Uint(256)], &output)?.pop() {
+        Some(Token::Uint(balance)) => Ok(balance),
+        token => unreachable!("Decoded a single uint, got {:?}; qed", token),
+    }
+}
+
+#[test]
+fn test_delta() {
+    assert_eq!(Delta::between(5.into(), 8.into()), Delta::increase(3));
+    assert_eq!(Delta::between(8.into(), 5.into()), Delta::decrease(3));
+    assert_eq!(Delta::between(5.into(), 5.into()), Delta::decrease(0));
+    assert!(Delta::decrease(0).is_zero());
+    assert_eq!(Delta::decrease(3).to_string(), "-3");
+}
+
+#[test]
+fn test_balance_change() {
+    let change = BalanceChange {
+        address: 1.into(),
+        before: 100.into(),
+        after: 70.into(),
+        fees: 10.into(),
+    };
+    assert_eq!(change.delta(), Delta::decrease(30));
+    assert_eq!(change.delta_without_fees(), Delta::decrease(20));
+}
@@ -0,0 +1,164 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Blocks of queued transactions.
+//!
+//! Transactions sent with `Evm::transact` and alike are executed right away in
+//! the current block. Transactions queued with `Evm::queue_transaction` and alike
+//! wait until `Evm::mine` executes them together in the current block, with
+//! cumulative gas accounting against the block gas limit, and starts the next
+//! block. That allows testing logic which depends on transaction order.
+//!
+//! Either way, executed transactions are kept with their receipts and traces,
+//! see `Evm::transactions`.
+
+use std::collections::BTreeSet;
+
+use ethcore::log_entry::LogEntry;
+use ethcore_transaction::SignedTransaction;
+use ethereum_types::{Address, Bloom, H256, U256};
+
+use trace::CallTrace;
+
+/// Outcome of an executed transaction.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Receipt {
+    pub transaction_hash: H256,
+    pub block_number: u64,
+    pub from: Address,
+    /// Receiver, `None` for contract creation.
+    pub to: Option<Address>,
+    /// Whether the transaction was executed without reverting.
+    pub status: bool,
+    pub gas_used: U256,
+    /// Gas used in the block up to and including the transaction.
+    pub cumulative_gas_used: U256,
+    pub contract_address: Option<Address>,
+    pub logs: Vec<LogEntry>,
+    pub logs_bloom: Bloom,
+}
+
+/// Transaction executed by the `Evm`, see `Evm::transactions`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutedTransaction {
+    pub hash: H256,
+    pub from: Address,
+    pub nonce: U256,
+    /// Receiver, `None` for contract creation.
+    pub to: Option<Address>,
+    pub value: U256,
+    pub gas: U256,
+    pub gas_price: U256,
+    pub data: Vec<u8>,
+    pub block_number: u64,
+    pub receipt: Receipt,
+    /// Call frames of the transaction.
+    pub trace: Vec<CallTrace>,
+}
+
+/// Bloom filter of the addresses and topics of `logs`.
+pub fn logs_bloom(logs: &[LogEntry]) -> Bloom {
+    logs.iter().fold(Bloom::default(), |mut bloom, log| {
+        bloom.accrue_bloom(&log.bloom());
+        bloom
+    })
+}
+
+/// Block produced by `Evm::mine`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub number: u64,
+    pub timestamp: u64,
+    pub gas_limit: U256,
+    /// Gas used in the block, including transactions executed right away.
+    pub gas_used: U256,
+    /// State root after the last transaction.
+    pub state_root: H256,
+    /// Receipts of the included transactions, in execution order.
+    pub receipts: Vec<Receipt>,
+    /// Hashes of transactions dropped as invalid, with the reason.
+    pub rejected: Vec<(H256, String)>,
+}
+
+/// Index of the transaction to include next: the one with the highest gas price
+/// among the first queued transactions of every sender, the earliest queued on ties.
+pub(crate) fn next_transaction(pending: &[SignedTransaction]) -> Option<usize> {
+    let mut senders = BTreeSet::new();
+    let mut next: Option<usize> = None;
+    for (index, transaction) in pending.iter().enumerate() {
+        if !senders.insert(transaction.sender()) {
+            continue;
+        }
+        if next.map_or(true, |next| transaction.gas_price > pending[next].gas_price) {
+            next = Some(index);
+        }
+    }
+    next
+}
+
+#[cfg(test)]
+fn test_transaction(sender: u64, nonce: u64, gas_price: u64) -> SignedTransaction {
+    use ethcore_transaction::{Action, Transaction};
+
+    Transaction {
+        nonce: nonce.into(),
+        gas_price: gas_price.into(),
+        gas: 21_000.into(),
+        action: Action::Call(Address::zero()),
+        value: 0.into(),
+        data: vec![],
+    }.fake_sign(sender.into())
+}
+
+#[test]
+fn test_next_transaction() {
+    let mut pending = vec![
+        test_transaction(1, 0, 1),
+        test_transaction(1, 1, 10),
+        test_transaction(2, 0, 5),
+        test_transaction(3, 0, 5),
+    ];
+
+    let mut order = Vec::new();
+    while let Some(index) = next_transaction(&pending) {
+        let transaction = pending.remove(index);
+        order.push((transaction.sender(), transaction.nonce));
+    }
+    assert_eq!(
+        order,
+        vec![
+            (2.into(), 0.into()),
+            (3.into(), 0.into()),
+            (1.into(), 0.into()),
+            (1.into(), 1.into()),
+        ]
+    );
+}
+
+#[test]
+fn test_logs_bloom() {
+    use ethereum_types::BloomInput;
+
+    let log = LogEntry {
+        address: 1.into(),
+        topics: vec![2.into()],
+        data: vec![],
+    };
+    let bloom = logs_bloom(&[log]);
+    assert!(bloom.contains_input(BloomInput::Raw(&Address::from(1)[..]));
+    assert!(bloom.contains_input(BloomInput::Raw(&H256::from(2)[..]));
+    assert_eq!(logs_bloom(&[]), Bloom::default());
+}
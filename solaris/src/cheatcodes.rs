@@ -0,0 +1,299 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Cheatcodes callable from Solidity.
+//!
+//! Enabled with `Evm::enable_cheatcodes`, which deploys a stub at the
+//! conventional hevm address. Solidity tests declare the interface and call it:
+//!
+//! ```solidity
+//! contract Hevm {
+//!     function warp(uint256 timestamp) public;
+//!     function roll(uint256 number) public;
+//!     function prank(address sender) public;
+//!     function deal(address who, uint256 balance) public;
+//!     function store(address who, bytes32 slot, bytes32 value) public;
+//!     function expectRevert() public;
+//!     function recordLogs() public;
+//! }
+//! ```
+//!
+//! Calls to the cheatcode address are handled natively while the transaction
+//! runs, see `executive`, so their effects are visible right after the call:
+//! `warp` and `roll` set the block timestamp and number, `deal` and `store`
+//! the balance and storage of an account, and `recordLogs` starts collecting
+//! logs, see `Evm::recorded_logs`. `prank` sets `msg.sender` of the next call
+//! made by the calling contract, `expectRevert` requires that call to fail and
+//! turns its failure into a success. A call expected to revert which succeeds
+//! fails the whole transaction.
+//!
+//! Effects of cheatcodes called from a frame which fails are undone along with
+//! its state changes.
+
+use ethabi::{self, ParamType, Token};
+use ethcore::log_entry::LogEntry;
+use ethereum_types::{Address, H256, U256};
+use vm;
+
+use abi;
+use signatures::SignatureDb;
+
+/// Address of the cheatcode contract, `address(keccak256("hevm cheat code"))`.
+pub const ADDRESS: &str = "7109709ECfa91a80626fF3989D68f67F5b1DD12D";
+
+/// Signatures of all supported cheatcodes.
+pub const SIGNATURES: &[&str] = &[
+    "warp(uint256)",
+    "roll(uint256)",
+    "prank(address)",
+    "deal(address,uint256)",
+    "store(address,bytes32,bytes32)",
+    "expectRevert()",
+    "recordLogs()",
+];
+
+pub fn address() -> Address {
+    ADDRESS.parse().expect("Valid address; qed")
+}
+
+/// Signature database of all supported cheatcodes, used to decode them in traces.
+pub fn signatures() -> SignatureDb {
+    let mut db = SignatureDb::default();
+    for signature in SIGNATURES {
+        db.insert(signature);
+    }
+    db
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cheatcode {
+    /// Sets `block.timestamp`.
+    Warp(U256),
+    /// Sets `block.number`.
+    Roll(U256),
+    /// Sets `msg.sender` of the next call made by the calling contract.
+    Prank(Address),
+    /// Sets the balance of an account.
+    Deal(Address, U256),
+    /// Sets a storage slot of an account.
+    Store(Address, H256, H256),
+    /// Expects the next call to revert.
+    ExpectRevert,
+    /// Starts recording logs.
+    RecordLogs,
+}
+
+impl Cheatcode {
+    /// Decodes a cheatcode from calldata, `None` if it's not a supported cheatcode.
+    pub fn decode(input: &[u8]) -> Option<Cheatcode> {
+        if input.len() < 4 {
+            return None;
+        }
+        let selector = &input[..4];
+        let is = |signature: &str| abi::selector(signature)[..] == *selector;
+        let decode = |kinds: &[ParamType]| ethabi::decode(kinds, &input[4..]).ok();
+
+        let cheatcode = if is("warp(uint256)") {
+            let tokens = decode(&[ParamType::Uint(256)])?;
+            Cheatcode::Warp(uint(&tokens[0])?)
+        } else if is("roll(uint256)") {
+            let tokens = decode(&[ParamType::Uint(256)])?;
+            Cheatcode::Roll(uint(&tokens[0])?)
+        } else if is("prank(address)") {
+            let tokens = decode(&[ParamType::Address])?;
+            Cheatcode::Prank(address_of(&tokens[0])?)
+        } else if is("deal(address,uint256)") {
+            let tokens = decode(&[ParamType::Address, ParamType::Uint(256)])?;
+            Cheatcode::Deal(address_of(&tokens[0])?, uint(&tokens[1])?)
+        } else if is("store(address,bytes32,bytes32)") {
+            let kinds = [
+                ParamType::Address,
+                ParamType::FixedBytes(32),
+                ParamType::FixedBytes(32),
+            ];
+            let tokens = decode(&kinds)?;
+            Cheatcode::Store(address_of(&tokens[0])?, word(&tokens[1])?, word(&tokens[2])?)
+        } else if is("expectRevert()") {
+            Cheatcode::ExpectRevert
+        } else if is("recordLogs()") {
+            Cheatcode::RecordLogs
+        } else {
+            return None;
+        };
+        Some(cheatcode)
+    }
+}
+
+fn uint(token: &Token) -> Option<U256> {
+    match *token {
+        Token::Uint(value) => Some(value),
+        _ => None,
+    }
+}
+
+fn address_of(token: &Token) -> Option<Address> {
+    match *token {
+        Token::Address(address) => Some(address),
+        _ => None,
+    }
+}
+
+fn word(token: &Token) -> Option<H256> {
+    match *token {
+        Token::FixedBytes(ref bytes) if bytes.len() == 32 => Some(H256::from_slice(bytes)),
+        _ => None,
+    }
+}
+
+/// Unmet expectation of a cheatcode, the transaction fails if there is any.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// A call following `expectRevert` succeeded.
+    NotReverted { to: Address, input: Vec<u8> },
+    /// `expectRevert` wasn't followed by a call.
+    NoCall,
+    /// The cheatcode address was called with unsupported calldata.
+    Unknown { input: Vec<u8> },
+}
+
+impl Violation {
+    pub fn format(&self, abi: &abi::AbiRegistry) -> String {
+        match *self {
+            Violation::NotReverted { ref to, ref input } => {
+                format!("Expected revert of {}", abi.format_call(to, input))
+            }
+            Violation::NoCall => "expectRevert() was not followed by a call".into(),
+            Violation::Unknown { ref input } => {
+                format!("Unknown cheatcode: {}", abi.format_call(&address(), input))
+            }
+        }
+    }
+}
+
+/// Block environment and recorded logs as changed by cheatcodes, shared by
+/// all frames of a transaction.
+#[derive(Debug, Clone)]
+pub struct Cheats {
+    pub env_info: vm::EnvInfo,
+    /// Logs emitted since `recordLogs` was called, `None` before.
+    pub recorded_logs: Option<Vec<LogEntry>>,
+    pub violations: Vec<Violation>,
+}
+
+/// What a failed frame restores, see `Cheats::checkpoint`.
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint {
+    number: u64,
+    timestamp: u64,
+    recorded_logs: Option<usize>,
+}
+
+impl Cheats {
+    pub fn new(env_info: vm::EnvInfo, recorded_logs: Option<Vec<LogEntry>>) -> Self {
+        Cheats {
+            env_info,
+            recorded_logs,
+            violations: Vec::new(),
+        }
+    }
+
+    /// Captures the effects a frame about to be executed can undo.
+    ///
+    /// Violations are kept, an unmet expectation fails the transaction
+    /// even if the frame it happened in fails.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            number: self.env_info.number,
+            timestamp: self.env_info.timestamp,
+            recorded_logs: self.recorded_logs.as_ref().map(Vec::len),
+        }
+    }
+
+    pub fn revert_to(&mut self, checkpoint: Checkpoint) {
+        self.env_info.number = checkpoint.number;
+        self.env_info.timestamp = checkpoint.timestamp;
+        match checkpoint.recorded_logs {
+            Some(len) => {
+                if let Some(ref mut logs) = self.recorded_logs {
+                    logs.truncate(len);
+                }
+            }
+            None => self.recorded_logs = None,
+        }
+    }
+
+    pub fn record_logs(&mut self) {
+        if self.recorded_logs.is_none() {
+            self.recorded_logs = Some(Vec::new());
+        }
+    }
+
+    /// Records `log` if `recordLogs` was called.
+    pub fn log(&mut self, log: &LogEntry) {
+        if let Some(ref mut logs) = self.recorded_logs {
+            logs.push(log.clone());
+        }
+    }
+}
+
+#[test]
+fn test_decode() {
+    let mut input = abi::selector("warp(uint256)").to_vec();
+    input.extend(ethabi::encode(&[Token::Uint(100.into())]));
+    assert_eq!(Cheatcode::decode(&input), Some(Cheatcode::Warp(100.into())));
+
+    let mut input = abi::selector("store(address,bytes32,bytes32)").to_vec();
+    input.extend(ethabi::encode(&[
+        Token::Address(2.into()),
+        Token::FixedBytes(vec![0; 32]),
+        Token::FixedBytes(vec![1; 32]),
+    ]));
+    assert_eq!(
+        Cheatcode::decode(&input),
+        Some(Cheatcode::Store(2.into(), 0.into(), H256::from_slice(&[1; 32])))
+    );
+
+    assert_eq!(Cheatcode::decode(&abi::selector("warp(uint256)")), None);
+    assert_eq!(Cheatcode::decode(&[1, 2, 3]), None);
+}
+
+#[test]
+fn test_revert_to() {
+    let log = LogEntry {
+        address: 1.into(),
+        topics: vec![],
+        data: vec![],
+    };
+    let mut cheats = Cheats::new(Default::default(), None);
+    let before = cheats.checkpoint();
+    cheats.env_info.timestamp = 1000;
+    cheats.record_logs();
+    cheats.log(&log);
+    let recording = cheats.checkpoint();
+    cheats.env_info.number = 42;
+    cheats.log(&log);
+    cheats.violations.push(Violation::NoCall);
+
+    cheats.revert_to(recording);
+    assert_eq!(cheats.env_info.number, 0);
+    assert_eq!(cheats.env_info.timestamp, 1000);
+    assert_eq!(cheats.recorded_logs.as_ref().map(Vec::len), Some(1));
+
+    cheats.revert_to(before);
+    assert_eq!(cheats.env_info.timestamp, 0);
+    assert!(cheats.recorded_logs.is_none());
+    assert_eq!(cheats.violations, vec![Violation::NoCall]);
+}
@@ -0,0 +1,177 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! `console.log` for Solidity.
+//!
+//! The conventional `console.sol` library sends its arguments with a
+//! `STATICCALL` to an address without code, which succeeds without doing
+//! anything. The calls are picked up from the call trace and decoded here.
+//! Both `uint` and `uint256` spellings of the signatures are recognised, as
+//! used by older and newer versions of the library.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ethabi::{self, ParamType, Token};
+use ethereum_types::Address;
+
+use abi::{self, AbiRegistry};
+use signatures;
+use trace::{CallKind, CallTrace};
+
+/// Address of the console, `address(bytes20(bytes("console.log")))`.
+pub const ADDRESS: &str = "000000000000000000636F6e736F6c652e6c6f67";
+
+lazy_static! {
+    static ref SIGNATURES: BTreeMap<[u8; 4], Vec<ParamType>> = {
+        let mut map = BTreeMap::new();
+        for signature in all_signatures() {
+            let kinds = signatures::parse(&signature)
+                .expect("Generated signatures are valid; qed")
+                .1;
+            map.insert(abi::selector(&signature), kinds);
+        }
+        map
+    };
+}
+
+pub fn address() -> Address {
+    ADDRESS.parse().expect("Valid address; qed")
+}
+
+/// Signatures of all `console.log` overloads.
+fn all_signatures() -> BTreeSet<String> {
+    let mut out = BTreeSet::new();
+    out.insert("log()".to_owned());
+
+    let single = ["int", "int256", "bytes", "string", "bool", "address", "uint", "uint256"];
+    for kind in &single {
+        out.insert(format!("log({})", kind));
+    }
+    for &(name, kind) in &[
+        ("logInt", "int"),
+        ("logInt", "int256"),
+        ("logUint", "uint"),
+        ("logUint", "uint256"),
+        ("logString", "string"),
+        ("logBool", "bool"),
+        ("logAddress", "address"),
+        ("logBytes", "bytes"),
+    ] {
+        out.insert(format!("{}({})", name, kind));
+    }
+    for size in 1..33 {
+        out.insert(format!("logBytes{}(bytes{})", size, size));
+        out.insert(format!("log(bytes{})", size));
+    }
+
+    // every combination of 2 to 4 of the basic types
+    for uint in &["uint", "uint256"] {
+        let kinds = [*uint, "string", "bool", "address"];
+        let mut combinations: Vec<Vec<&str>> = kinds.iter().map(|kind| vec![*kind]).collect();
+        for _ in 1..4 {
+            combinations = combinations
+                .iter()
+                .flat_map(|prefix| {
+                    kinds.iter().map(move |kind| {
+                        let mut combination = prefix.clone();
+                        combination.push(*kind);
+                        combination
+                    })
+                })
+                .collect();
+            out.extend(
+                combinations
+                    .iter()
+                    .map(|combination| format!("log({})", combination.join(","))),
+            );
+        }
+    }
+
+    out
+}
+
+/// Decodes a `console.log` call into the message, arguments separated with spaces.
+///
+/// Returns `None` if `input` is not a known `console.log` overload.
+pub fn decode(registry: &AbiRegistry, input: &[u8]) -> Option<String> {
+    if input.len() < 4 {
+        return None;
+    }
+    let mut selector = [0u8; 4];
+    selector.copy_from_slice(&input[..4]);
+
+    let kinds = SIGNATURES.get(&selector)?;
+    let tokens = ethabi::decode(kinds, &input[4..]).ok()?;
+    let message = tokens
+        .iter()
+        .map(|token| match *token {
+            Token::String(ref text) => text.clone(),
+            Token::Uint(ref value) => value.to_string(),
+            _ => registry.format_token(token),
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(message)
+}
+
+/// Messages logged by all frames, in execution order, including frames which failed later.
+pub fn messages(traces: &[CallTrace], registry: &AbiRegistry) -> Vec<String> {
+    let console = address();
+    let mut out = Vec::new();
+    collect(traces, &console, registry, &mut out);
+    out
+}
+
+fn collect(traces: &[CallTrace], console: &Address, registry: &AbiRegistry, out: &mut Vec<String>) {
+    for trace in traces {
+        if trace.to == *console && trace.kind != CallKind::SelfDestruct {
+            out.extend(decode(registry, &trace.input));
+        }
+        collect(&trace.subtraces, console, registry, out);
+    }
+}
+
+#[test]
+fn test_decode() {
+    let registry = AbiRegistry::default();
+
+    let mut input = abi::selector("log(string,uint256)").to_vec();
+    input.extend(ethabi::encode(&[Token::String("balance".into()), Token::Uint(5.into())]));
+    assert_eq!(decode(&registry, &input), Some("balance 5".into()));
+
+    let mut input = abi::selector("log(uint,bool,address,string)").to_vec();
+    input.extend(ethabi::encode(&[
+        Token::Uint(1.into()),
+        Token::Bool(true),
+        Token::Address(2.into()),
+        Token::String("end".into()),
+    ]));
+    assert_eq!(
+        decode(&registry, &input),
+        Some(format!("1 true {:?} end", Address::from(2)))
+    );
+
+    assert_eq!(decode(&registry, &abi::selector("log(string,uint256)")), None);
+    assert_eq!(decode(&registry, &abi::selector("transfer(address,uint256)")), None);
+}
+
+#[test]
+fn test_signatures() {
+    // log(), 8 single values, 8 named, 64 bytesN, and (16 + 64 + 256) combinations
+    // per uint spelling, of which (9 + 27 + 81) without uint are the same for both
+    assert_eq!(all_signatures().len(), 1 + 8 + 8 + 64 + 2 * (16 + 64 + 256) - (9 + 27 + 81));
+    assert_eq!(SIGNATURES.len(), all_signatures().len());
+}
@@ -0,0 +1,323 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Differential testing of two implementations of the same contract.
+//!
+//! Both versions are deployed by the current sender from the same snapshot,
+//! so they end up at the same address. Every run replays the same random
+//! sequence of calls to the functions of the shared ABI against each of them
+//! and compares, after every call, the success, return data, emitted events,
+//! selected storage slots and balances. The first divergence is reported.
+//!
+//! ```ignore
+//! let mut tester = DifferentialTester::new(token_abi);
+//! tester.with_actors(vec![alice, bob]).with_storage(vec![0.into()]);
+//! tester.assert(&mut evm, &old_code, &new_code);
+//! ```
+
+use std::fmt;
+
+use ethabi::{self, Token};
+use ethereum_types::{Address, H256, U256};
+use rand::Rng;
+use rustc_hex::ToHex;
+
+use abi;
+use evm::{Evm, Snapshot};
+use fuzz;
+
+/// Observable effects of a single call.
+#[derive(Debug, Clone, PartialEq)]
+struct Outcome {
+    success: bool,
+    output: Vec<u8>,
+    /// Topics and data of emitted logs.
+    logs: Vec<(Vec<H256>, Vec<u8>)>,
+    storage: Vec<H256>,
+    balances: Vec<U256>,
+}
+
+/// First difference between the two implementations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// Decoded calls up to and including the diverging one.
+    pub calls: Vec<String>,
+    /// What differs, e.g. `return data`.
+    pub what: String,
+    pub old: String,
+    pub new: String,
+    /// Seed to replay the runs with.
+    pub seed: u64,
+    pub run: usize,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Implementations diverge in {} on run {} (SOLARIS_FUZZ_SEED={})",
+            self.what, self.run, self.seed
+        )?;
+        writeln!(f, "  old: {}", self.old)?;
+        writeln!(f, "  new: {}", self.new)?;
+        write!(f, "Call sequence:")?;
+        for (index, call) in self.calls.iter().enumerate() {
+            write!(f, "\n  {}. {}", index + 1, call)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    sender: Address,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DifferentialTester {
+    abi: ethabi::Contract,
+    runs: usize,
+    depth: usize,
+    seed: u64,
+    actors: Vec<Address>,
+    storage: Vec<H256>,
+    balances: Vec<Address>,
+}
+
+impl DifferentialTester {
+    /// Compares implementations of `abi`.
+    pub fn new(abi: ethabi::Contract) -> Self {
+        DifferentialTester {
+            abi,
+            runs: fuzz::runs_from_env(64),
+            depth: 16,
+            seed: fuzz::seed_from_env(),
+            actors: vec![],
+            storage: vec![],
+            balances: vec![],
+        }
+    }
+
+    pub fn with_runs(&mut self, runs: usize) -> &mut Self {
+        self.runs = runs;
+        self
+    }
+
+    /// Sets the number of calls of every run.
+    pub fn with_depth(&mut self, depth: usize) -> &mut Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_seed(&mut self, seed: u64) -> &mut Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the senders of the calls, the current sender of the `Evm` by default.
+    pub fn with_actors(&mut self, actors: Vec<Address>) -> &mut Self {
+        self.actors = actors;
+        self
+    }
+
+    /// Sets the storage slots of the contract compared after every call.
+    pub fn with_storage(&mut self, slots: Vec<H256>) -> &mut Self {
+        self.storage = slots;
+        self
+    }
+
+    /// Sets the accounts whose balances are compared after every call,
+    /// the balance of the contract is always compared.
+    pub fn with_balances(&mut self, accounts: Vec<Address>) -> &mut Self {
+        self.balances = accounts;
+        self
+    }
+
+    /// Deploys `old` and `new` init code and compares them on random call sequences.
+    ///
+    /// The `Evm` is left in the state it was in before.
+    pub fn check(&self, evm: &mut Evm, old: &[u8], new: &[u8]) -> Result<(), Divergence> {
+        let sender = evm.sender();
+        let actors = if self.actors.is_empty() {
+            vec![sender]
+        } else {
+            self.actors.clone()
+        };
+
+        let base = evm.snapshot();
+        let deploy = |evm: &mut Evm, code: &[u8]| -> (Address, Snapshot) {
+            evm.revert_to(&base);
+            let address = evm.deploy(code).expect("Unable to deploy the implementation.");
+            (address, evm.snapshot())
+        };
+        let (old_address, old_snapshot) = deploy(evm, old);
+        let (new_address, new_snapshot) = deploy(evm, new);
+        assert_eq!(
+            old_address, new_address,
+            "Both implementations must be deployed at the same address."
+        );
+
+        let mut addresses: Vec<Address> = evm.dump_accounts().keys().cloned().collect();
+        addresses.extend(actors.iter().cloned());
+
+        let mut rng = fuzz::rng(self.seed);
+        let mut result = Ok(());
+        for run in 0..self.runs {
+            let steps: Vec<Step> = (0..self.depth)
+                .filter_map(|_| self.generate(&mut rng, &actors, &addresses))
+                .collect();
+            let old_outcomes = self.replay(evm, &old_snapshot, old_address, &steps);
+            let new_outcomes = self.replay(evm, &new_snapshot, new_address, &steps);
+
+            let diverging = old_outcomes
+                .iter()
+                .zip(&new_outcomes)
+                .position(|(old, new)| old != new);
+            if let Some(index) = diverging {
+                let (what, old, new) = describe(&old_outcomes[index], &new_outcomes[index]);
+                let mut registry = evm.abi().clone();
+                registry.register(old_address, self.abi.clone());
+                result = Err(Divergence {
+                    calls: steps[..index + 1]
+                        .iter()
+                        .map(|step| {
+                            format!("from {:?}: {}", step.sender, registry.format_call(&old_address, &step.data))
+                        })
+                        .collect(),
+                    what,
+                    old,
+                    new,
+                    seed: self.seed,
+                    run,
+                });
+                break;
+            }
+        }
+
+        evm.revert_to(&base);
+        evm.with_sender(sender);
+        result
+    }
+
+    /// Like `check`, but panics with the divergence.
+    pub fn assert(&self, evm: &mut Evm, old: &[u8], new: &[u8]) {
+        if let Err(divergence) = self.check(evm, old, new) {
+            panic!("{}", divergence);
+        }
+    }
+
+    fn generate<R: Rng>(&self, rng: &mut R, actors: &[Address], addresses: &[Address]) -> Option<Step> {
+        let functions = abi::sorted_functions(&self.abi);
+        let function = rng.choose(&functions)?;
+        let tokens: Vec<Token> = function
+            .inputs
+            .iter()
+            .map(|param| fuzz::generate(rng, &param.kind, addresses))
+            .collect();
+
+        Some(Step {
+            sender: *rng.choose(actors).expect("There is at least one actor; qed"),
+            data: function
+                .encode_input(&tokens)
+                .expect("Tokens are generated from the function inputs; qed"),
+        })
+    }
+
+    fn replay(&self, evm: &mut Evm, snapshot: &Snapshot, address: Address, steps: &[Step]) -> Vec<Outcome> {
+        evm.revert_to(snapshot);
+        steps
+            .iter()
+            .map(|step| {
+                let (success, output, logs) = match evm.with_sender(step.sender).transact_to(address, step.data.clone()) {
+                    Ok(output) => (
+                        output.is_success(),
+                        output.output().to_vec(),
+                        output
+                            .logs()
+                            .iter()
+                            .map(|log| (log.topics.clone(), log.data.clone()))
+                            .collect(),
+                    ),
+                    Err(e) => (false, e.to_string().into_bytes(), vec![]),
+                };
+
+                let account = evm.account(&address).unwrap_or_default();
+                let storage = self.storage
+                    .iter()
+                    .map(|slot| account.storage.get(slot).cloned().unwrap_or_default())
+                    .collect();
+                let balances = Some(address)
+                    .into_iter()
+                    .chain(self.balances.iter().cloned())
+                    .map(|account| evm.account(&account).map_or_else(U256::zero, |account| account.balance))
+                    .collect();
+
+                Outcome {
+                    success,
+                    output,
+                    logs,
+                    storage,
+                    balances,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Names the first differing part of two outcomes.
+fn describe(old: &Outcome, new: &Outcome) -> (String, String, String) {
+    if old.success != new.success {
+        let status = |success: bool| if success { "success" } else { "revert" }.to_owned();
+        return ("status".into(), status(old.success), status(new.success));
+    }
+    if old.output != new.output {
+        let hex = |output: &[u8]| format!("0x{}", output.to_hex());
+        return ("return data".into(), hex(&old.output), hex(&new.output));
+    }
+    if old.logs != new.logs {
+        return ("events".into(), format!("{:?}", old.logs), format!("{:?}", new.logs));
+    }
+    if old.storage != new.storage {
+        return ("storage".into(), format!("{:?}", old.storage), format!("{:?}", new.storage));
+    }
+    ("balances".into(), format!("{:?}", old.balances), format!("{:?}", new.balances))
+}
+
+#[test]
+fn test_describe() {
+    let outcome = Outcome {
+        success: true,
+        output: vec![1],
+        logs: vec![],
+        storage: vec![],
+        balances: vec![0.into()],
+    };
+    let other = Outcome {
+        output: vec![2],
+        ..outcome.clone()
+    };
+    assert_eq!(
+        describe(&outcome, &other),
+        ("return data".to_owned(), "0x01".to_owned(), "0x02".to_owned())
+    );
+
+    let other = Outcome {
+        balances: vec![1.into()],
+        ..outcome.clone()
+    };
+    assert_eq!(describe(&outcome, &other).0, "balances");
+}
@@ -16,7 +16,9 @@
 
 use ethabi;
 use ethcore::client::EvmTestError;
+use ethereum_types::{Address, U256};
 use evm;
+use rustc_hex::ToHex;
 
 error_chain! {
     types {
@@ -24,7 +26,10 @@ error_chain! {
     }
 
     foreign_links {
+        Io(::std::io::Error);
         Ethabi(ethabi::Error);
+        Hex(::rustc_hex::FromHexError);
+        Ethkey(::ethkey::Error);
         TransactError(evm::TransactError);
     }
 
@@ -35,6 +40,61 @@ error_chain! {
             display("{:?}", err),
 
         }
+
+        Reverted(data: Vec<u8>) {
+            description("Execution reverted"),
+            display("Execution reverted: 0x{}", data.to_hex()),
+        }
+
+        Cheatcode(msg: String) {
+            description("Cheatcode expectation not met"),
+            display("Cheatcode expectation not met: {}", msg),
+        }
+
+        MissingKey(address: Address) {
+            description("No key to sign the transaction with"),
+            display("No key to sign the transaction of {:?} with, see `Evm::with_accounts`", address),
+        }
+
+        InvalidNonce(expected: U256, got: U256) {
+            description("Invalid transaction nonce"),
+            display("Invalid transaction nonce: expected {}, got {}", expected, got),
+        }
+
+        WrongChain(expected: u64, got: u64) {
+            description("Transaction signed for another chain"),
+            display("Transaction signed for chain {}, expected {}", got, expected),
+        }
+
+        InsufficientBalance(required: U256, balance: U256) {
+            description("Insufficient balance for gas and value"),
+            display("Insufficient balance for gas and value: required {}, got {}", required, balance),
+        }
+
+        IntrinsicGas(required: U256, got: U256) {
+            description("Gas limit below the intrinsic gas"),
+            display("Gas limit below the intrinsic gas: required {}, got {}", required, got),
+        }
+
+        BlockGasLimit(limit: U256, got: U256) {
+            description("Gas limit above the block gas limit"),
+            display("Gas limit above the block gas limit: limit {}, got {}", limit, got),
+        }
+
+        UnknownBlock(number: u64) {
+            description("State of the block is not known"),
+            display("State of block {} is not known", number),
+        }
+
+        Signing(msg: String) {
+            description("Invalid signature or typed data"),
+            display("Invalid signature or typed data: {}", msg),
+        }
+
+        Fork(msg: String) {
+            description("Invalid fork data"),
+            display("Invalid fork data: {}", msg),
+        }
     }
 }
 
@@ -14,30 +14,79 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 
+use abi;
+use accounts;
+use address;
+use backend;
+use balances;
+use chain;
+use cheatcodes;
+use console;
 use error;
 use ethabi;
 use ethabi::ContractFunction;
 use ethcore;
-use ethcore::client::{EvmTestClient, TransactResult};
+use ethcore::state::State;
+use ethcore::state_db::StateDB;
+use ethcore::trace::{Tracer, VMTracer};
 use ethcore_transaction::{Action, SignedTransaction, Transaction};
+use ethkey::Secret;
 use ethereum_types::{Address, H160, H256, U256};
 use std::error::Error;
 use std::fmt;
 use vm;
 
+use fork;
+use mock;
+use profile;
+use render;
+use signatures;
+use state;
 use trace;
+use wei;
 
 #[derive(Debug)]
 pub struct Evm {
-    evm: EvmTestClient<'static>,
+    backend: backend::Backend,
     sender: Address,
     contract_address: Option<Address>,
     value: U256,
     gas: U256,
     gas_price: U256,
     logs: Vec<ethcore::log_entry::LogEntry>,
+    profile: Option<profile::GasProfile>,
+    abi: Arc<abi::AbiRegistry>,
+    verbosity: trace::Verbosity,
+    trace_sink: trace::Sink,
+    touched: state::Touched,
+    mocks: BTreeMap<Address, mock::Mock>,
+    block_number: u64,
+    timestamp: u64,
+    cheatcodes: bool,
+    recorded_logs: Option<Vec<ethcore::log_entry::LogEntry>>,
+    console: Vec<String>,
+    /// Accounts with known keys, transactions from them are signed.
+    accounts: Vec<accounts::TestAccount>,
+    chain_id: u64,
+    /// State roots at the end of past blocks, see `state_at`.
+    history: BTreeMap<u64, H256>,
+    /// Block of the latest transaction.
+    last_block: Option<u64>,
+    block_gas_limit: U256,
+    /// Seconds between mined blocks.
+    block_time: u64,
+    /// Transactions queued for the next `mine`.
+    pending: Vec<SignedTransaction>,
+    /// Gas used by the transactions of the current block.
+    block_gas_used: U256,
+    transactions: Vec<chain::ExecutedTransaction>,
+    /// Accounts to fork from, see `with_fork`.
+    fork: Option<fork::ForkSource>,
+    /// Forked accounts already copied into the state.
+    materialized: BTreeSet<Address>,
 }
 
 impl Default for Evm {
@@ -46,7 +95,7 @@ impl Default for Evm {
     }
 }
 
-// temporary workaround for https://github.com/paritytech/parity/issues/8755
+/// Executed transaction, before its trace is recorded.
 struct TransactSuccess<T, V> {
     /// State root
     state_root: H256,
@@ -66,7 +115,7 @@ struct TransactSuccess<T, V> {
     outcome: ethcore::receipt::TransactionOutcome,
 }
 
-// temporary workaround for https://github.com/paritytech/parity/issues/8755
+/// Transaction rejected before execution.
 #[derive(Debug)]
 pub struct TransactError {
     /// State root
@@ -87,89 +136,346 @@ impl fmt::Display for TransactError {
     }
 }
 
-// temporary workaround for https://github.com/paritytech/parity/issues/8755
-fn split_transact_result<T, V>(
-    result: TransactResult<T, V>,
-) -> Result<TransactSuccess<T, V>, TransactError> {
-    match result {
-        TransactResult::Ok {
-            state_root,
-            gas_left,
-            output,
-            trace,
-            vm_trace,
-            contract_address,
-            logs,
-            outcome,
-        } => Ok(TransactSuccess {
-            state_root,
-            gas_left,
-            output,
-            trace,
-            vm_trace,
-            contract_address,
-            logs,
-            outcome,
-        }),
-        TransactResult::Err { state_root, error } => Err(TransactError { state_root, error }),
-    }
-}
-
 pub struct TransactionOutput {
+    hash: H256,
     state_root: H256,
     gas_left: U256,
     output: Vec<u8>,
     contract_address: Option<H160>,
     logs: Vec<ethcore::log_entry::LogEntry>,
     outcome: ethcore::receipt::TransactionOutcome,
+    trace: Vec<trace::CallTrace>,
+}
+
+impl TransactionOutput {
+    /// Whether the transaction was executed without reverting.
+    pub fn is_success(&self) -> bool {
+        self.trace.first().map_or(false, |trace| trace.is_success())
+    }
+
+    /// Hash of the transaction, see `Evm::transaction`.
+    pub fn hash(&self) -> H256 {
+        self.hash
+    }
+
+    /// State root after the transaction.
+    pub fn state_root(&self) -> H256 {
+        self.state_root
+    }
+
+    /// Data returned by the transaction.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Logs emitted by the transaction.
+    pub fn logs(&self) -> &[ethcore::log_entry::LogEntry] {
+        &self.logs
+    }
+
+    /// Call frames of the transaction.
+    pub fn trace(&self) -> &[trace::CallTrace] {
+        &self.trace
+    }
+
+    /// Contracts destroyed by the transaction.
+    pub fn self_destructs(&self) -> Vec<trace::SelfDestruct> {
+        trace::self_destructs(&self.trace)
+    }
+
+    /// Ether sent by contracts during the transaction.
+    pub fn internal_transfers(&self) -> Vec<trace::Transfer> {
+        trace::internal_transfers(&self.trace)
+    }
+
+    /// Addresses of all contracts created by the transaction.
+    pub fn created_contracts(&self) -> Vec<Address> {
+        trace::created_contracts(&self.trace)
+    }
 }
 
-impl<T, V> From<TransactSuccess<T, V>> for TransactionOutput {
-    fn from(t: TransactSuccess<T, V>) -> Self {
+impl<V> From<TransactSuccess<trace::CallTrace, V>> for TransactionOutput {
+    fn from(t: TransactSuccess<trace::CallTrace, V>) -> Self {
         TransactionOutput {
+            hash: H256::zero(),
             state_root: t.state_root,
             gas_left: t.gas_left,
             output: t.output,
             contract_address: t.contract_address,
             logs: t.logs,
             outcome: t.outcome,
+            trace: t.trace,
         }
     }
 }
 
+/// Transaction with explicit fields, see `Evm::send_transaction`.
+///
+/// Fields which are not set are filled in like `deploy` and `transact` do.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxRequest {
+    pub from: Address,
+    /// Receiver, `None` to create a contract.
+    pub to: Option<Address>,
+    pub nonce: Option<U256>,
+    pub gas: Option<U256>,
+    pub gas_price: Option<U256>,
+    pub value: Option<U256>,
+    pub data: Vec<u8>,
+    /// Chain id signed with EIP-155, the chain id of the `Evm` if not set.
+    pub chain_id: Option<u64>,
+    /// Whether the chain id is signed at all.
+    pub replay_protected: bool,
+}
+
+impl TxRequest {
+    /// Call of `to` with `data`.
+    pub fn call(from: Address, to: Address, data: Vec<u8>) -> Self {
+        TxRequest {
+            from,
+            to: Some(to),
+            nonce: None,
+            gas: None,
+            gas_price: None,
+            value: None,
+            data,
+            chain_id: None,
+            replay_protected: true,
+        }
+    }
+
+    /// Deployment of `code`.
+    pub fn create(from: Address, code: Vec<u8>) -> Self {
+        TxRequest {
+            to: None,
+            ..TxRequest::call(from, Address::zero(), code)
+        }
+    }
+
+    pub fn with_nonce(&mut self, nonce: U256) -> &mut Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    pub fn with_gas(&mut self, gas: U256) -> &mut Self {
+        self.gas = Some(gas);
+        self
+    }
+
+    pub fn with_gas_price(&mut self, gas_price: U256) -> &mut Self {
+        self.gas_price = Some(gas_price);
+        self
+    }
+
+    pub fn with_value(&mut self, value: U256) -> &mut Self {
+        self.value = Some(value);
+        self
+    }
+
+    pub fn with_chain_id(&mut self, chain_id: u64) -> &mut Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    /// Sets whether the chain id is signed, a pre-EIP-155 transaction is sent otherwise.
+    pub fn with_replay_protection(&mut self, replay_protected: bool) -> &mut Self {
+        self.replay_protected = replay_protected;
+        self
+    }
+}
+
+/// Block environment transactions are executed in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockEnv {
+    pub number: u64,
+    pub timestamp: u64,
+    pub author: Address,
+    pub difficulty: U256,
+    pub gas_limit: U256,
+}
+
+impl Default for BlockEnv {
+    fn default() -> Self {
+        BlockEnv {
+            number: 5_000_000,
+            timestamp: 1,
+            author: 0.into(),
+            difficulty: 1.into(),
+            gas_limit: 4_700_000.into(),
+        }
+    }
+}
+
+impl BlockEnv {
+    fn env_info(&self) -> vm::EnvInfo {
+        vm::EnvInfo {
+            number: self.number,
+            author: self.author,
+            timestamp: self.timestamp,
+            difficulty: self.difficulty,
+            last_hashes: Arc::new([0.into(); 256].to_vec()),
+            gas_used: 0.into(),
+            gas_limit: self.gas_limit,
+        }
+    }
+}
+
+/// State of an `Evm` to go back to, see `Evm::snapshot`.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    state_root: H256,
+    contract_address: Option<Address>,
+    logs: usize,
+    console: usize,
+    mocks: BTreeMap<Address, mock::Mock>,
+    block_number: u64,
+    timestamp: u64,
+    recorded_logs: Option<Vec<ethcore::log_entry::LogEntry>>,
+    last_block: Option<u64>,
+    pending: Vec<SignedTransaction>,
+    block_gas_used: U256,
+    transactions: usize,
+    materialized: BTreeSet<Address>,
+}
+
+/// Account used by `ensure_funds` to top up the sender.
+const FAUCET: &str = "7c532DB9E0c06C26fd40Acc56AC55C1eE92D3C3A";
+
 impl Evm {
     pub fn new_current() -> Self {
-        let evm = EvmTestClient::new(&*::FOUNDATION).expect("Valid spec given; qed");
+        let mut abi = abi::AbiRegistry::default();
+        if let Some(signatures) = signatures::compiled() {
+            abi.add_signatures(signatures);
+        }
+        if let Ok(path) = ::std::env::var("SOLARIS_SIGNATURES") {
+            let signatures = signatures::SignatureDb::load(&path)
+                .unwrap_or_else(|e| panic!("Unable to load signatures from {}: {}", path, e));
+            abi.add_signatures(signatures);
+        }
+        let mut touched = state::Touched::default();
+        touched.account(FAUCET.parse().unwrap());
         Evm {
-            evm,
+            backend: backend::Backend::new(),
             sender: 0.into(),
             contract_address: None,
             gas: 4_000_000.into(),
             gas_price: 0.into(),
             value: 0.into(),
             logs: vec![],
+            profile: None,
+            abi: Arc::new(abi),
+            verbosity: trace::Verbosity::from_env(),
+            trace_sink: trace::Sink::from_env(),
+            touched,
+            mocks: BTreeMap::new(),
+            block_number: BlockEnv::default().number,
+            timestamp: BlockEnv::default().timestamp,
+            cheatcodes: false,
+            recorded_logs: None,
+            console: vec![],
+            accounts: vec![],
+            chain_id: ::FOUNDATION.chain_id(),
+            history: BTreeMap::new(),
+            last_block: None,
+            block_gas_limit: BlockEnv::default().gas_limit,
+            block_time: 15,
+            pending: vec![],
+            block_gas_used: 0.into(),
+            transactions: vec![],
+            fork: None,
+            materialized: BTreeSet::new(),
         }
     }
 
     fn tracers(&self) -> (trace::PrintingTracer, trace::PrintingTracer) {
-        Default::default()
+        trace::PrintingTracer::pair(self.abi.clone(), self.verbosity, self.profile.is_some())
+    }
+
+    /// Records state touched, mocks called and messages logged by a transaction or call.
+    fn record_execution(&mut self, touched: state::Touched, traces: &[trace::CallTrace]) {
+        self.touched.extend(touched);
+        for (address, mock) in &mut self.mocks {
+            mock.record(address, traces);
+        }
+        for message in console::messages(traces, &self.abi) {
+            if self.verbosity != trace::Verbosity::Silent {
+                self.trace_sink.write(&format!("console.log: {}", message));
+            }
+            self.console.push(message);
+        }
+    }
+
+    fn record_profile(&mut self, profile: Option<profile::GasProfile>) {
+        if let (Some(collected), Some(profile)) = (self.profile.as_mut(), profile) {
+            collected.extend(profile);
+        }
+    }
+
+    /// Cheatcode state a transaction or call in `env_info` starts with,
+    /// `None` unless cheatcodes are enabled.
+    fn cheats(&self, env_info: &vm::EnvInfo) -> Option<cheatcodes::Cheats> {
+        if self.cheatcodes {
+            Some(cheatcodes::Cheats::new(env_info.clone(), self.recorded_logs.clone()))
+        } else {
+            None
+        }
+    }
+
+    /// Unmet cheatcode expectations, formatted.
+    fn violations(&self, cheats: Option<&cheatcodes::Cheats>) -> Vec<String> {
+        cheats
+            .into_iter()
+            .flat_map(|cheats| cheats.violations.iter().map(|violation| violation.format(&self.abi)))
+            .collect()
+    }
+
+    /// Signs `transaction` for the chain with the key of `sender` if it's a test account,
+    /// fakes the signature otherwise.
+    fn sign(&self, transaction: Transaction, sender: Address) -> SignedTransaction {
+        match self.secret(&sender) {
+            Some(secret) => transaction.sign(secret, Some(self.chain_id)),
+            None => transaction.fake_sign(sender),
+        }
+    }
+
+    fn secret(&self, address: &Address) -> Option<&Secret> {
+        self.accounts
+            .iter()
+            .find(|account| account.address() == *address)
+            .map(accounts::TestAccount::secret)
     }
 
     fn env_info(&self) -> vm::EnvInfo {
-        vm::EnvInfo {
-            number: 5_000_000u64,
-            author: 0.into(),
-            timestamp: 1u64,
-            difficulty: 1.into(),
-            last_hashes: Arc::new([0.into(); 256].to_vec()),
-            gas_used: 0.into(),
-            gas_limit: 4_700_000.into(),
+        self.block_env().env_info()
+    }
+
+    /// Block environment of the following transactions.
+    pub fn block_env(&self) -> BlockEnv {
+        BlockEnv {
+            number: self.block_number,
+            timestamp: self.timestamp,
+            gas_limit: self.block_gas_limit,
+            ..BlockEnv::default()
         }
     }
 
     pub fn deploy(&mut self, code: &[u8]) -> error::Result<Address> {
+        let transaction_output = self.deploy_transaction(code)?;
+
+        let contract_address = transaction_output
+            .contract_address
+            .expect("transaction output must have contract_address after deploy");
+
+        self.contract_address = Some(contract_address);
+
+        Ok(contract_address)
+    }
+
+    fn deploy_transaction(&mut self, code: &[u8]) -> error::Result<TransactionOutput> {
         let env_info = self.env_info();
-        let nonce = self.evm.state().nonce(&self.sender).expect(STATE);
+        let sender = self.sender;
+        self.materialize(&[sender]);
+        let nonce = self.backend.state().nonce(&sender).expect(STATE);
         let transaction = Transaction {
             nonce,
             gas_price: self.gas_price,
@@ -177,17 +483,510 @@ impl Evm {
             action: Action::Create,
             value: self.value,
             data: code.to_vec(),
-        }.fake_sign((&*self.sender).into());
+        };
+        let transaction = self.sign(transaction, sender);
 
-        let transaction_output = self.raw_transact(&env_info, transaction)?;
+        self.raw_transact(&env_info, transaction)
+    }
 
-        let contract_address = transaction_output
-            .contract_address
-            .expect("transaction output must have contract_address after deploy");
+    /// Estimates the gas limit `f` needs to succeed when sent to the current contract.
+    pub fn estimate_gas<F: ContractFunction>(&mut self, f: F) -> error::Result<U256> {
+        let contract_address = self.contract_address
+            .expect("Contract address is not set. Did you forget to deploy the contract?");
+        self.estimate_gas_to(contract_address, f.encoded())
+    }
 
-        self.contract_address = Some(contract_address);
+    /// Estimates the gas limit a transaction with raw `data` to `contract_address` needs to succeed.
+    pub fn estimate_gas_to(&mut self, contract_address: Address, data: Vec<u8>) -> error::Result<U256> {
+        let (gas, sender, value, gas_price) = (self.gas, self.sender, self.value, self.gas_price);
+        let result = self.estimate(sender, value, gas_price, |evm, limit| {
+            evm.gas = limit;
+            evm.transact_to(contract_address, data.clone())
+        });
+        self.gas = gas;
+        result
+    }
 
-        Ok(contract_address)
+    /// Estimates the gas limit the deployment of `code` needs to succeed.
+    pub fn estimate_deploy_gas(&mut self, code: &[u8]) -> error::Result<U256> {
+        let (gas, sender, value, gas_price) = (self.gas, self.sender, self.value, self.gas_price);
+        let result = self.estimate(sender, value, gas_price, |evm, limit| {
+            evm.gas = limit;
+            evm.deploy_transaction(code)
+        });
+        self.gas = gas;
+        result
+    }
+
+    /// Estimates the gas limit `request` needs to succeed, ignoring its own gas limit.
+    pub fn estimate_transaction_gas(&mut self, request: &TxRequest) -> error::Result<U256> {
+        let mut request = request.clone();
+        let value = request.value.unwrap_or(self.value);
+        let gas_price = request.gas_price.unwrap_or(self.gas_price);
+        self.estimate(request.from, value, gas_price, |evm, limit| {
+            evm.send_transaction(request.with_gas(limit))
+        })
+    }
+
+    /// Binary search for the lowest gas limit up to the block gas limit `execute`
+    /// succeeds with, like nodes do for `eth_estimateGas`. With a non-zero gas
+    /// price the search is also capped by the gas `sender` can pay for after
+    /// sending `value`.
+    ///
+    /// Every attempt is executed and reverted, so gas withheld from sub-calls by
+    /// the 63/64 rule and refunds are accounted for. Fails with the error or the
+    /// revert of the attempt with the highest gas limit.
+    fn estimate<F>(&mut self, sender: Address, value: U256, gas_price: U256, mut execute: F) -> error::Result<U256>
+    where
+        F: FnMut(&mut Self, U256) -> error::Result<TransactionOutput>,
+    {
+        let snapshot = self.snapshot();
+        let contract_address = self.contract_address;
+        let verbosity = ::std::mem::replace(&mut self.verbosity, trace::Verbosity::Silent);
+        let profile = self.profile.take();
+
+        let mut attempt = |evm: &mut Self, limit: U256| {
+            let result = execute(evm, limit);
+            evm.revert_to(&snapshot);
+            result
+        };
+        let mut high = self.env_info().gas_limit;
+        if !gas_price.is_zero() {
+            let balance = self.balance(&sender);
+            let available = if balance > value { balance - value } else { U256::zero() };
+            high = ::std::cmp::min(high, available / gas_price);
+        }
+        let result = match attempt(self, high) {
+            Ok(ref output) if output.is_success() => {
+                // no transaction succeeds with less than the base transaction cost
+                let mut low = U256::from(TRANSACTION_GAS - 1);
+                while low + U256::one() < high {
+                    let middle = (low + high) / 2;
+                    match attempt(self, middle) {
+                        Ok(ref output) if output.is_success() => high = middle,
+                        _ => low = middle,
+                    }
+                }
+                Ok(high)
+            }
+            Ok(output) => Err(error::ErrorKind::Reverted(output.output).into()),
+            Err(e) => Err(e),
+        };
+
+        self.contract_address = contract_address;
+        self.verbosity = verbosity;
+        self.profile = profile;
+        result
+    }
+
+    /// Deploys a compiled contract and registers its ABI under `name`,
+    /// so that traces and logs of the contract are decoded.
+    pub fn deploy_artifact(&mut self, name: &str, abi: &[u8], code: &[u8]) -> error::Result<Address> {
+        let abi = ethabi::Contract::load(abi)?;
+        let address = self.deploy(code)?;
+        self.register_abi(address, abi);
+        self.set_label(address, name);
+        Ok(address)
+    }
+
+    /// Registers the ABI of the contract at `address`, used to decode its calls and logs.
+    pub fn register_abi(&mut self, address: Address, abi: ethabi::Contract) -> &mut Self {
+        Arc::make_mut(&mut self.abi).register(address, abi);
+        self
+    }
+
+    /// Sets a human-readable label of `address`, used in traces and decoded logs.
+    pub fn set_label<S: Into<String>>(&mut self, address: Address, label: S) -> &mut Self {
+        Arc::make_mut(&mut self.abi).set_label(address, label);
+        self
+    }
+
+    /// Adds signatures used to resolve selectors and topics of contracts without registered ABI.
+    pub fn add_signatures(&mut self, signatures: signatures::SignatureDb) -> &mut Self {
+        Arc::make_mut(&mut self.abi).add_signatures(signatures);
+        self
+    }
+
+    /// Loads a signature database file, see `signatures::SignatureDb`.
+    pub fn load_signatures<P: AsRef<::std::path::Path>>(&mut self, path: P) -> error::Result<&mut Self> {
+        let signatures = signatures::SignatureDb::load(path)?;
+        Ok(self.add_signatures(signatures))
+    }
+
+    /// Renders the call tree of a transaction, with decoded calls, events and return values.
+    pub fn render_trace(&self, output: &TransactionOutput) -> String {
+        render::render(&output.trace, &output.logs, &self.abi, self.trace_sink.color())
+    }
+
+    /// Returns registered ABIs and address labels.
+    pub fn abi(&self) -> &abi::AbiRegistry {
+        &self.abi
+    }
+
+    /// Returns the state of the account at `address`, or `None` if it doesn't exist.
+    ///
+    /// The storage includes only slots written by earlier transactions or set explicitly.
+    pub fn account(&self, address: &Address) -> Option<state::Account> {
+        self.account_in(self.backend.state(), address)
+    }
+
+    fn account_in(&self, state: &State<StateDB>, address: &Address) -> Option<state::Account> {
+        if !state.exists(address).expect(STATE) {
+            return None;
+        }
+
+        let mut account = state::Account {
+            balance: state.balance(address).expect(STATE),
+            nonce: state.nonce(address).expect(STATE),
+            code: state
+                .code(address)
+                .expect(STATE)
+                .map_or_else(Vec::new, |code| code.to_vec()),
+            storage: BTreeMap::new(),
+        };
+        for key in self.touched.storage_keys(address) {
+            let value = state.storage_at(address, key).expect(STATE);
+            if !value.is_zero() {
+                account.storage.insert(*key, value);
+            }
+        }
+        Some(account)
+    }
+
+    /// Returns the balance of `address`.
+    pub fn balance(&self, address: &Address) -> U256 {
+        self.backend.state().balance(address).expect(STATE)
+    }
+
+    /// Starts tracking the balances of `addresses`, see `balances::BalanceTracker`.
+    pub fn track_balances(&self, addresses: &[Address]) -> balances::BalanceTracker {
+        balances::BalanceTracker::new(self, addresses)
+    }
+
+    /// Returns the state of all accounts touched so far.
+    pub fn dump_accounts(&self) -> BTreeMap<Address, state::Account> {
+        self.touched
+            .iter()
+            .filter_map(|(address, _)| self.account(address).map(|account| (*address, account)))
+            .collect()
+    }
+
+    /// Captures the state, block environment and labels, see `state::StateDump`.
+    pub fn dump(&self) -> state::StateDump {
+        state::StateDump {
+            block: self.block_env(),
+            chain_id: self.chain_id,
+            accounts: self.dump_accounts(),
+            labels: self.abi
+                .labels()
+                .iter()
+                .map(|(address, label)| (*address, label.clone()))
+                .collect(),
+        }
+    }
+
+    /// Replaces the state with `dump` and sets its block environment and labels.
+    ///
+    /// The author and difficulty of the block are not restored.
+    pub fn restore(&mut self, dump: &state::StateDump) -> &mut Self {
+        let removed: Vec<Address> = self.touched
+            .iter()
+            .map(|(address, _)| *address)
+            .filter(|address| !dump.accounts.contains_key(address))
+            .collect();
+        self.backend.remove_accounts(&removed);
+        self.insert_accounts(&dump.accounts);
+        self.block_number = dump.block.number;
+        self.timestamp = dump.block.timestamp;
+        self.block_gas_limit = dump.block.gas_limit;
+        self.chain_id = dump.chain_id;
+        for (address, label) in &dump.labels {
+            self.set_label(*address, label.clone());
+        }
+        self
+    }
+
+    /// Saves the dump of the `Evm` as JSON, see `dump`.
+    pub fn save_state<P: AsRef<::std::path::Path>>(&self, path: P) -> error::Result<()> {
+        Ok(self.dump().save(path)?)
+    }
+
+    /// Restores a dump saved with `save_state`.
+    pub fn load_state<P: AsRef<::std::path::Path>>(&mut self, path: P) -> error::Result<&mut Self> {
+        let dump = state::StateDump::load(path)?;
+        Ok(self.restore(&dump))
+    }
+
+    /// Captures the current state, block environment, contract address and collected logs.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            state_root: self.backend.root(),
+            contract_address: self.contract_address,
+            logs: self.logs.len(),
+            console: self.console.len(),
+            mocks: self.mocks.clone(),
+            block_number: self.block_number,
+            timestamp: self.timestamp,
+            recorded_logs: self.recorded_logs.clone(),
+            last_block: self.last_block,
+            pending: self.pending.clone(),
+            block_gas_used: self.block_gas_used,
+            transactions: self.transactions.len(),
+            materialized: self.materialized.clone(),
+        }
+    }
+
+    /// Goes back to a snapshot, discarding everything that happened since.
+    ///
+    /// The same snapshot can be reverted to any number of times.
+    pub fn revert_to(&mut self, snapshot: &Snapshot) -> &mut Self {
+        self.backend.reset(snapshot.state_root);
+        self.contract_address = snapshot.contract_address;
+        self.logs.truncate(snapshot.logs);
+        self.console.truncate(snapshot.console);
+        self.mocks = snapshot.mocks.clone();
+        self.block_number = snapshot.block_number;
+        self.timestamp = snapshot.timestamp;
+        self.recorded_logs = snapshot.recorded_logs.clone();
+        // blocks which ended since the snapshot was taken
+        match snapshot.last_block {
+            Some(last) => {
+                self.history.split_off(&last);
+            }
+            None => self.history.clear(),
+        }
+        self.last_block = snapshot.last_block;
+        self.pending = snapshot.pending.clone();
+        self.block_gas_used = snapshot.block_gas_used;
+        self.transactions.truncate(snapshot.transactions);
+        self.materialized = snapshot.materialized.clone();
+        self
+    }
+
+    /// Keeps the state at the end of the block of the previous transaction
+    /// once a transaction is executed in another block.
+    fn record_block(&mut self) {
+        if self.last_block == Some(self.block_number) {
+            return;
+        }
+        if let Some(last) = self.last_block {
+            self.history.insert(last, self.backend.root());
+        }
+        self.last_block = Some(self.block_number);
+        self.block_gas_used = 0.into();
+    }
+
+    /// State root at the end of block `number`, `None` if that's the current state.
+    fn block_root(&self, number: u64) -> error::Result<Option<H256>> {
+        if number > self.block_number {
+            bail!(error::ErrorKind::UnknownBlock(number));
+        }
+        if self.last_block.map_or(true, |last| last <= number) {
+            return Ok(None);
+        }
+        match self.history.range(..number + 1).next_back() {
+            Some((_, root)) => Ok(Some(*root)),
+            None => bail!(error::ErrorKind::UnknownBlock(number)),
+        }
+    }
+
+    /// Returns the state of all touched accounts at the end of block `number`.
+    ///
+    /// Blocks up to the current `block.number` are known, once a transaction
+    /// was executed in them or in a block before. The state of a block is kept
+    /// when the first transaction of a later block is executed, including
+    /// changes made with `set_balance` and alike after its last transaction.
+    pub fn state_at(&self, number: u64) -> error::Result<BTreeMap<Address, state::Account>> {
+        let root = match self.block_root(number)? {
+            Some(root) => root,
+            None => return Ok(self.dump_accounts()),
+        };
+        let state = self.backend.at(root);
+        Ok(self.touched
+            .iter()
+            .filter_map(|(address, _)| self.account_in(&state, address).map(|account| (*address, account)))
+            .collect())
+    }
+
+    /// Returns the state root at the end of block `number`, see `state_at`.
+    pub fn state_root_at(&self, number: u64) -> error::Result<H256> {
+        Ok(self.block_root(number)?.unwrap_or_else(|| self.backend.root()))
+    }
+
+    /// Returns the account at `address` at the end of block `number`, see `state_at`.
+    pub fn account_at(&self, number: u64, address: &Address) -> error::Result<Option<state::Account>> {
+        Ok(match self.block_root(number)? {
+            Some(root) => self.account_in(&self.backend.at(root), address),
+            None => self.account(address),
+        })
+    }
+
+    /// Returns the balance of `address` at the end of block `number`, see `state_at`.
+    pub fn balance_at(&self, number: u64, address: &Address) -> error::Result<U256> {
+        Ok(match self.block_root(number)? {
+            Some(root) => self.backend.at(root).balance(address).expect(STATE),
+            None => self.balance(address),
+        })
+    }
+
+    /// Returns a storage slot of `address` at the end of block `number`, see `state_at`.
+    ///
+    /// Unlike `account_at`, any slot can be read, not only touched ones.
+    pub fn storage_at(&self, number: u64, address: &Address, key: &H256) -> error::Result<H256> {
+        let root = self.state_root_at(number)?;
+        Ok(self.backend.at(root).storage_at(address, key).expect(STATE))
+    }
+
+    /// Replaces the code of the account at `address`, creating the account if needed.
+    pub fn set_code(&mut self, address: Address, code: Vec<u8>) -> &mut Self {
+        self.materialize(&[address]);
+        self.touched.account(address);
+        self.backend.set_code(&address, code);
+        self
+    }
+
+    /// Sets the balance of the account at `address`, creating the account if needed.
+    pub fn set_balance(&mut self, address: Address, balance: U256) -> &mut Self {
+        self.materialize(&[address]);
+        self.touched.account(address);
+        self.backend.set_balance(&address, balance);
+        self
+    }
+
+    /// Sets a storage slot of the account at `address`, creating the account if needed.
+    pub fn set_storage(&mut self, address: Address, key: H256, value: H256) -> &mut Self {
+        self.materialize(&[address]);
+        self.touched.storage(address, key);
+        self.backend.set_storage(&address, key, value);
+        self
+    }
+
+    /// Deploys the cheatcode contract, see `cheatcodes` for the supported calls.
+    pub fn enable_cheatcodes(&mut self) -> &mut Self {
+        if !self.cheatcodes {
+            self.cheatcodes = true;
+            let address = cheatcodes::address();
+            // Solidity checks that called contracts have code, a single `STOP` will do.
+            self.set_code(address, vec![0x00]);
+            self.set_label(address, "hevm");
+            self.add_signatures(cheatcodes::signatures());
+        }
+        self
+    }
+
+    /// Forks the accounts of `source`, see `fork`.
+    ///
+    /// Forked accounts are copied into the state when first touched.
+    /// Accounts touched before keep their current state.
+    pub fn with_fork(&mut self, source: fork::ForkSource) -> &mut Self {
+        self.materialized = self.touched.iter().map(|(address, _)| *address).collect();
+        self.fork = Some(source);
+        self
+    }
+
+    /// Copies the forked state of `addresses` which aren't copied yet.
+    pub fn materialize(&mut self, addresses: &[Address]) -> &mut Self {
+        let forked: BTreeMap<Address, state::Account> = addresses
+            .iter()
+            .filter_map(|address| self.forked_account(address).map(|account| (*address, account.clone())))
+            .collect();
+        self.materialized.extend(forked.keys().cloned());
+        if !forked.is_empty() {
+            self.insert_accounts(&forked);
+        }
+        self
+    }
+
+    /// Replaces `accounts` in the state, recording them as touched.
+    fn insert_accounts(&mut self, accounts: &BTreeMap<Address, state::Account>) {
+        for (address, account) in accounts {
+            self.touched.account(*address);
+            for key in account.storage.keys() {
+                self.touched.storage(*address, *key);
+            }
+        }
+        self.backend.insert_accounts(accounts);
+    }
+
+    /// Forked state of `address` if it isn't copied yet.
+    fn forked_account(&self, address: &Address) -> Option<&state::Account> {
+        match self.fork {
+            Some(ref fork) if !self.materialized.contains(address) => fork.account(address),
+            _ => None,
+        }
+    }
+
+    /// Forked accounts in `touched` which aren't copied yet.
+    fn unmaterialized(&self, touched: &state::Touched) -> Vec<Address> {
+        touched
+            .iter()
+            .map(|(address, _)| *address)
+            .filter(|address| self.forked_account(address).is_some())
+            .collect()
+    }
+
+    /// Creates `count` test accounts derived from `seed`, see `accounts`.
+    ///
+    /// The accounts are labelled with their names and funded with a million ether,
+    /// transactions sent from them are signed with their keys. Calling it again
+    /// adds `count` more accounts, derived and named from the following indexes.
+    pub fn with_accounts(&mut self, seed: &str, count: usize) -> &mut Self {
+        let first = self.accounts.len();
+        let created: Vec<accounts::TestAccount> = (first..first + count)
+            .map(|index| accounts::TestAccount::new(accounts::name(index), accounts::derive(seed, index)))
+            .collect();
+        for account in &created {
+            self.set_balance(account.address(), wei::from_mether(1));
+            self.set_label(account.address(), account.name());
+        }
+        self.accounts.extend(created);
+        self
+    }
+
+    /// Test accounts created so far.
+    pub fn accounts(&self) -> &[accounts::TestAccount] {
+        &self.accounts
+    }
+
+    /// Returns the test account called `name`.
+    pub fn test_account(&self, name: &str) -> Option<&accounts::TestAccount> {
+        self.accounts.iter().find(|account| account.name() == name)
+    }
+
+    /// Messages logged with `console.log` so far, in order.
+    pub fn console_logs(&self) -> &[String] {
+        &self.console
+    }
+
+    /// Logs collected since the `recordLogs` cheatcode was called.
+    pub fn recorded_logs(&self) -> Vec<ethabi::RawLog> {
+        self.recorded_logs
+            .iter()
+            .flat_map(|logs| logs.iter().map(ethcore_log_to_ethabi_log))
+            .collect()
+    }
+
+    /// Replaces the code at `address` with a mock returning programmed responses.
+    pub fn mock(&mut self, address: Address) -> mock::MockBuilder {
+        if !self.mocks.contains_key(&address) {
+            self.mocks.insert(address, mock::Mock::default());
+            self.set_code(address, mock::Mock::default().code());
+        }
+        mock::MockBuilder::new(self, address)
+    }
+
+    pub(crate) fn add_mock_rule(&mut self, address: Address, matcher: mock::Matcher, response: mock::Response) {
+        let code = {
+            let mock = self.mocks.entry(address).or_insert_with(Default::default);
+            mock.add_rule(matcher, response);
+            mock.code()
+        };
+        self.set_code(address, code);
+    }
+
+    /// Calls received by the mock at `address`, in order.
+    pub fn mock_calls(&self, address: &Address) -> &[mock::MockCall] {
+        self.mocks.get(address).map(|mock| mock.calls()).unwrap_or(&[])
     }
 
     pub fn with_gas(&mut self, gas: U256) -> &mut Self {
@@ -205,17 +1004,84 @@ impl Evm {
         self
     }
 
+    /// Sets the gas limit of the following blocks.
+    pub fn with_block_gas_limit(&mut self, gas_limit: U256) -> &mut Self {
+        self.block_gas_limit = gas_limit;
+        self
+    }
+
+    /// Sets by how many seconds `mine` advances `block.timestamp`.
+    pub fn with_block_time(&mut self, seconds: u64) -> &mut Self {
+        self.block_time = seconds;
+        self
+    }
+
+    /// Sets the chain id transactions sent with `send_transaction` have to be signed for.
+    pub fn with_chain_id(&mut self, chain_id: u64) -> &mut Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    /// Sender of the following transactions and calls.
+    pub fn sender(&self) -> Address {
+        self.sender
+    }
+
     pub fn with_sender(&mut self, address: Address) -> &mut Self {
         self.sender = address;
         self
     }
 
+    /// Sets `block.number` of the following transactions.
+    pub fn with_block_number(&mut self, number: u64) -> &mut Self {
+        self.block_number = number;
+        self
+    }
+
+    /// Sets `block.timestamp` of the following transactions.
+    pub fn with_timestamp(&mut self, timestamp: u64) -> &mut Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Sets how much of the execution trace is written out,
+    /// overriding `SOLARIS_TRACE`.
+    pub fn with_verbosity(&mut self, verbosity: trace::Verbosity) -> &mut Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Sets where the execution trace is written to, overriding `SOLARIS_TRACE_LOG`.
+    pub fn with_trace_sink(&mut self, sink: trace::Sink) -> &mut Self {
+        self.trace_sink = sink;
+        self
+    }
+
+    /// Starts collecting a gas profile of all following transactions and calls.
+    pub fn enable_profiler(&mut self) -> &mut Self {
+        if self.profile.is_none() {
+            self.profile = Some(Default::default());
+        }
+        self
+    }
+
+    /// Returns the gas profile collected since `enable_profiler` was called.
+    pub fn profile(&self) -> Option<&profile::GasProfile> {
+        self.profile.as_ref()
+    }
+
     /// Ensures that sender has enough funds (value) to call next transaction.
     pub fn ensure_funds(&mut self) -> &mut Self {
         // TODO [ToDr] Just transfer to amount that is actually needed
         let env_info = self.env_info();
-        let sender = "7c532DB9E0c06C26fd40Acc56AC55C1eE92D3C3A".parse().unwrap();
-        let nonce = self.evm.state().nonce(&sender).expect(STATE);
+        let sender = FAUCET.parse().unwrap();
+        let recipient = self.sender;
+        self.materialize(&[sender, recipient]);
+        let nonce = self.backend.state().nonce(&sender).expect(STATE);
         let transaction = Transaction {
             nonce,
             gas_price: 0.into(),
@@ -241,6 +1107,15 @@ impl Evm {
             .collect()
     }
 
+    /// returns all logs emitted by contracts with registered ABI, decoded
+    /// to event names and named arguments.
+    pub fn decoded_logs(&self) -> Vec<abi::DecodedLog> {
+        self.logs
+            .iter()
+            .filter_map(|log| self.abi.decode_log(log))
+            .collect()
+    }
+
     /// returns a vector of all raw logs collected until now
     pub fn raw_logs(&self) -> Vec<ethabi::RawLog> {
         self.logs.iter().map(ethcore_log_to_ethabi_log).collect()
@@ -257,25 +1132,139 @@ impl Evm {
     pub fn call<F: ContractFunction>(&mut self, f: F) -> error::Result<F::Output> {
         let contract_address = self.contract_address
             .expect("Contract address is not set. Did you forget to deploy the contract?");
+        let output = self.call_to(contract_address, f.encoded())?;
+        let output = f.output(output).expect(
+            "output must be decodable with `ContractFunction` that has encoded input. q.e.d.",
+        );
+        Ok(output)
+    }
+
+    /// Calls `contract_address` with raw `data` without committing any changes,
+    /// returns the output or `ErrorKind::Reverted` with the revert data.
+    ///
+    /// The call is executed in the current block environment, but unlike
+    /// `simulate_to` it's a plain message call, no transaction is involved.
+    pub fn call_to(&mut self, contract_address: Address, data: Vec<u8>) -> error::Result<Vec<u8>> {
+        self.execute_call(contract_address, data, vm::CallType::Call)
+    }
+
+    /// Calls `f` on the current contract like `call`, but with `STATICCALL` semantics.
+    pub fn static_call<F: ContractFunction>(&mut self, f: F) -> error::Result<F::Output> {
+        let contract_address = self.contract_address
+            .expect("Contract address is not set. Did you forget to deploy the contract?");
+        let output = self.static_call_to(contract_address, f.encoded())?;
+        let output = f.output(output).expect(
+            "output must be decodable with `ContractFunction` that has encoded input. q.e.d.",
+        );
+        Ok(output)
+    }
+
+    /// Calls `contract_address` like `call_to`, but with `STATICCALL` semantics,
+    /// so any attempt to modify state, including by sub-calls, fails the call.
+    /// Any value set with `with_value` is not sent.
+    pub fn static_call_to(&mut self, contract_address: Address, data: Vec<u8>) -> error::Result<Vec<u8>> {
+        self.execute_call(contract_address, data, vm::CallType::StaticCall)
+    }
+
+    fn execute_call(
+        &mut self,
+        contract_address: Address,
+        data: Vec<u8>,
+        call_type: vm::CallType,
+    ) -> error::Result<Vec<u8>> {
+        let env_info = self.env_info();
+        let output = self.call_once(&env_info, contract_address, data, call_type)?;
+        if !output.is_success() {
+            bail!(error::ErrorKind::Reverted(output.output));
+        }
+        Ok(output.output)
+    }
+
+    /// Calls `contract_address` with raw `data` in `env` with `STATICCALL` semantics,
+    /// like `static_call_to`, and returns the output with the trace of the call.
+    ///
+    /// The output isn't a success if the call reverts or attempts to modify state.
+    /// Nothing is committed either way.
+    pub fn static_call_at(
+        &mut self,
+        env: &BlockEnv,
+        contract_address: Address,
+        data: Vec<u8>,
+    ) -> error::Result<TransactionOutput> {
+        let env_info = env.env_info();
+        self.call_once(&env_info, contract_address, data, vm::CallType::StaticCall)
+    }
+
+    /// Executes a message call and discards its changes, failed calls are
+    /// reported in the trace of the output.
+    fn call_once(
+        &mut self,
+        env_info: &vm::EnvInfo,
+        contract_address: Address,
+        data: Vec<u8>,
+        call_type: vm::CallType,
+    ) -> error::Result<TransactionOutput> {
+        let sender = self.sender;
+        self.materialize(&[sender, contract_address]);
         let mut params = vm::ActionParams::default();
-        params.sender = self.sender;
-        params.origin = self.sender;
+        params.sender = sender;
+        params.origin = sender;
         params.address = contract_address;
         params.code_address = contract_address;
-        params.code = self.evm.state().code(&contract_address).expect(STATE);
-        params.data = Some(f.encoded());
-        params.call_type = vm::CallType::Call;
-        params.value = vm::ActionValue::Transfer(self.value);
+        params.code = self.backend.state().code(&contract_address).expect(STATE);
+        params.data = Some(data);
+        params.value = match call_type {
+            vm::CallType::StaticCall => vm::ActionValue::Transfer(0.into()),
+            _ => vm::ActionValue::Transfer(self.value),
+        };
+        params.call_type = call_type;
         params.gas = self.gas;
         params.gas_price = self.gas_price;
 
-        let mut tracers = self.tracers();
-        let result = self.evm.call(params, &mut tracers.0, &mut tracers.1)?;
+        let (report, tracers, result, cheats) = loop {
+            let mut tracers = self.tracers();
+            let report = tracers.0.report();
+            let mut cheats = self.cheats(env_info);
+            let result = match cheats {
+                Some(ref mut cheats) => {
+                    self.backend
+                        .call_with_cheatcodes(cheats, params.clone(), &mut tracers.0, &mut tracers.1)
+                }
+                None => self.backend.call(env_info, params.clone(), &mut tracers.0, &mut tracers.1),
+            };
+            // the call is run again once the forked accounts it touched are copied
+            let unmaterialized = self.unmaterialized(&report.touched());
+            if unmaterialized.is_empty() {
+                break (report, tracers, result, cheats);
+            }
+            self.materialize(&unmaterialized);
+        };
+        let traces = Tracer::drain(tracers.0);
+        self.record_execution(report.touched(), &traces);
+        let (gas_left, output, logs) = match result {
+            Ok((result, logs)) => (result.gas_left, result.return_data.to_vec(), logs),
+            Err(_) => (U256::zero(), vec![], vec![]),
+        };
+        let violations = self.violations(cheats.as_ref());
+        report.logs(&self.abi, &logs);
+        report.finish(self.verbosity, self.trace_sink, !violations.is_empty(), || {
+            render::render(&traces, &logs, &self.abi, self.trace_sink.color())
+        });
+        self.record_profile(VMTracer::drain(tracers.1));
+        if !violations.is_empty() {
+            bail!(error::ErrorKind::Cheatcode(violations.join("; ")));
+        }
 
-        let output = f.output(result.return_data.to_vec()).expect(
-            "output must be decodable with `ContractFunction` that has encoded input. q.e.d.",
-        );
-        Ok(output)
+        Ok(TransactionOutput {
+            hash: H256::zero(),
+            state_root: self.backend.root(),
+            gas_left,
+            output,
+            contract_address: None,
+            logs,
+            outcome: ethcore::receipt::TransactionOutcome::Unknown,
+            trace: traces,
+        })
     }
 
     fn raw_transact(
@@ -283,36 +1272,441 @@ impl Evm {
         env_info: &vm::EnvInfo,
         transaction: SignedTransaction,
     ) -> error::Result<TransactionOutput> {
-        let mut tracers = self.tracers();
-        let transact_success =
-            split_transact_result(
-                self.evm
-                    .transact(env_info, transaction, tracers.0, tracers.1),
-            )?;
+        self.record_block();
+        let mut executed = chain::ExecutedTransaction {
+            hash: transaction.hash(),
+            from: transaction.sender(),
+            nonce: transaction.nonce,
+            to: match transaction.action {
+                Action::Call(address) => Some(address),
+                Action::Create => None,
+            },
+            value: transaction.value,
+            gas: transaction.gas,
+            gas_price: transaction.gas_price,
+            data: transaction.data.clone(),
+            block_number: env_info.number,
+            receipt: Default::default(),
+            trace: vec![],
+        };
+
+        let (report, result, cheats, state_root) = loop {
+            let state_root = self.backend.root();
+            let tracers = self.tracers();
+            let report = tracers.0.report();
+            let mut cheats = self.cheats(env_info);
+            let result = match cheats {
+                Some(ref mut cheats) => {
+                    self.backend
+                        .transact_with_cheatcodes(cheats, self.chain_id, &transaction, tracers.0, tracers.1)
+                }
+                None => self.backend.transact(env_info, self.chain_id, &transaction, tracers.0, tracers.1),
+            };
+            // the transaction is run again once the forked accounts it touched are copied,
+            // the sender and recipient are copied before it's signed
+            let unmaterialized = self.unmaterialized(&report.touched());
+            if unmaterialized.is_empty() {
+                break (report, result, cheats, state_root);
+            }
+            self.backend.reset(state_root);
+            self.materialize(&unmaterialized);
+        };
+        let result = result
+            .map(|outcome| TransactSuccess {
+                state_root: self.backend.root(),
+                gas_left: transaction.gas - outcome.receipt.gas_used,
+                output: outcome.output,
+                trace: outcome.trace,
+                vm_trace: outcome.vm_trace,
+                contract_address: match transaction.action {
+                    Action::Create => Some(address::create_address(&transaction.sender(), &transaction.nonce)),
+                    Action::Call(_) => None,
+                },
+                logs: outcome.receipt.logs,
+                outcome: outcome.receipt.outcome,
+            })
+            .map_err(|error| TransactError {
+                state_root: self.backend.root(),
+                error,
+            });
+        let violations = match result {
+            Ok(_) => self.violations(cheats.as_ref()),
+            Err(_) => vec![],
+        };
+        let mut touched = report.touched();
+        // the author is paid the fees
+        touched.account(env_info.author);
+        match result {
+            Ok(ref transact_success) => {
+                report.logs(&self.abi, &transact_success.logs);
+                report.finish(self.verbosity, self.trace_sink, !violations.is_empty(), || {
+                    render::render(
+                        &transact_success.trace,
+                        &transact_success.logs,
+                        &self.abi,
+                        self.trace_sink.color(),
+                    )
+                });
+            }
+            Err(ref err) => {
+                report.finish(self.verbosity, self.trace_sink, true, || {
+                    format!("Transaction rejected: {}", err)
+                });
+            }
+        }
+
+        let mut transact_success = result?;
+        if !violations.is_empty() {
+            // the transaction fails as a whole, nothing it did is kept
+            self.backend.reset(state_root);
+            bail!(error::ErrorKind::Cheatcode(violations.join("; ")));
+        }
+        if let Some(cheats) = cheats {
+            if cheats.env_info.number != env_info.number {
+                self.block_number = cheats.env_info.number;
+            }
+            if cheats.env_info.timestamp != env_info.timestamp {
+                self.timestamp = cheats.env_info.timestamp;
+            }
+            self.recorded_logs = cheats.recorded_logs;
+        }
+        // every transaction is executed with no gas used in `EnvInfo`,
+        // the cumulative gas is accounted for here
+        let gas_used = executed.gas - transact_success.gas_left;
+        self.block_gas_used = self.block_gas_used + gas_used;
+        executed.receipt = chain::Receipt {
+            transaction_hash: executed.hash,
+            block_number: executed.block_number,
+            from: executed.from,
+            to: executed.to,
+            status: transact_success.trace.first().map_or(false, |trace| trace.is_success()),
+            gas_used,
+            cumulative_gas_used: self.block_gas_used,
+            contract_address: transact_success.contract_address,
+            logs: transact_success.logs.clone(),
+            logs_bloom: chain::logs_bloom(&transact_success.logs),
+        };
+        executed.trace = transact_success.trace.clone();
+        let hash = executed.hash;
+        self.transactions.push(executed);
+
+        self.record_execution(touched, &transact_success.trace);
         self.logs.extend(transact_success.logs.clone());
-        Ok(transact_success.into())
+        self.record_profile(transact_success.vm_trace.take());
+        let mut output = TransactionOutput::from(transact_success);
+        output.hash = hash;
+        Ok(output)
     }
 
     pub fn transact<F: ContractFunction>(&mut self, f: F) -> error::Result<TransactionOutput> {
         let contract_address = self.contract_address
             .expect("Contract address is not set. Did you forget to deploy the contract?");
+        self.transact_to(contract_address, f.encoded())
+    }
+
+    /// Signs `request` with the key of its sender and sends it, checking it the way
+    /// a node does before execution.
+    ///
+    /// Transactions signed for another chain, with a nonce other than the next
+    /// one, a gas limit below the intrinsic gas or more gas and value than the
+    /// sender can pay for are rejected with a dedicated error.
+    pub fn send_transaction(&mut self, request: &TxRequest) -> error::Result<TransactionOutput> {
+        self.materialize_request(request);
+        let expected_nonce = self.backend.state().nonce(&request.from).expect(STATE);
+        let transaction = self.request_transaction(request, expected_nonce)?;
+        if transaction.nonce != expected_nonce {
+            bail!(error::ErrorKind::InvalidNonce(expected_nonce, transaction.nonce));
+        }
+        let balance = self.backend.state().balance(&request.from).expect(STATE);
+        let (cost, overflow) = transaction.gas.overflowing_mul(transaction.gas_price);
+        let (required, overflow_value) = cost.overflowing_add(transaction.value);
+        let required = if overflow || overflow_value { U256::max_value() } else { required };
+        if required > balance {
+            bail!(error::ErrorKind::InsufficientBalance(required, balance));
+        }
+
         let env_info = self.env_info();
-        let nonce = self.evm.state().nonce(&self.sender).expect(STATE);
+        let output = self.raw_transact(&env_info, transaction)?;
+        if output.contract_address.is_some() {
+            self.contract_address = output.contract_address;
+        }
+        Ok(output)
+    }
+
+    /// Builds and signs the transaction of `request` and checks what doesn't depend on the state.
+    fn request_transaction(&self, request: &TxRequest, nonce: U256) -> error::Result<SignedTransaction> {
+        let secret = self.secret(&request.from)
+            .cloned()
+            .ok_or_else(|| error::ErrorKind::MissingKey(request.from))?;
+        let chain_id = request.chain_id.unwrap_or(self.chain_id);
+        if request.replay_protected && chain_id != self.chain_id {
+            bail!(error::ErrorKind::WrongChain(self.chain_id, chain_id));
+        }
+
         let transaction = Transaction {
-            nonce,
+            nonce: request.nonce.unwrap_or(nonce),
+            gas_price: request.gas_price.unwrap_or(self.gas_price),
+            gas: request.gas.unwrap_or(self.gas),
+            action: request.to.map_or(Action::Create, Action::Call),
+            value: request.value.unwrap_or(self.value),
+            data: request.data.clone(),
+        };
+        let schedule = ::FOUNDATION.engine.schedule(self.block_number);
+        let intrinsic_gas = U256::from(transaction.gas_required(&schedule));
+        if transaction.gas < intrinsic_gas {
+            bail!(error::ErrorKind::IntrinsicGas(intrinsic_gas, transaction.gas));
+        }
+        let chain_id = if request.replay_protected { Some(chain_id) } else { None };
+        Ok(transaction.sign(&secret, chain_id))
+    }
+
+    /// Copies the forked state of the sender and recipient of `request`.
+    fn materialize_request(&mut self, request: &TxRequest) {
+        let mut addresses = vec![request.from];
+        addresses.extend(request.to);
+        self.materialize(&addresses);
+    }
+
+    /// Nonce of the next transaction of `sender`, counting queued transactions.
+    fn next_nonce(&self, sender: &Address) -> U256 {
+        let queued = self.pending
+            .iter()
+            .filter(|transaction| transaction.sender() == *sender)
+            .count();
+        let nonce = match self.forked_account(sender) {
+            Some(account) => account.nonce,
+            None => self.backend.state().nonce(sender).expect(STATE),
+        };
+        nonce + U256::from(queued)
+    }
+
+    /// Address of the contract the next transaction of `sender` would create,
+    /// counting queued transactions.
+    pub fn next_deploy_address(&self, sender: &Address) -> Address {
+        address::create_address(sender, &self.next_nonce(sender))
+    }
+
+    /// Queues `request` for the next block, returns the transaction hash.
+    ///
+    /// The nonce defaults to the next one after the queued transactions of the sender.
+    /// The nonce and balance are only checked once the transaction is mined.
+    pub fn queue_transaction(&mut self, request: &TxRequest) -> error::Result<H256> {
+        self.materialize_request(request);
+        let nonce = self.next_nonce(&request.from);
+        let transaction = self.request_transaction(request, nonce)?;
+        Ok(self.queue(transaction))
+    }
+
+    /// Queues a transaction with raw `data` to `contract_address` for the next block,
+    /// with the sender, gas and value of `transact_to`.
+    pub fn queue_to(&mut self, contract_address: Address, data: Vec<u8>) -> H256 {
+        let sender = self.sender;
+        self.materialize(&[sender, contract_address]);
+        let transaction = Transaction {
+            nonce: self.next_nonce(&sender),
             gas_price: self.gas_price,
             gas: self.gas,
             action: Action::Call(contract_address),
             value: self.value,
-            data: f.encoded(),
-        }.fake_sign(self.sender);
+            data,
+        };
+        let transaction = self.sign(transaction, sender);
+        self.queue(transaction)
+    }
+
+    fn queue(&mut self, transaction: SignedTransaction) -> H256 {
+        let hash = transaction.hash();
+        self.pending.push(transaction);
+        hash
+    }
 
+    /// Transactions waiting for the next `mine`, in the order they were queued.
+    pub fn pending_transactions(&self) -> &[SignedTransaction] {
+        &self.pending
+    }
+
+    /// Executes the queued transactions in the current block and starts the next block,
+    /// `block_time` seconds later.
+    ///
+    /// Transactions with higher gas price go first, the transactions of every
+    /// sender are executed in the order they were queued. Transactions which don't
+    /// fit in the rest of the block gas limit stay queued, together with the later
+    /// transactions of their sender. Invalid ones, including those with more gas
+    /// than the whole block gas limit, are dropped.
+    pub fn mine(&mut self) -> chain::Block {
+        self.record_block();
+        let env_info = self.env_info();
+        let mut block = chain::Block {
+            number: self.block_number,
+            timestamp: self.timestamp,
+            gas_limit: env_info.gas_limit,
+            gas_used: 0.into(),
+            state_root: H256::zero(),
+            receipts: vec![],
+            rejected: vec![],
+        };
+
+        let mut pending = ::std::mem::replace(&mut self.pending, vec![]);
+        let mut postponed = Vec::new();
+        while let Some(index) = chain::next_transaction(&pending) {
+            let transaction = pending.remove(index);
+            let sender = transaction.sender();
+            if transaction.gas > block.gas_limit {
+                let error = error::Error::from(error::ErrorKind::BlockGasLimit(block.gas_limit, transaction.gas));
+                block.rejected.push((transaction.hash(), error.to_string()));
+                continue;
+            }
+            if self.block_gas_used + transaction.gas > block.gas_limit {
+                postponed.push(transaction);
+                let (later, rest): (Vec<_>, Vec<_>) = pending
+                    .into_iter()
+                    .partition(|transaction| transaction.sender() == sender);
+                postponed.extend(later);
+                pending = rest;
+                continue;
+            }
+
+            let hash = transaction.hash();
+            match self.raw_transact(&env_info, transaction) {
+                Ok(_) => {
+                    let receipt = self.receipt(&hash).expect("executed transactions have receipts; qed");
+                    block.receipts.push(receipt.clone());
+                }
+                Err(e) => block.rejected.push((hash, e.to_string())),
+            }
+        }
+
+        self.pending = postponed;
+        block.gas_used = self.block_gas_used;
+        block.state_root = self.backend.root();
+        self.block_number += 1;
+        self.timestamp += self.block_time;
+        block
+    }
+
+    /// Transactions executed so far, in order, including reverted ones.
+    pub fn transactions(&self) -> &[chain::ExecutedTransaction] {
+        &self.transactions
+    }
+
+    /// Returns the executed transaction with `hash`.
+    pub fn transaction(&self, hash: &H256) -> Option<&chain::ExecutedTransaction> {
+        self.transactions
+            .iter()
+            .rev()
+            .find(|transaction| transaction.hash == *hash)
+    }
+
+    /// Returns the receipt of the executed transaction with `hash`.
+    pub fn receipt(&self, hash: &H256) -> Option<&chain::Receipt> {
+        self.transaction(hash).map(|transaction| &transaction.receipt)
+    }
+
+    /// Sends a transaction with raw `data` to `contract_address`.
+    pub fn transact_to(&mut self, contract_address: Address, data: Vec<u8>) -> error::Result<TransactionOutput> {
+        let env_info = self.env_info();
+        let transaction = self.call_transaction(contract_address, data);
         self.raw_transact(&env_info, transaction)
     }
+
+    /// Executes `f` on the current contract like `eth_call`, see `simulate_at`.
+    pub fn simulate<F: ContractFunction>(&mut self, f: F) -> error::Result<TransactionOutput> {
+        let contract_address = self.contract_address
+            .expect("Contract address is not set. Did you forget to deploy the contract?");
+        self.simulate_to(contract_address, f.encoded())
+    }
+
+    /// Executes a transaction with raw `data` to `contract_address` in the current
+    /// block environment and discards its changes, see `simulate_at`.
+    pub fn simulate_to(&mut self, contract_address: Address, data: Vec<u8>) -> error::Result<TransactionOutput> {
+        let env = self.block_env();
+        self.simulate_at(&env, contract_address, data)
+    }
+
+    /// Executes a transaction with raw `data` to `contract_address` in `env`
+    /// and discards its changes, like `eth_call`.
+    ///
+    /// Unlike `call_to` the simulated transaction is a regular transaction of the
+    /// sender with its next nonce, and the logs and trace are kept in the output.
+    pub fn simulate_at(
+        &mut self,
+        env: &BlockEnv,
+        contract_address: Address,
+        data: Vec<u8>,
+    ) -> error::Result<TransactionOutput> {
+        let state_root = self.backend.root();
+        self.simulate_in(state_root, env, contract_address, data)
+    }
+
+    /// Simulates a transaction like `simulate_at` in the state with `state_root`.
+    fn simulate_in(
+        &mut self,
+        state_root: H256,
+        env: &BlockEnv,
+        contract_address: Address,
+        data: Vec<u8>,
+    ) -> error::Result<TransactionOutput> {
+        let snapshot = self.snapshot();
+        self.backend.reset(state_root);
+        let transaction = self.call_transaction(contract_address, data);
+        let result = self.raw_transact(&env.env_info(), transaction);
+        self.revert_to(&snapshot);
+        result
+    }
+
+    /// Calls `f` on the current contract in the state at the end of block `number`.
+    pub fn call_at_block<F: ContractFunction>(&mut self, number: u64, f: F) -> error::Result<F::Output> {
+        let contract_address = self.contract_address
+            .expect("Contract address is not set. Did you forget to deploy the contract?");
+        let output = self.call_at_block_to(number, contract_address, f.encoded())?;
+        let output = f.output(output).expect(
+            "output must be decodable with `ContractFunction` that has encoded input. q.e.d.",
+        );
+        Ok(output)
+    }
+
+    /// Simulates a transaction with raw `data` to `contract_address` in the state
+    /// at the end of block `number` and with `block.number` set to it, see `state_at`.
+    ///
+    /// Returns the output or `ErrorKind::Reverted` with the revert data.
+    pub fn call_at_block_to(
+        &mut self,
+        number: u64,
+        contract_address: Address,
+        data: Vec<u8>,
+    ) -> error::Result<Vec<u8>> {
+        let state_root = self.state_root_at(number)?;
+        let mut env = self.block_env();
+        env.number = number;
+
+        let output = self.simulate_in(state_root, &env, contract_address, data)?;
+        if !output.is_success() {
+            bail!(error::ErrorKind::Reverted(output.output));
+        }
+        Ok(output.output)
+    }
+
+    fn call_transaction(&mut self, contract_address: Address, data: Vec<u8>) -> SignedTransaction {
+        let sender = self.sender;
+        self.materialize(&[sender, contract_address]);
+        let nonce = self.backend.state().nonce(&sender).expect(STATE);
+        let transaction = Transaction {
+            nonce,
+            gas_price: self.gas_price,
+            gas: self.gas,
+            action: Action::Call(contract_address),
+            value: self.value,
+            data,
+        };
+        self.sign(transaction, sender)
+    }
 }
 
 const STATE: &str = "State failure.";
 
+/// Gas of a plain value transfer, the least any transaction costs.
+const TRANSACTION_GAS: u64 = 21_000;
+
 /// converts an `ethcore::log_entry::LogEntry` to an `ethabi::RawLog`
 /// since the events in a contract derived with `ethabi` can only
 /// be parsed from `ethabi::RawLog` (via `event.parse_log(raw_log)`)
@@ -0,0 +1,812 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Execution with calls to the cheatcode address handled natively.
+//!
+//! The executive of `ethcore` builds its own externalities for every frame,
+//! which leaves no way to intercept a call. This one follows it closely, but
+//! frames run against externalities which execute their calls and creates
+//! with this executive again, so a call to `cheatcodes::ADDRESS` is handled at
+//! any depth and its effects are seen by the rest of the transaction.
+//! Builtin contracts are still executed by `ethcore`.
+//!
+//! Frames run on the calling thread: unlike `ethcore`, no thread with a bigger
+//! stack is spawned for deep call chains.
+
+use std::cmp;
+use std::mem;
+use std::sync::Arc;
+
+use ethcore;
+use ethcore::error::ExecutionError;
+use ethcore::executive::contract_address;
+use ethcore::log_entry::LogEntry;
+use ethcore::machine::EthereumMachine;
+use ethcore::receipt::{Receipt, TransactionOutcome};
+use ethcore::state::{ApplyOutcome, CleanupMode, State, Substate};
+use ethcore::state_db::StateDB;
+use ethcore::trace::{Tracer, VMTracer};
+use ethcore_bytes::Bytes;
+use ethcore_evm::{Factory, FinalizationResult, Finalize};
+use ethcore_transaction::{Action, SignedTransaction};
+use ethereum_types::{Address, H256, U256, U512};
+use vm::{self, ActionParams, ActionValue, CallType, EnvInfo, ReturnData, Schedule};
+use vm::{ContractCreateResult, CreateContractAddress, MessageCallResult};
+
+use backend;
+use cheatcodes::{self, Cheatcode, Cheats, Violation};
+use mock;
+
+pub struct Executive<'a> {
+    state: &'a mut State<StateDB>,
+    /// Block environment of the transaction, changed by cheatcodes.
+    cheats: &'a mut Cheats,
+    machine: &'a EthereumMachine,
+    schedule: &'a Schedule,
+    factory: &'a Factory,
+    depth: usize,
+    static_flag: bool,
+}
+
+impl<'a> Executive<'a> {
+    /// Executive of a transaction or top level call in the block environment of `cheats`.
+    pub fn new(
+        state: &'a mut State<StateDB>,
+        cheats: &'a mut Cheats,
+        machine: &'a EthereumMachine,
+        schedule: &'a Schedule,
+        factory: &'a Factory,
+    ) -> Self {
+        Executive {
+            state,
+            cheats,
+            machine,
+            schedule,
+            factory,
+            depth: 0,
+            static_flag: false,
+        }
+    }
+
+    /// Executes `transaction`, its changes are left uncommitted.
+    pub fn transact<T, V>(
+        &mut self,
+        transaction: &SignedTransaction,
+        mut tracer: T,
+        mut vm_tracer: V,
+    ) -> Result<ApplyOutcome<T::Output, V::Output>, ExecutionError>
+    where
+        T: Tracer,
+        V: VMTracer,
+    {
+        let sender = transaction.sender();
+        let nonce = self.state.nonce(&sender)?;
+
+        let base_gas = U256::from(transaction.gas_required(self.schedule));
+        if transaction.gas < base_gas {
+            return Err(ExecutionError::NotEnoughBaseGas {
+                required: base_gas,
+                got: transaction.gas,
+            });
+        }
+        if transaction.nonce != nonce {
+            return Err(ExecutionError::InvalidNonce {
+                expected: nonce,
+                got: transaction.nonce,
+            });
+        }
+        let (gas_used, gas_limit) = (self.cheats.env_info.gas_used, self.cheats.env_info.gas_limit);
+        if gas_used + transaction.gas > gas_limit {
+            return Err(ExecutionError::BlockGasLimitReached {
+                gas_limit,
+                gas_used,
+                gas: transaction.gas,
+            });
+        }
+        let gas_cost = transaction.gas.full_mul(transaction.gas_price);
+        let total_cost = U512::from(transaction.value) + gas_cost;
+        let balance = U512::from(self.state.balance(&sender)?);
+        if balance < total_cost {
+            return Err(ExecutionError::NotEnoughCash {
+                required: total_cost,
+                got: balance,
+            });
+        }
+
+        let mut substate = Substate::new();
+        self.state.inc_nonce(&sender)?;
+        self.state
+            .sub_balance(&sender, &U256::from(gas_cost), &mut substate.to_cleanup_mode(self.schedule))?;
+
+        let mut params = ActionParams::default();
+        params.sender = sender;
+        params.origin = sender;
+        params.gas = transaction.gas - base_gas;
+        params.gas_price = transaction.gas_price;
+        params.value = ActionValue::Transfer(transaction.value);
+        let (result, output) = match transaction.action {
+            Action::Create => {
+                let scheme = self.machine.create_address_scheme(self.cheats.env_info.number);
+                let (address, code_hash) = contract_address(scheme, &sender, &nonce, &transaction.data);
+                params.address = address;
+                params.code_address = address;
+                params.code = Some(Arc::new(transaction.data.clone()));
+                params.code_hash = code_hash;
+                params.data = None;
+                params.call_type = CallType::None;
+                params.params_type = vm::ParamsType::Embedded;
+                let result = self.create(params, &mut substate, &mut tracer, &mut vm_tracer);
+                (result, Vec::new())
+            }
+            Action::Call(address) => {
+                params.address = address;
+                params.code_address = address;
+                params.code = self.state.code(&address)?;
+                params.code_hash = Some(self.state.code_hash(&address)?);
+                params.data = Some(transaction.data.clone());
+                params.call_type = CallType::Call;
+                let result = self.call(params, &mut substate, &mut tracer, &mut vm_tracer);
+                let output = match result {
+                    Ok(ref result) => result.return_data.to_vec(),
+                    Err(_) => Vec::new(),
+                };
+                (result, output)
+            }
+        };
+
+        self.finalize(transaction, substate, result, output, tracer.drain(), vm_tracer.drain())
+    }
+
+    /// Refunds the sender, pays the author and builds the receipt.
+    fn finalize<T, V>(
+        &mut self,
+        transaction: &SignedTransaction,
+        mut substate: Substate,
+        result: vm::Result<FinalizationResult>,
+        output: Bytes,
+        trace: Vec<T>,
+        vm_trace: Option<V>,
+    ) -> Result<ApplyOutcome<T, V>, ExecutionError> {
+        let schedule = self.schedule;
+        let sstore_refunds = U256::from(schedule.sstore_refund_gas) * substate.sstore_clears_count;
+        let suicide_refunds = U256::from(schedule.suicide_refund_gas) * U256::from(substate.suicides.len());
+        let gas_left = match result {
+            Ok(ref result) => result.gas_left,
+            Err(_) => U256::zero(),
+        };
+        let refunded = cmp::min(sstore_refunds + suicide_refunds, (transaction.gas - gas_left) >> 1);
+        let gas_left = gas_left + refunded;
+        let gas_used = transaction.gas - gas_left;
+
+        let sender = transaction.sender();
+        let author = self.cheats.env_info.author;
+        self.state.add_balance(
+            &sender,
+            &(gas_left * transaction.gas_price),
+            substate.to_cleanup_mode(schedule),
+        )?;
+        self.state.add_balance(
+            &author,
+            &(gas_used * transaction.gas_price),
+            substate.to_cleanup_mode(schedule),
+        )?;
+        for address in &substate.suicides {
+            self.state.kill_account(address);
+        }
+        self.state
+            .kill_garbage(&substate.touched, schedule.kill_empty, &None, false)?;
+
+        let (failed, logs) = match result {
+            Err(vm::Error::Internal(message)) => return Err(ExecutionError::Internal(message)),
+            Err(_) => (true, Vec::new()),
+            Ok(result) => (!result.apply_state, substate.logs),
+        };
+        let outcome = if self.cheats.env_info.number >= self.machine.params().eip658_transition {
+            TransactionOutcome::StatusCode(if failed { 0 } else { 1 })
+        } else {
+            TransactionOutcome::Unknown
+        };
+        Ok(ApplyOutcome {
+            receipt: Receipt::new(outcome, self.cheats.env_info.gas_used + gas_used, logs),
+            output,
+            trace,
+            vm_trace,
+        })
+    }
+
+    /// Executes a message call, its changes are reverted if it fails.
+    pub fn call<T, V>(
+        &mut self,
+        params: ActionParams,
+        substate: &mut Substate,
+        tracer: &mut T,
+        vm_tracer: &mut V,
+    ) -> vm::Result<FinalizationResult>
+    where
+        T: Tracer,
+        V: VMTracer,
+    {
+        if self.machine
+            .builtin(&params.code_address, self.cheats.env_info.number)
+            .is_some()
+        {
+            return self.builtin(params, substate, tracer, vm_tracer);
+        }
+
+        let static_call = params.call_type == CallType::StaticCall;
+        let mutable_call = params.call_type == CallType::Call && self.static_flag;
+        if (static_call || mutable_call) && !params.value.value().is_zero() {
+            return Err(vm::Error::MutableCallInStaticContext);
+        }
+
+        self.state.checkpoint();
+        if let ActionValue::Transfer(value) = params.value {
+            self.state.transfer_balance(
+                &params.sender,
+                &params.address,
+                &value,
+                substate.to_cleanup_mode(self.schedule),
+            )?;
+        }
+
+        let trace_info = tracer.prepare_trace_call(&params);
+        let gas = params.gas;
+        let code = match params.code {
+            Some(ref code) => code.clone(),
+            None => {
+                self.state.discard_checkpoint();
+                let output = tracer.prepare_trace_output();
+                tracer.trace_call(trace_info, U256::zero(), output, vec![]);
+                return Ok(FinalizationResult {
+                    gas_left: gas,
+                    return_data: ReturnData::empty(),
+                    apply_state: true,
+                });
+            }
+        };
+
+        let mut subtracer = tracer.subtracer();
+        let mut subvmtracer = vm_tracer.prepare_subtrace(&code[..]);
+        let mut unconfirmed = Substate::new();
+        let cheats = self.cheats.checkpoint();
+        let result = self.exec(params, &mut unconfirmed, false, &mut subtracer, &mut subvmtracer);
+        vm_tracer.done_subtrace(subvmtracer);
+
+        let traces = subtracer.drain();
+        match result {
+            Ok(ref result) if result.apply_state => {
+                let output = tracer
+                    .prepare_trace_output()
+                    .map(|_| result.return_data.to_vec());
+                tracer.trace_call(trace_info, gas - result.gas_left, output, traces);
+            }
+            Ok(_) => tracer.trace_failed_call(trace_info, traces, (&vm::Error::Reverted).into()),
+            Err(ref error) => tracer.trace_failed_call(trace_info, traces, error.into()),
+        }
+
+        self.enact(&result, substate, unconfirmed, cheats);
+        result
+    }
+
+    /// Calls a builtin contract, which can't call back into this executive.
+    fn builtin<T, V>(
+        &mut self,
+        params: ActionParams,
+        substate: &mut Substate,
+        tracer: &mut T,
+        vm_tracer: &mut V,
+    ) -> vm::Result<FinalizationResult>
+    where
+        T: Tracer,
+        V: VMTracer,
+    {
+        let env_info = &self.cheats.env_info;
+        let mut executive = match self.depth {
+            0 => ethcore::executive::Executive::new(&mut *self.state, env_info, self.machine, self.schedule),
+            depth => ethcore::executive::Executive::from_parent(
+                &mut *self.state,
+                env_info,
+                self.machine,
+                self.schedule,
+                depth - 1,
+                self.static_flag,
+            ),
+        };
+        executive.call(params, substate, tracer, vm_tracer)
+    }
+
+    /// Creates a contract, its changes are reverted if it fails.
+    pub fn create<T, V>(
+        &mut self,
+        params: ActionParams,
+        substate: &mut Substate,
+        tracer: &mut T,
+        vm_tracer: &mut V,
+    ) -> vm::Result<FinalizationResult>
+    where
+        T: Tracer,
+        V: VMTracer,
+    {
+        // EIP-684: creating a contract over an existing one fails like an invalid opcode
+        if self.state.exists_and_has_code_or_nonce(&params.address)? {
+            return Err(vm::Error::OutOfGas);
+        }
+
+        self.state.checkpoint();
+        let nonce_offset = if self.schedule.no_empty { 1 } else { 0 }.into();
+        let balance = self.state.balance(&params.address)?;
+        match params.value {
+            ActionValue::Transfer(value) => {
+                self.state
+                    .sub_balance(&params.sender, &value, &mut substate.to_cleanup_mode(self.schedule))?;
+                self.state
+                    .new_contract(&params.address, value + balance, nonce_offset);
+            }
+            ActionValue::Apparent(_) => self.state.new_contract(&params.address, balance, nonce_offset),
+        }
+
+        let trace_info = tracer.prepare_trace_create(&params);
+        let gas = params.gas;
+        let created = params.address;
+        let mut subtracer = tracer.subtracer();
+        let mut subvmtracer = vm_tracer.prepare_subtrace(
+            params
+                .code
+                .as_ref()
+                .expect("creates always have init code in `params`; qed"),
+        );
+        let mut unconfirmed = Substate::new();
+        let cheats = self.cheats.checkpoint();
+        let result = self.exec(params, &mut unconfirmed, true, &mut subtracer, &mut subvmtracer);
+        vm_tracer.done_subtrace(subvmtracer);
+
+        let traces = subtracer.drain();
+        match result {
+            Ok(ref result) if result.apply_state => {
+                let code = tracer
+                    .prepare_trace_output()
+                    .map(|_| result.return_data.to_vec());
+                tracer.trace_create(trace_info, gas - result.gas_left, code, created, traces);
+            }
+            Ok(_) => tracer.trace_failed_create(trace_info, traces, (&vm::Error::Reverted).into()),
+            Err(ref error) => tracer.trace_failed_create(trace_info, traces, error.into()),
+        }
+
+        self.enact(&result, substate, unconfirmed, cheats);
+        result
+    }
+
+    /// Runs the code of a frame.
+    fn exec<T, V>(
+        &mut self,
+        params: ActionParams,
+        substate: &mut Substate,
+        init_contract: bool,
+        tracer: &mut T,
+        vm_tracer: &mut V,
+    ) -> vm::Result<FinalizationResult>
+    where
+        T: Tracer,
+        V: VMTracer,
+    {
+        let mut vm = self.factory.create(&params.gas);
+        let mut ext = Externalities {
+            state: &mut *self.state,
+            cheats: &mut *self.cheats,
+            machine: self.machine,
+            schedule: self.schedule,
+            factory: self.factory,
+            depth: self.depth,
+            static_flag: self.static_flag || params.call_type == CallType::StaticCall,
+            address: params.address,
+            origin: params.origin,
+            gas_price: params.gas_price,
+            value: params.value.value(),
+            init_contract,
+            substate,
+            tracer,
+            vm_tracer,
+            prank: None,
+            expect_revert: false,
+        };
+        let result = vm.exec(params, &mut ext);
+        if ext.expect_revert {
+            ext.cheats.violations.push(Violation::NoCall);
+        }
+        result.finalize(ext)
+    }
+
+    /// Keeps the changes of a frame which succeeded, reverts them otherwise.
+    fn enact(
+        &mut self,
+        result: &vm::Result<FinalizationResult>,
+        substate: &mut Substate,
+        unconfirmed: Substate,
+        cheats: cheatcodes::Checkpoint,
+    ) {
+        let applied = match *result {
+            Ok(ref result) => result.apply_state,
+            Err(vm::Error::Internal(_)) => true,
+            Err(_) => false,
+        };
+        if applied {
+            self.state.discard_checkpoint();
+            substate.accrue(unconfirmed);
+        } else {
+            self.state.revert_to_checkpoint();
+            self.cheats.revert_to(cheats);
+        }
+    }
+}
+
+struct Externalities<'a, T: 'a, V: 'a> {
+    state: &'a mut State<StateDB>,
+    cheats: &'a mut Cheats,
+    machine: &'a EthereumMachine,
+    schedule: &'a Schedule,
+    factory: &'a Factory,
+    depth: usize,
+    static_flag: bool,
+    /// Address the frame is executed in.
+    address: Address,
+    origin: Address,
+    gas_price: U256,
+    value: U256,
+    /// Whether the frame returns the code of a contract it creates.
+    init_contract: bool,
+    substate: &'a mut Substate,
+    tracer: &'a mut T,
+    vm_tracer: &'a mut V,
+    /// Sender of the next call, set by `prank`.
+    prank: Option<Address>,
+    /// Whether the next call has to fail, set by `expectRevert`.
+    expect_revert: bool,
+}
+
+impl<'a, T, V> Externalities<'a, T, V>
+where
+    T: Tracer,
+    V: VMTracer,
+{
+    /// Runs `f` with the executive of a frame entered from this one.
+    fn nested<F, R>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut Executive, &mut Substate, &mut T, &mut V) -> R,
+    {
+        let mut executive = Executive {
+            state: &mut *self.state,
+            cheats: &mut *self.cheats,
+            machine: self.machine,
+            schedule: self.schedule,
+            factory: self.factory,
+            depth: self.depth + 1,
+            static_flag: self.static_flag,
+        };
+        f(&mut executive, &mut *self.substate, &mut *self.tracer, &mut *self.vm_tracer)
+    }
+
+    /// Handles a call to the cheatcode address, see `cheatcodes`.
+    fn cheat(&mut self, gas: &U256, sender: &Address, data: &[u8], call_type: CallType) -> MessageCallResult {
+        let mut params = ActionParams::default();
+        params.sender = *sender;
+        params.origin = self.origin;
+        params.address = cheatcodes::address();
+        params.code_address = cheatcodes::address();
+        params.gas = *gas;
+        params.gas_price = self.gas_price;
+        params.data = Some(data.to_vec());
+        params.call_type = call_type;
+        let trace_info = self.tracer.prepare_trace_call(&params);
+
+        let result = match Cheatcode::decode(data) {
+            Some(cheatcode) => self.apply(cheatcode),
+            None => {
+                self.cheats.violations.push(Violation::Unknown { input: data.to_vec() });
+                Err(vm::Error::Reverted)
+            }
+        };
+        match result {
+            Ok(()) => {
+                let output = self.tracer.prepare_trace_output();
+                self.tracer.trace_call(trace_info, U256::zero(), output, vec![]);
+                MessageCallResult::Success(*gas, ReturnData::empty())
+            }
+            Err(error) => {
+                self.tracer.trace_failed_call(trace_info, vec![], (&error).into());
+                MessageCallResult::Failed
+            }
+        }
+    }
+
+    fn apply(&mut self, cheatcode: Cheatcode) -> vm::Result<()> {
+        match cheatcode {
+            Cheatcode::Warp(timestamp) => self.cheats.env_info.timestamp = timestamp.low_u64(),
+            Cheatcode::Roll(number) => self.cheats.env_info.number = number.low_u64(),
+            Cheatcode::Prank(sender) => self.prank = Some(sender),
+            Cheatcode::Deal(address, balance) => backend::set_balance(self.state, &address, balance)?,
+            Cheatcode::Store(address, key, value) => self.state.set_storage(&address, key, value)?,
+            Cheatcode::ExpectRevert => self.expect_revert = true,
+            Cheatcode::RecordLogs => self.cheats.record_logs(),
+        }
+        Ok(())
+    }
+
+    /// Turns the result of a call expected to revert into a success if it failed
+    /// and into a failure with a violation otherwise.
+    fn expected_revert(&mut self, result: MessageCallResult, to: &Address, data: &[u8]) -> MessageCallResult {
+        match result {
+            MessageCallResult::Success(gas_left, _) => {
+                self.cheats.violations.push(Violation::NotReverted {
+                    to: *to,
+                    input: data.to_vec(),
+                });
+                let reason = mock::revert_reason("Call did not revert as expected");
+                let len = reason.len();
+                MessageCallResult::Reverted(gas_left, ReturnData::new(reason, 0, len))
+            }
+            MessageCallResult::Reverted(gas_left, _) => MessageCallResult::Success(gas_left, ReturnData::empty()),
+            MessageCallResult::Failed => MessageCallResult::Success(U256::zero(), ReturnData::empty()),
+        }
+    }
+}
+
+impl<'a, T, V> vm::Ext for Externalities<'a, T, V>
+where
+    T: Tracer,
+    V: VMTracer,
+{
+    fn storage_at(&self, key: &H256) -> vm::Result<H256> {
+        Ok(self.state.storage_at(&self.address, key)?)
+    }
+
+    fn set_storage(&mut self, key: H256, value: H256) -> vm::Result<()> {
+        if self.static_flag {
+            return Err(vm::Error::MutableCallInStaticContext);
+        }
+        Ok(self.state.set_storage(&self.address, key, value)?)
+    }
+
+    fn exists(&self, address: &Address) -> vm::Result<bool> {
+        Ok(self.state.exists(address)?)
+    }
+
+    fn exists_and_not_null(&self, address: &Address) -> vm::Result<bool> {
+        Ok(self.state.exists_and_not_null(address)?)
+    }
+
+    fn origin_balance(&self) -> vm::Result<U256> {
+        self.balance(&self.address)
+    }
+
+    fn balance(&self, address: &Address) -> vm::Result<U256> {
+        Ok(self.state.balance(address)?)
+    }
+
+    fn blockhash(&mut self, number: &U256) -> H256 {
+        let env_info = &self.cheats.env_info;
+        let current = U256::from(env_info.number);
+        if *number < current && current - *number <= 256.into() {
+            let index = (current - *number).low_u64() as usize - 1;
+            env_info.last_hashes.get(index).cloned().unwrap_or_default()
+        } else {
+            H256::zero()
+        }
+    }
+
+    fn create(
+        &mut self,
+        gas: &U256,
+        value: &U256,
+        code: &[u8],
+        address_scheme: CreateContractAddress,
+    ) -> ContractCreateResult {
+        let nonce = match self.state.nonce(&self.address) {
+            Ok(nonce) => nonce,
+            Err(_) => return ContractCreateResult::Failed,
+        };
+        let (address, code_hash) = contract_address(address_scheme, &self.address, &nonce, code);
+
+        let mut params = ActionParams::default();
+        params.sender = self.address;
+        params.origin = self.origin;
+        params.address = address;
+        params.code_address = address;
+        params.gas = *gas;
+        params.gas_price = self.gas_price;
+        params.value = ActionValue::Transfer(*value);
+        params.code = Some(Arc::new(code.to_vec()));
+        params.code_hash = code_hash;
+        params.data = None;
+        params.call_type = CallType::None;
+        params.params_type = vm::ParamsType::Embedded;
+
+        if !self.static_flag && self.state.inc_nonce(&self.address).is_err() {
+            return ContractCreateResult::Failed;
+        }
+
+        let result = self.nested(|executive, substate, tracer, vm_tracer| {
+            executive.create(params, substate, tracer, vm_tracer)
+        });
+        match result {
+            Ok(ref result) if result.apply_state => {
+                self.substate.contracts_created.push(address);
+                ContractCreateResult::Created(address, result.gas_left)
+            }
+            Ok(result) => ContractCreateResult::Reverted(result.gas_left, result.return_data),
+            Err(_) => ContractCreateResult::Failed,
+        }
+    }
+
+    fn call(
+        &mut self,
+        gas: &U256,
+        sender_address: &Address,
+        receive_address: &Address,
+        value: Option<U256>,
+        data: &[u8],
+        code_address: &Address,
+        call_type: CallType,
+    ) -> MessageCallResult {
+        if *code_address == cheatcodes::address() {
+            return self.cheat(gas, sender_address, data, call_type);
+        }
+
+        let (code, code_hash) = match (self.state.code(code_address), self.state.code_hash(code_address)) {
+            (Ok(code), Ok(code_hash)) => (code, code_hash),
+            _ => return MessageCallResult::Failed,
+        };
+
+        let mut params = ActionParams::default();
+        params.sender = *sender_address;
+        if call_type == CallType::Call || call_type == CallType::StaticCall {
+            if let Some(sender) = self.prank.take() {
+                params.sender = sender;
+            }
+        }
+        params.origin = self.origin;
+        params.address = *receive_address;
+        params.code_address = *code_address;
+        params.gas = *gas;
+        params.gas_price = self.gas_price;
+        params.value = match value {
+            Some(value) => ActionValue::Transfer(value),
+            None => ActionValue::Apparent(self.value),
+        };
+        params.code = code;
+        params.code_hash = Some(code_hash);
+        params.data = Some(data.to_vec());
+        params.call_type = call_type;
+
+        let expect_revert = mem::replace(&mut self.expect_revert, false);
+        let result = self.nested(|executive, substate, tracer, vm_tracer| {
+            executive.call(params, substate, tracer, vm_tracer)
+        });
+        let result = match result {
+            Ok(result) => match result.apply_state {
+                true => MessageCallResult::Success(result.gas_left, result.return_data),
+                false => MessageCallResult::Reverted(result.gas_left, result.return_data),
+            },
+            Err(_) => MessageCallResult::Failed,
+        };
+        if expect_revert {
+            self.expected_revert(result, receive_address, data)
+        } else {
+            result
+        }
+    }
+
+    fn extcode(&self, address: &Address) -> vm::Result<Arc<Bytes>> {
+        Ok(self.state.code(address)?.unwrap_or_else(|| Arc::new(Vec::new())))
+    }
+
+    fn extcodesize(&self, address: &Address) -> vm::Result<usize> {
+        Ok(self.state.code_size(address)?.unwrap_or(0))
+    }
+
+    fn log(&mut self, topics: Vec<H256>, data: &[u8]) -> vm::Result<()> {
+        if self.static_flag {
+            return Err(vm::Error::MutableCallInStaticContext);
+        }
+        let log = LogEntry {
+            address: self.address,
+            topics,
+            data: data.to_vec(),
+        };
+        self.cheats.log(&log);
+        self.substate.logs.push(log);
+        Ok(())
+    }
+
+    fn ret(mut self, gas: &U256, data: &ReturnData, apply_state: bool) -> vm::Result<U256>
+    where
+        Self: Sized,
+    {
+        if !self.init_contract || !apply_state {
+            return Ok(*gas);
+        }
+
+        let cost = U256::from(data.len()) * U256::from(self.schedule.create_data_gas);
+        if cost > *gas || data.len() > self.schedule.create_data_limit {
+            return match self.schedule.exceptional_failed_code_deposit {
+                true => Err(vm::Error::OutOfGas),
+                false => Ok(*gas),
+            };
+        }
+        self.state.init_code(&self.address, data.to_vec())?;
+        Ok(*gas - cost)
+    }
+
+    fn suicide(&mut self, refund_address: &Address) -> vm::Result<()> {
+        if self.static_flag {
+            return Err(vm::Error::MutableCallInStaticContext);
+        }
+        let address = self.address;
+        let balance = self.state.balance(&address)?;
+        if address == *refund_address {
+            self.state
+                .sub_balance(&address, &balance, &mut CleanupMode::NoEmpty)?;
+        } else {
+            self.state.transfer_balance(
+                &address,
+                refund_address,
+                &balance,
+                self.substate.to_cleanup_mode(self.schedule),
+            )?;
+        }
+        self.tracer.trace_suicide(address, balance, *refund_address);
+        self.substate.suicides.insert(address);
+        Ok(())
+    }
+
+    fn schedule(&self) -> &Schedule {
+        self.schedule
+    }
+
+    fn env_info(&self) -> &EnvInfo {
+        &self.cheats.env_info
+    }
+
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn inc_sstore_clears(&mut self) {
+        self.substate.sstore_clears_count = self.substate.sstore_clears_count + U256::one();
+    }
+
+    fn trace_next_instruction(&mut self, pc: usize, instruction: u8, current_gas: U256) -> bool {
+        self.vm_tracer.trace_next_instruction(pc, instruction, current_gas)
+    }
+
+    fn trace_prepare_execute(&mut self, pc: usize, instruction: u8, gas_cost: U256) {
+        self.vm_tracer.trace_prepare_execute(pc, instruction, gas_cost)
+    }
+
+    fn trace_executed(
+        &mut self,
+        gas_used: U256,
+        stack_push: &[U256],
+        mem_diff: Option<(usize, &[u8])>,
+        store_diff: Option<(U256, U256)>,
+    ) {
+        self.vm_tracer
+            .trace_executed(gas_used, stack_push, mem_diff, store_diff)
+    }
+
+    fn is_static(&self) -> bool {
+        self.static_flag
+    }
+}
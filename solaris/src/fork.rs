@@ -0,0 +1,337 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Offline fork of recorded state.
+//!
+//! A `ForkSource` holds accounts imported from a state dump or from recorded
+//! `eth_getProof`, `eth_getCode` and `eth_getStorageAt` responses. Forked
+//! accounts are copied into the `Evm` state the first time a transaction or
+//! call touches them, see `Evm::with_fork`.
+//!
+//! State reads can't be intercepted, so the sender and recipient are copied
+//! before a transaction is signed, and an execution which touches other
+//! forked accounts for the first time is rolled back and run again once
+//! they're copied. Only the last run is traced and profiled.
+//! Accounts which are only inspected with `BALANCE` or `EXTCODE*` aren't
+//! noticed, copy them upfront with `Evm::materialize`.
+//!
+//! ```ignore
+//! let mut source = ForkSource::default();
+//! source.load_state_dump("fixtures/mainnet.json")?;
+//! source.load_recordings("fixtures/rpc")?;
+//! evm.with_fork(source);
+//! ```
+//!
+//! Recordings are JSON files with one exchange or an array of exchanges:
+//!
+//! ```json
+//! {
+//!   "request": { "method": "eth_getCode", "params": ["0x…", "latest"] },
+//!   "response": { "jsonrpc": "2.0", "id": 1, "result": "0x6080…" }
+//! }
+//! ```
+//!
+//! Other methods are skipped.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ethereum_types::{Address, H256, U256};
+use rustc_hex::FromHex;
+use serde_json::{self, Value};
+
+use error;
+use state::Account;
+
+/// Accounts to fork from.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ForkSource {
+    accounts: BTreeMap<Address, Account>,
+}
+
+impl ForkSource {
+    /// Adds or replaces a forked account.
+    pub fn insert(&mut self, address: Address, account: Account) -> &mut Self {
+        self.accounts.insert(address, account);
+        self
+    }
+
+    pub fn account(&self, address: &Address) -> Option<&Account> {
+        self.accounts.get(address)
+    }
+
+    pub fn accounts(&self) -> &BTreeMap<Address, Account> {
+        &self.accounts
+    }
+
+    /// Adds accounts from a JSON state dump file, see `add_state_dump`.
+    pub fn load_state_dump<P: AsRef<Path>>(&mut self, path: P) -> error::Result<&mut Self> {
+        let dump = read_json(path.as_ref())?;
+        self.add_state_dump(&dump)
+    }
+
+    /// Adds accounts from a state dump: the `state` of `parity export state`,
+    /// the `accounts` of a chain spec or of `Evm::save_state`, or a plain map
+    /// from addresses to accounts.
+    ///
+    /// Quantities may be hex or decimal, builtin accounts are skipped.
+    pub fn add_state_dump(&mut self, dump: &Value) -> error::Result<&mut Self> {
+        let accounts = dump.get("state")
+            .or_else(|| dump.get("accounts"))
+            .unwrap_or(dump)
+            .as_object()
+            .ok_or_else(|| invalid("state dump is not an object of accounts"))?;
+        for (address, account) in accounts {
+            if account.get("builtin").is_some() {
+                continue;
+            }
+            let address = parse_address(address)?;
+            let account = parse_account(account)
+                .map_err(|e| invalid(format!("account {:?}: {}", address, e)))?;
+            self.accounts.insert(address, account);
+        }
+        Ok(self)
+    }
+
+    /// Adds recorded responses from a JSON file, or from all `*.json` files of a directory.
+    pub fn load_recordings<P: AsRef<Path>>(&mut self, path: P) -> error::Result<&mut Self> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            let recordings = read_json(path)?;
+            return self.add_recordings(&recordings);
+        }
+
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        for path in paths {
+            let recordings = read_json(&path)?;
+            self.add_recordings(&recordings)?;
+        }
+        Ok(self)
+    }
+
+    /// Adds a recorded exchange or an array of them.
+    pub fn add_recordings(&mut self, recordings: &Value) -> error::Result<&mut Self> {
+        match recordings.as_array() {
+            Some(recordings) => {
+                for recording in recordings {
+                    self.add_recording(recording)?;
+                }
+            }
+            None => {
+                self.add_recording(recordings)?;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Adds the response of a recorded `eth_getProof`, `eth_getCode` or `eth_getStorageAt`.
+    pub fn add_recording(&mut self, recording: &Value) -> error::Result<&mut Self> {
+        let method = recording
+            .pointer("/request/method")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("recording without request method"))?;
+        let params = recording
+            .pointer("/request/params")
+            .and_then(Value::as_array)
+            .map_or(&[][..], |params| &params[..]);
+        if !["eth_getProof", "eth_getCode", "eth_getStorageAt"].contains(&method) {
+            return Ok(self);
+        }
+
+        let result = recording
+            .pointer("/response/result")
+            .ok_or_else(|| invalid(format!("{} recording without result", method)))?;
+        let address = params
+            .get(0)
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid(format!("{} recording without address", method)))
+            .and_then(parse_address)?;
+        let account = self.accounts.entry(address).or_insert_with(Default::default);
+        match method {
+            "eth_getProof" => {
+                account.balance = parse_uint(field(result, "balance")?)?;
+                account.nonce = parse_uint(field(result, "nonce")?)?;
+                let proofs = result
+                    .get("storageProof")
+                    .and_then(Value::as_array)
+                    .map_or(&[][..], |proofs| &proofs[..]);
+                for proof in proofs {
+                    let key = parse_uint(field(proof, "key")?)?;
+                    let value = parse_uint(field(proof, "value")?)?;
+                    account.set_storage(key.into(), value.into());
+                }
+            }
+            "eth_getCode" => account.code = parse_bytes(result)?,
+            _ => {
+                let key = parse_uint(params.get(1).ok_or_else(|| invalid("eth_getStorageAt without slot"))?)?;
+                account.set_storage(key.into(), parse_uint(result)?.into());
+            }
+        }
+        Ok(self)
+    }
+}
+
+fn invalid<S: Into<String>>(msg: S) -> error::Error {
+    error::ErrorKind::Fork(msg.into()).into()
+}
+
+fn read_json(path: &Path) -> error::Result<Value> {
+    let file = io::BufReader::new(fs::File::open(path)?);
+    serde_json::from_reader(file)
+        .map_err(|e| invalid(format!("{}: {}", path.display(), e)))
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> error::Result<&'a Value> {
+    value
+        .get(name)
+        .ok_or_else(|| invalid(format!("missing {}", name)))
+}
+
+fn strip_hex_prefix(s: &str) -> Option<&str> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        Some(&s[2..])
+    } else {
+        None
+    }
+}
+
+fn parse_address(s: &str) -> error::Result<Address> {
+    let hex = strip_hex_prefix(s).unwrap_or(s);
+    hex.parse()
+        .map_err(|_| invalid(format!("invalid address {}", s)))
+}
+
+/// Parses a hex or decimal quantity, either a string or a number.
+fn parse_uint(value: &Value) -> error::Result<U256> {
+    if let Some(number) = value.as_u64() {
+        return Ok(number.into());
+    }
+    let s = value
+        .as_str()
+        .ok_or_else(|| invalid(format!("{} is not a quantity", value)))?;
+    match strip_hex_prefix(s) {
+        Some("") => Ok(U256::zero()),
+        Some(hex) if hex.len() <= 64 => hex.parse()
+            .map_err(|_| invalid(format!("invalid quantity {}", s))),
+        Some(_) => Err(invalid(format!("quantity {} is too long", s))),
+        None => U256::from_dec_str(s).map_err(|_| invalid(format!("invalid quantity {}", s))),
+    }
+}
+
+fn parse_bytes(value: &Value) -> error::Result<Vec<u8>> {
+    let s = value
+        .as_str()
+        .ok_or_else(|| invalid(format!("{} is not hex data", value)))?;
+    Ok(strip_hex_prefix(s).unwrap_or(s).from_hex()?)
+}
+
+fn parse_account(value: &Value) -> error::Result<Account> {
+    let mut account = Account::default();
+    if let Some(balance) = value.get("balance") {
+        account.balance = parse_uint(balance)?;
+    }
+    if let Some(nonce) = value.get("nonce") {
+        account.nonce = parse_uint(nonce)?;
+    }
+    if let Some(code) = value.get("code") {
+        account.code = parse_bytes(code)?;
+    }
+    if let Some(storage) = value.get("storage").and_then(Value::as_object) {
+        for (key, value) in storage {
+            let key = parse_uint(&Value::String(key.clone()))?;
+            account.set_storage(key.into(), parse_uint(value)?.into());
+        }
+    }
+    Ok(account)
+}
+
+#[test]
+fn test_add_state_dump() {
+    let dump = serde_json::from_str(
+        r#"{
+            "state": {
+                "0x0000000000000000000000000000000000000001": { "builtin": { "name": "ecrecover" } },
+                "0x00000000000000000000000000000000000000aa": {
+                    "balance": "1000",
+                    "nonce": "0x1",
+                    "code": "0x6000",
+                    "storage": { "0x00": "0x2a", "0x01": "0x0" }
+                }
+            }
+        }"#,
+    ).unwrap();
+    let mut source = ForkSource::default();
+    source.add_state_dump(&dump).unwrap();
+
+    assert_eq!(source.accounts().len(), 1);
+    let account = source.account(&0xaa.into()).unwrap();
+    assert_eq!(account.balance, 1000.into());
+    assert_eq!(account.nonce, 1.into());
+    assert_eq!(account.code, vec![0x60, 0x00]);
+    assert_eq!(account.storage.len(), 1);
+    assert_eq!(account.storage[&H256::zero()], H256::from(42));
+}
+
+#[test]
+fn test_add_recordings() {
+    let recordings = serde_json::from_str(
+        r#"[
+            {
+                "request": { "method": "eth_getProof", "params": ["0x00000000000000000000000000000000000000aa", ["0x1"], "latest"] },
+                "response": { "jsonrpc": "2.0", "id": 1, "result": {
+                    "balance": "0x10",
+                    "nonce": "0x2",
+                    "storageProof": [{ "key": "0x1", "value": "0x5", "proof": [] }]
+                } }
+            },
+            {
+                "request": { "method": "eth_getCode", "params": ["0x00000000000000000000000000000000000000aa", "latest"] },
+                "response": { "jsonrpc": "2.0", "id": 2, "result": "0x6001" }
+            },
+            {
+                "request": { "method": "eth_getStorageAt", "params": ["0x00000000000000000000000000000000000000aa", "0x2", "latest"] },
+                "response": { "jsonrpc": "2.0", "id": 3, "result": "0x0000000000000000000000000000000000000000000000000000000000000007" }
+            },
+            {
+                "request": { "method": "eth_blockNumber", "params": [] },
+                "response": { "jsonrpc": "2.0", "id": 4, "result": "0x10" }
+            }
+        ]"#,
+    ).unwrap();
+    let mut source = ForkSource::default();
+    source.add_recordings(&recordings).unwrap();
+
+    assert_eq!(source.accounts().len(), 1);
+    let account = source.account(&0xaa.into()).unwrap();
+    assert_eq!(account.balance, 16.into());
+    assert_eq!(account.nonce, 2.into());
+    assert_eq!(account.code, vec![0x60, 0x01]);
+    assert_eq!(account.storage[&H256::from(1)], H256::from(5));
+    assert_eq!(account.storage[&H256::from(2)], H256::from(7));
+
+    let incomplete = serde_json::from_str(r#"{ "request": { "method": "eth_getCode", "params": [] }, "response": {} }"#)
+        .unwrap();
+    assert!(source.add_recording(&incomplete).is_err());
+}
@@ -0,0 +1,531 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Property-based fuzzing of contract functions.
+//!
+//! ```ignore
+//! let function = contract.function("transfer").unwrap();
+//! Fuzzer::default().assert(&mut evm, token, function, |evm, tokens, output| {
+//!     // check the property, `Err(reason)` if it doesn't hold
+//!     Ok(())
+//! });
+//! ```
+//!
+//! Every run starts from a snapshot of the `Evm` taken before the first one.
+//! Arguments are generated from the function's parameter types, with integers
+//! biased towards edge values and addresses picked mostly from accounts which
+//! already exist. Failing arguments are shrunk and printed as Rust code.
+//! `SOLARIS_FUZZ_RUNS` and `SOLARIS_FUZZ_SEED` override the number of runs
+//! and the seed, which is printed on failure to replay it.
+
+use std::fmt;
+
+use ethabi::{self, ParamType, Token};
+use ethereum_types::{Address, U256};
+use rand::{self, Rng, SeedableRng, StdRng};
+use rustc_hex::ToHex;
+
+use abi;
+use error;
+use evm::{Evm, TransactionOutput};
+
+/// Longest generated `bytes` and `string`.
+pub(crate) const MAX_BYTES: usize = 64;
+/// Longest generated dynamic array.
+pub(crate) const MAX_ARRAY: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct Fuzzer {
+    runs: usize,
+    seed: u64,
+    max_shrinks: usize,
+    addresses: Option<Vec<Address>>,
+}
+
+impl Default for Fuzzer {
+    fn default() -> Self {
+        Fuzzer {
+            runs: runs_from_env(256),
+            seed: seed_from_env(),
+            max_shrinks: 1024,
+            addresses: None,
+        }
+    }
+}
+
+/// Failing arguments found by the fuzzer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    /// Name of the fuzzed function.
+    pub function: String,
+    /// Shrunk arguments.
+    pub tokens: Vec<Token>,
+    pub reason: String,
+    /// Seed to replay the run with.
+    pub seed: u64,
+    /// Number of the failing run.
+    pub run: usize,
+}
+
+impl Counterexample {
+    /// Rust code building the failing arguments.
+    pub fn reproducer(&self) -> String {
+        let tokens = self.tokens
+            .iter()
+            .map(|token| format!("    {},\n", to_rust(token)))
+            .collect::<String>();
+        format!("let tokens = vec![\n{}];", tokens)
+    }
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registry = abi::AbiRegistry::default();
+        let args = self.tokens
+            .iter()
+            .map(|token| registry.format_token(token))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            f,
+            "Property of {} failed on run {} (SOLARIS_FUZZ_SEED={}): {}",
+            self.function, self.run, self.seed, self.reason
+        )?;
+        writeln!(f, "Minimal failing input: {}({})", self.function, args)?;
+        write!(f, "Reproducer:\n{}", self.reproducer())
+    }
+}
+
+impl Fuzzer {
+    pub fn with_runs(&mut self, runs: usize) -> &mut Self {
+        self.runs = runs;
+        self
+    }
+
+    pub fn with_seed(&mut self, seed: u64) -> &mut Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the maximal number of runs spent on shrinking a failing input.
+    pub fn with_max_shrinks(&mut self, max_shrinks: usize) -> &mut Self {
+        self.max_shrinks = max_shrinks;
+        self
+    }
+
+    /// Sets the addresses picked for `address` parameters, all existing accounts by default.
+    pub fn with_addresses(&mut self, addresses: Vec<Address>) -> &mut Self {
+        self.addresses = Some(addresses);
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Calls `function` of `target` with generated arguments and checks `property` after every call.
+    ///
+    /// The property gets the arguments and the transaction output and returns
+    /// `Err(reason)` if it doesn't hold.
+    pub fn check<P>(
+        &self,
+        evm: &mut Evm,
+        target: Address,
+        function: &ethabi::Function,
+        mut property: P,
+    ) -> Result<(), Counterexample>
+    where
+        P: FnMut(&mut Evm, &[Token], &error::Result<TransactionOutput>) -> Result<(), String>,
+    {
+        let snapshot = evm.snapshot();
+        let addresses = self.addresses
+            .clone()
+            .unwrap_or_else(|| evm.dump_accounts().keys().cloned().collect());
+        let kinds: Vec<ParamType> = function.inputs.iter().map(|param| param.kind.clone()).collect();
+
+        let result = {
+            let mut execute = |tokens: &[Token]| -> Result<(), String> {
+                evm.revert_to(&snapshot);
+                let data = function.encode_input(tokens).map_err(|e| e.to_string())?;
+                let output = evm.transact_to(target, data);
+                property(evm, tokens, &output)
+            };
+            self.find_counterexample(&kinds, &addresses, &mut execute)
+        };
+        evm.revert_to(&snapshot);
+
+        result.map_err(|(run, tokens, reason)| Counterexample {
+            function: function.name.clone(),
+            tokens,
+            reason,
+            seed: self.seed,
+            run,
+        })
+    }
+
+    /// Like `check`, but panics with the counterexample and its reproducer.
+    pub fn assert<P>(&self, evm: &mut Evm, target: Address, function: &ethabi::Function, property: P)
+    where
+        P: FnMut(&mut Evm, &[Token], &error::Result<TransactionOutput>) -> Result<(), String>,
+    {
+        if let Err(counterexample) = self.check(evm, target, function, property) {
+            panic!("{}", counterexample);
+        }
+    }
+
+    /// Returns the failing run with shrunk arguments and the failure reason.
+    fn find_counterexample<F>(
+        &self,
+        kinds: &[ParamType],
+        addresses: &[Address],
+        execute: &mut F,
+    ) -> Result<(), (usize, Vec<Token>, String)>
+    where
+        F: FnMut(&[Token]) -> Result<(), String>,
+    {
+        let mut rng = rng(self.seed);
+        for run in 0..self.runs {
+            let tokens: Vec<Token> = kinds
+                .iter()
+                .map(|kind| generate(&mut rng, kind, addresses))
+                .collect();
+            if let Err(reason) = execute(&tokens) {
+                let (tokens, reason) = self.shrink(tokens, reason, execute);
+                return Err((run, tokens, reason));
+            }
+        }
+        Ok(())
+    }
+
+    fn shrink<F>(&self, mut tokens: Vec<Token>, mut reason: String, execute: &mut F) -> (Vec<Token>, String)
+    where
+        F: FnMut(&[Token]) -> Result<(), String>,
+    {
+        let mut budget = self.max_shrinks;
+        'progress: while budget > 0 {
+            for index in 0..tokens.len() {
+                for candidate in shrink(&tokens[index]) {
+                    if budget == 0 {
+                        break 'progress;
+                    }
+                    budget -= 1;
+
+                    let mut shrunk = tokens.clone();
+                    shrunk[index] = candidate;
+                    if let Err(shrunk_reason) = execute(&shrunk) {
+                        tokens = shrunk;
+                        reason = shrunk_reason;
+                        continue 'progress;
+                    }
+                }
+            }
+            break;
+        }
+        (tokens, reason)
+    }
+}
+
+/// `SOLARIS_FUZZ_RUNS`, or `default` if it's not set.
+pub(crate) fn runs_from_env(default: usize) -> usize {
+    ::std::env::var("SOLARIS_FUZZ_RUNS")
+        .ok()
+        .and_then(|runs| runs.parse().ok())
+        .unwrap_or(default)
+}
+
+/// `SOLARIS_FUZZ_SEED`, or a random seed if it's not set.
+pub(crate) fn seed_from_env() -> u64 {
+    ::std::env::var("SOLARIS_FUZZ_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_else(rand::random)
+}
+
+pub(crate) fn rng(seed: u64) -> StdRng {
+    let seed: &[usize] = &[seed as usize, (seed >> 32) as usize];
+    StdRng::from_seed(seed)
+}
+
+pub(crate) fn max_uint(bits: usize) -> U256 {
+    if bits >= 256 {
+        U256::max_value()
+    } else {
+        (U256::one() << bits) - U256::one()
+    }
+}
+
+fn negate(value: U256) -> U256 {
+    (!value).overflowing_add(U256::one()).0
+}
+
+fn is_negative(value: &U256) -> bool {
+    value.bit(255)
+}
+
+fn random_word<R: Rng>(rng: &mut R) -> U256 {
+    let mut bytes = [0u8; 32];
+    rng.fill_bytes(&mut bytes);
+    U256::from(&bytes[..])
+}
+
+fn uint<R: Rng>(rng: &mut R, bits: usize) -> U256 {
+    let max = max_uint(bits);
+    match rng.gen_range(0, 8) {
+        0 => U256::zero(),
+        1 => U256::one(),
+        2 => max,
+        3 => max - U256::one(),
+        4 => U256::one() << (bits - 1),
+        5 => rng.gen_range(0u64, 1000).into(),
+        6 => (random_word(rng) & max) >> rng.gen_range(0, bits),
+        _ => random_word(rng) & max,
+    }
+}
+
+fn int<R: Rng>(rng: &mut R, bits: usize) -> U256 {
+    let min = U256::one() << (bits - 1);
+    match rng.gen_range(0, 6) {
+        0 => U256::zero(),
+        1 => U256::one(),
+        2 => negate(U256::one()),
+        3 => min - U256::one(),
+        4 => negate(min),
+        _ => {
+            let value = uint(rng, bits - 1);
+            if rng.gen() {
+                negate(value)
+            } else {
+                value
+            }
+        }
+    }
+}
+
+fn random_bytes<R: Rng>(rng: &mut R, len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Generates a random value of given type.
+///
+/// Addresses are picked from `addresses` most of the time, if there are any.
+pub fn generate<R: Rng>(rng: &mut R, kind: &ParamType, addresses: &[Address]) -> Token {
+    match *kind {
+        ParamType::Address => {
+            if !addresses.is_empty() && !rng.gen_weighted_bool(4) {
+                Token::Address(*rng.choose(addresses).expect("addresses are not empty; qed"))
+            } else if rng.gen_weighted_bool(8) {
+                Token::Address(Address::zero())
+            } else {
+                Token::Address(Address::from_slice(&random_bytes(rng, 20)))
+            }
+        }
+        ParamType::Uint(bits) => Token::Uint(uint(rng, bits)),
+        ParamType::Int(bits) => Token::Int(int(rng, bits)),
+        ParamType::Bool => Token::Bool(rng.gen()),
+        ParamType::FixedBytes(len) => Token::FixedBytes(random_bytes(rng, len)),
+        ParamType::Bytes => {
+            let len = rng.gen_range(0, MAX_BYTES + 1);
+            Token::Bytes(random_bytes(rng, len))
+        }
+        ParamType::String => {
+            let len = rng.gen_range(0, MAX_BYTES + 1);
+            Token::String(rng.gen_ascii_chars().take(len).collect())
+        }
+        ParamType::Array(ref inner) => {
+            let len = rng.gen_range(0, MAX_ARRAY + 1);
+            Token::Array((0..len).map(|_| generate(rng, inner, addresses)).collect())
+        }
+        ParamType::FixedArray(ref inner, len) => {
+            Token::FixedArray((0..len).map(|_| generate(rng, inner, addresses)).collect())
+        }
+    }
+}
+
+/// Simpler values to try in place of a failing one, simplest first.
+pub fn shrink(token: &Token) -> Vec<Token> {
+    let mut out = Vec::new();
+    match *token {
+        Token::Uint(value) if !value.is_zero() => {
+            out.push(Token::Uint(U256::zero()));
+            out.push(Token::Uint(value >> 1));
+            out.push(Token::Uint(value - U256::one()));
+        }
+        Token::Int(value) if !value.is_zero() => {
+            out.push(Token::Int(U256::zero()));
+            if is_negative(&value) {
+                let magnitude = negate(value);
+                out.push(Token::Int(magnitude));
+                // zero is already a candidate, -1 halves and increments to it
+                let halved = magnitude >> 1;
+                if !halved.is_zero() {
+                    out.push(Token::Int(negate(halved)));
+                }
+                let (incremented, _) = value.overflowing_add(U256::one());
+                if !incremented.is_zero() {
+                    out.push(Token::Int(incremented));
+                }
+            } else {
+                out.push(Token::Int(value >> 1));
+                out.push(Token::Int(value - U256::one()));
+            }
+        }
+        Token::Bool(true) => out.push(Token::Bool(false)),
+        Token::FixedBytes(ref bytes) if bytes.iter().any(|b| *b != 0) => {
+            out.push(Token::FixedBytes(vec![0; bytes.len()]));
+        }
+        Token::Bytes(ref bytes) if !bytes.is_empty() => {
+            out.push(Token::Bytes(vec![]));
+            out.push(Token::Bytes(bytes[..bytes.len() / 2].to_vec()));
+            out.push(Token::Bytes(bytes[..bytes.len() - 1].to_vec()));
+        }
+        Token::String(ref text) if !text.is_empty() => {
+            let chars: Vec<char> = text.chars().collect();
+            out.push(Token::String(String::new()));
+            out.push(Token::String(chars[..chars.len() / 2].iter().collect()));
+            out.push(Token::String(chars[..chars.len() - 1].iter().collect()));
+        }
+        Token::Array(ref tokens) => {
+            if !tokens.is_empty() {
+                out.push(Token::Array(vec![]));
+            }
+            for index in 0..tokens.len() {
+                let mut removed = tokens.clone();
+                removed.remove(index);
+                out.push(Token::Array(removed));
+            }
+            out.extend(shrink_elements(tokens).into_iter().map(Token::Array));
+        }
+        Token::FixedArray(ref tokens) => {
+            out.extend(shrink_elements(tokens).into_iter().map(Token::FixedArray));
+        }
+        _ => {}
+    }
+    out.dedup();
+    out.retain(|candidate| candidate != token);
+    out
+}
+
+fn shrink_elements(tokens: &[Token]) -> Vec<Vec<Token>> {
+    let mut out = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        for candidate in shrink(token) {
+            let mut shrunk = tokens.to_vec();
+            shrunk[index] = candidate;
+            out.push(shrunk);
+        }
+    }
+    out
+}
+
+/// Rust expression building `token`.
+pub fn to_rust(token: &Token) -> String {
+    match *token {
+        Token::Address(ref address) => format!(
+            "ethabi::Token::Address(\"{}\".parse().unwrap())",
+            address.to_hex()
+        ),
+        Token::Uint(ref value) if *value <= U256::from(u64::max_value()) => {
+            format!("ethabi::Token::Uint({}u64.into())", value.low_u64())
+        }
+        Token::Uint(ref value) => format!("ethabi::Token::Uint(\"{:x}\".parse().unwrap())", value),
+        Token::Int(ref value) => format!("ethabi::Token::Int(\"{:x}\".parse().unwrap())", value),
+        Token::Bool(value) => format!("ethabi::Token::Bool({})", value),
+        Token::String(ref text) => format!("ethabi::Token::String({:?}.into())", text),
+        Token::Bytes(ref bytes) => format!(
+            "ethabi::Token::Bytes(\"{}\".from_hex().unwrap())",
+            bytes.to_hex()
+        ),
+        Token::FixedBytes(ref bytes) => format!(
+            "ethabi::Token::FixedBytes(\"{}\".from_hex().unwrap())",
+            bytes.to_hex()
+        ),
+        Token::Array(ref tokens) => format!("ethabi::Token::Array(vec![{}])", to_rust_list(tokens)),
+        Token::FixedArray(ref tokens) => {
+            format!("ethabi::Token::FixedArray(vec![{}])", to_rust_list(tokens))
+        }
+    }
+}
+
+fn to_rust_list(tokens: &[Token]) -> String {
+    tokens.iter().map(to_rust).collect::<Vec<_>>().join(", ")
+}
+
+#[test]
+fn test_generate_fits_type() {
+    let mut rng = rng(42);
+    for _ in 0..100 {
+        match generate(&mut rng, &ParamType::Uint(8), &[]) {
+            Token::Uint(value) => assert!(value <= 255.into()),
+            token => panic!("{:?} is not uint8", token),
+        }
+        match generate(&mut rng, &ParamType::Int(16), &[]) {
+            Token::Int(value) => {
+                let in_range = value <= 32767.into() || value >= negate(32768.into());
+                assert!(in_range, "{:?} is not int16", value);
+            }
+            token => panic!("{:?} is not int16", token),
+        }
+        match generate(&mut rng, &ParamType::Array(Box::new(ParamType::FixedBytes(4))), &[]) {
+            Token::Array(tokens) => {
+                assert!(tokens.len() <= MAX_ARRAY);
+                for token in tokens {
+                    match token {
+                        Token::FixedBytes(ref bytes) if bytes.len() == 4 => {}
+                        token => panic!("{:?} is not bytes4", token),
+                    }
+                }
+            }
+            token => panic!("{:?} is not bytes4[]", token),
+        }
+    }
+}
+
+#[test]
+fn test_shrink() {
+    assert_eq!(
+        shrink(&Token::Uint(10.into())),
+        vec![Token::Uint(0.into()), Token::Uint(5.into()), Token::Uint(9.into())]
+    );
+    assert_eq!(shrink(&Token::Uint(0.into())), vec![]);
+    assert_eq!(
+        shrink(&Token::Int(negate(4.into()))),
+        vec![
+            Token::Int(0.into()),
+            Token::Int(4.into()),
+            Token::Int(negate(2.into())),
+            Token::Int(negate(3.into())),
+        ]
+    );
+    assert_eq!(
+        shrink(&Token::Int(negate(1.into()))),
+        vec![Token::Int(0.into()), Token::Int(1.into())]
+    );
+    assert_eq!(
+        shrink(&Token::Array(vec![Token::Bool(true)])),
+        vec![Token::Array(vec![]), Token::Array(vec![Token::Bool(false)])]
+    );
+}
+
+#[test]
+fn test_to_rust() {
+    assert_eq!(to_rust(&Token::Uint(5.into())), "ethabi::Token::Uint(5u64.into())");
+    assert_eq!(
+        to_rust(&Token::Array(vec![Token::Bool(true), Token::String("a".into())])),
+        "ethabi::Token::Array(vec![ethabi::Token::Bool(true), ethabi::Token::String(\"a\".into())])"
+    );
+}
@@ -0,0 +1,305 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Stateful invariant testing.
+//!
+//! Every run starts from a snapshot and sends a random sequence of
+//! transactions from the actors to non-constant functions of the targets,
+//! with arguments generated as in `fuzz`. The invariant is checked before
+//! the first and after every transaction; reverted transactions are fine.
+//! A failing sequence is shrunk by dropping transactions and simplifying
+//! their arguments. Runs are deterministic for a given seed.
+//!
+//! ```ignore
+//! let mut tester = InvariantTester::default();
+//! tester.with_actors(vec![alice, bob]).with_target(token, token_abi);
+//! tester.assert(&mut evm, |evm| {
+//!     // `Err(reason)` if the invariant doesn't hold
+//!     Ok(())
+//! });
+//! ```
+
+use std::fmt;
+
+use ethabi::{self, Token};
+use ethereum_types::Address;
+use rand::Rng;
+
+use abi;
+use evm::{Evm, Snapshot};
+use fuzz;
+
+/// A single transaction of a sequence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub sender: Address,
+    pub target: Address,
+    /// Index of the target.
+    target_index: usize,
+    pub function: String,
+    pub tokens: Vec<Token>,
+}
+
+/// Sequence of transactions breaking an invariant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvariantFailure {
+    /// Shrunk sequence, the invariant fails after the last step.
+    pub steps: Vec<Step>,
+    /// Decoded calls of the steps.
+    pub calls: Vec<String>,
+    pub reason: String,
+    /// Seed to replay the runs with.
+    pub seed: u64,
+    /// Number of the failing run.
+    pub run: usize,
+}
+
+impl fmt::Display for InvariantFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Invariant failed on run {} (SOLARIS_FUZZ_SEED={}): {}",
+            self.run, self.seed, self.reason
+        )?;
+        if self.steps.is_empty() {
+            return write!(f, "The invariant doesn't hold in the initial state");
+        }
+        write!(f, "Call sequence:")?;
+        for (index, (step, call)) in self.steps.iter().zip(&self.calls).enumerate() {
+            write!(f, "\n  {}. from {:?}: {}", index + 1, step.sender, call)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InvariantTester {
+    runs: usize,
+    depth: usize,
+    seed: u64,
+    max_shrinks: usize,
+    actors: Vec<Address>,
+    targets: Vec<(Address, ethabi::Contract)>,
+}
+
+impl Default for InvariantTester {
+    fn default() -> Self {
+        InvariantTester {
+            runs: fuzz::runs_from_env(64),
+            depth: 16,
+            seed: fuzz::seed_from_env(),
+            max_shrinks: 1024,
+            actors: vec![],
+            targets: vec![],
+        }
+    }
+}
+
+impl InvariantTester {
+    pub fn with_runs(&mut self, runs: usize) -> &mut Self {
+        self.runs = runs;
+        self
+    }
+
+    /// Sets the number of transactions of every run.
+    pub fn with_depth(&mut self, depth: usize) -> &mut Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_seed(&mut self, seed: u64) -> &mut Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the maximal number of replays spent on shrinking a failing sequence.
+    pub fn with_max_shrinks(&mut self, max_shrinks: usize) -> &mut Self {
+        self.max_shrinks = max_shrinks;
+        self
+    }
+
+    /// Sets the senders of the transactions, the current sender of the `Evm` by default.
+    pub fn with_actors(&mut self, actors: Vec<Address>) -> &mut Self {
+        self.actors = actors;
+        self
+    }
+
+    /// Adds a contract whose non-constant functions are called.
+    pub fn with_target(&mut self, address: Address, abi: ethabi::Contract) -> &mut Self {
+        self.targets.push((address, abi));
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Runs random sequences of transactions, checking `invariant` after every one.
+    pub fn check<F>(&self, evm: &mut Evm, mut invariant: F) -> Result<(), InvariantFailure>
+    where
+        F: FnMut(&mut Evm) -> Result<(), String>,
+    {
+        assert!(!self.targets.is_empty(), "No targets to call, see `with_target`.");
+
+        let sender = evm.sender();
+        let actors = if self.actors.is_empty() {
+            vec![sender]
+        } else {
+            self.actors.clone()
+        };
+        let mut addresses: Vec<Address> = evm.dump_accounts().keys().cloned().collect();
+        addresses.extend(actors.iter().cloned());
+
+        let snapshot = evm.snapshot();
+        let mut rng = fuzz::rng(self.seed);
+        let mut result = Ok(());
+        for run in 0..self.runs {
+            let steps: Vec<Step> = (0..self.depth)
+                .filter_map(|_| self.generate(&mut rng, &actors, &addresses))
+                .collect();
+            if let Some((len, reason)) = self.replay(evm, &snapshot, &steps, &mut invariant) {
+                let mut steps = steps;
+                steps.truncate(len);
+                let (steps, reason) = self.shrink(evm, &snapshot, steps, reason, &mut invariant);
+                let calls = steps
+                    .iter()
+                    .map(|step| evm.abi().format_call(&step.target, &self.encode(step)))
+                    .collect();
+                result = Err(InvariantFailure {
+                    steps,
+                    calls,
+                    reason,
+                    seed: self.seed,
+                    run,
+                });
+                break;
+            }
+        }
+
+        evm.revert_to(&snapshot);
+        evm.with_sender(sender);
+        result
+    }
+
+    /// Like `check`, but panics with the failing sequence.
+    pub fn assert<F>(&self, evm: &mut Evm, invariant: F)
+    where
+        F: FnMut(&mut Evm) -> Result<(), String>,
+    {
+        if let Err(failure) = self.check(evm, invariant) {
+            panic!("{}", failure);
+        }
+    }
+
+    fn generate<R: Rng>(&self, rng: &mut R, actors: &[Address], addresses: &[Address]) -> Option<Step> {
+        let target_index = rng.gen_range(0, self.targets.len());
+        let (target, ref abi) = self.targets[target_index];
+        let functions: Vec<&ethabi::Function> = abi::sorted_functions(abi)
+            .into_iter()
+            .filter(|function| !function.constant)
+            .collect();
+        let function = rng.choose(&functions)?;
+
+        Some(Step {
+            sender: *rng.choose(actors).expect("There is at least one actor; qed"),
+            target,
+            target_index,
+            function: function.name.clone(),
+            tokens: function
+                .inputs
+                .iter()
+                .map(|param| fuzz::generate(rng, &param.kind, addresses))
+                .collect(),
+        })
+    }
+
+    fn encode(&self, step: &Step) -> Vec<u8> {
+        self.targets[step.target_index]
+            .1
+            .function(&step.function)
+            .and_then(|function| function.encode_input(&step.tokens))
+            .expect("Steps are generated from the target ABI; qed")
+    }
+
+    /// Replays `steps` from `snapshot`, returns the number of steps after
+    /// which the invariant failed and the reason.
+    fn replay<F>(&self, evm: &mut Evm, snapshot: &Snapshot, steps: &[Step], invariant: &mut F) -> Option<(usize, String)>
+    where
+        F: FnMut(&mut Evm) -> Result<(), String>,
+    {
+        evm.revert_to(snapshot);
+        if let Err(reason) = invariant(evm) {
+            return Some((0, reason));
+        }
+        for (index, step) in steps.iter().enumerate() {
+            // reverted transactions are part of the sequence too
+            let _ = evm.with_sender(step.sender)
+                .transact_to(step.target, self.encode(step));
+            if let Err(reason) = invariant(evm) {
+                return Some((index + 1, reason));
+            }
+        }
+        None
+    }
+
+    fn shrink<F>(
+        &self,
+        evm: &mut Evm,
+        snapshot: &Snapshot,
+        mut steps: Vec<Step>,
+        mut reason: String,
+        invariant: &mut F,
+    ) -> (Vec<Step>, String)
+    where
+        F: FnMut(&mut Evm) -> Result<(), String>,
+    {
+        let mut budget = self.max_shrinks;
+        'progress: while budget > 0 {
+            // dropping steps first, then simplifying arguments
+            let mut candidates = Vec::new();
+            for index in 0..steps.len() {
+                let mut dropped = steps.clone();
+                dropped.remove(index);
+                candidates.push(dropped);
+            }
+            for (index, step) in steps.iter().enumerate() {
+                for (param, token) in step.tokens.iter().enumerate() {
+                    for shrunk in fuzz::shrink(token) {
+                        let mut candidate = steps.clone();
+                        candidate[index].tokens[param] = shrunk;
+                        candidates.push(candidate);
+                    }
+                }
+            }
+
+            for mut candidate in candidates {
+                if budget == 0 {
+                    break 'progress;
+                }
+                budget -= 1;
+
+                if let Some((len, candidate_reason)) = self.replay(evm, snapshot, &candidate, invariant) {
+                    candidate.truncate(len);
+                    steps = candidate;
+                    reason = candidate_reason;
+                    continue 'progress;
+                }
+            }
+            break;
+        }
+        (steps, reason)
+    }
+}
@@ -19,7 +19,14 @@ extern crate ethcore;
 extern crate ethcore_bytes;
 extern crate ethcore_transaction;
 extern crate ethereum_types;
+extern crate ethjson;
+extern crate ethkey;
 extern crate evm as ethcore_evm;
+extern crate keccak_hash;
+extern crate rand;
+extern crate rustc_hex;
+extern crate serde;
+extern crate serde_json;
 extern crate vm;
 
 #[macro_use]
@@ -28,15 +35,46 @@ extern crate error_chain;
 #[macro_use]
 extern crate lazy_static;
 
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate serde_derive;
+
+#[cfg(feature = "proptest")]
+#[macro_use]
+extern crate proptest;
+
 /// re-export these for now since they provide useful conversion from
 /// integer primitives and to byte arrays (which are required by the ethabi for now)
 pub use ethereum_types::{Address, U256};
 
-mod trace;
-
+pub mod abi;
+pub mod accounts;
+pub mod address;
+pub mod backend;
+pub mod balances;
+pub mod chain;
+pub mod cheatcodes;
+pub mod console;
 pub mod convert;
+pub mod differential;
 pub mod error;
 pub mod evm;
+pub mod executive;
+pub mod fork;
+pub mod fuzz;
+pub mod invariant;
+pub mod mock;
+pub mod profile;
+pub mod render;
+pub mod runner;
+pub mod signatures;
+pub mod signing;
+pub mod state;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+pub mod trace;
 pub mod wei;
 
 lazy_static! {
@@ -0,0 +1,425 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Mock contracts with programmable responses.
+//!
+//! ```ignore
+//! evm.mock(oracle)
+//!     .on(oracle_contract::functions().price("ETH".into()))
+//!     .returns(&[Token::Uint(100.into())])
+//!     .on_selector(oracle_contract::functions().update(0))
+//!     .reverts("Not allowed");
+//! ```
+//!
+//! Responses are compiled into a dispatcher deployed at the mocked address,
+//! so the mock is called like any other contract. Expectations added later
+//! take precedence, and calls matching none of them revert without data.
+
+use ethabi::{self, ContractFunction, Token};
+use ethereum_types::{Address, U256};
+
+use evm::Evm;
+use trace::{CallKind, CallTrace};
+
+/// Selector of `Error(string)`, the revert reason emitted by `revert("...")`.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// Which calls an expectation applies to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Matcher {
+    /// Calls with the same 4-byte selector, regardless of arguments.
+    Selector([u8; 4]),
+    /// Calls with exactly the same calldata.
+    Calldata(Vec<u8>),
+}
+
+impl Matcher {
+    pub fn matches(&self, input: &[u8]) -> bool {
+        match *self {
+            Matcher::Selector(ref selector) => input.len() >= 4 && input[..4] == selector[..],
+            Matcher::Calldata(ref data) => input == &data[..],
+        }
+    }
+}
+
+/// What the mock responds with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    Return(Vec<u8>),
+    Revert(Vec<u8>),
+}
+
+/// Call received by a mock.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockCall {
+    pub from: Address,
+    pub value: U256,
+    pub input: Vec<u8>,
+}
+
+impl MockCall {
+    /// Whether the call was made with the same calldata as `f`.
+    pub fn matches<F: ContractFunction>(&self, f: &F) -> bool {
+        self.input == f.encoded()
+    }
+}
+
+/// Expectations and recorded calls of a single mock.
+#[derive(Debug, Default, Clone)]
+pub struct Mock {
+    rules: Vec<(Matcher, Response)>,
+    calls: Vec<MockCall>,
+}
+
+impl Mock {
+    pub(crate) fn add_rule(&mut self, matcher: Matcher, response: Response) {
+        self.rules.push((matcher, response));
+    }
+
+    /// Records calls made to `address` by given call frames.
+    pub(crate) fn record(&mut self, address: &Address, traces: &[CallTrace]) {
+        for trace in traces {
+            match trace.kind {
+                CallKind::Create | CallKind::SelfDestruct => {}
+                _ if trace.to == *address => self.calls.push(MockCall {
+                    from: trace.from,
+                    value: trace.value,
+                    input: trace.input.clone(),
+                }),
+                _ => {}
+            }
+            self.record(address, &trace.subtraces);
+        }
+    }
+
+    /// Calls received so far, in order.
+    pub fn calls(&self) -> &[MockCall] {
+        &self.calls
+    }
+
+    /// Returns the response to `input`, as the deployed code would.
+    pub fn respond(&self, input: &[u8]) -> Response {
+        self.rules
+            .iter()
+            .rev()
+            .find(|&&(ref matcher, _)| matcher.matches(input))
+            .map(|&(_, ref response)| response.clone())
+            .unwrap_or_else(|| Response::Revert(vec![]))
+    }
+
+    /// Compiles the expectations into contract code.
+    pub fn code(&self) -> Vec<u8> {
+        let mut asm = Assembler::default();
+        for &(ref matcher, ref response) in self.rules.iter().rev() {
+            let next = asm.new_label();
+            match *matcher {
+                Matcher::Selector(ref selector) => {
+                    // CALLDATASIZE < 4
+                    asm.push1(4);
+                    asm.op(CALLDATASIZE);
+                    asm.op(LT);
+                    asm.jumpi(next);
+                    // CALLDATALOAD(0) / 2^224 != selector
+                    let mut shift = [0u8; 29];
+                    shift[0] = 1;
+                    asm.op(PUSH29);
+                    asm.bytes(&shift);
+                    asm.push1(0);
+                    asm.op(CALLDATALOAD);
+                    asm.op(DIV);
+                    asm.op(PUSH4);
+                    asm.bytes(selector);
+                    asm.op(EQ);
+                    asm.op(ISZERO);
+                    asm.jumpi(next);
+                }
+                Matcher::Calldata(ref data) => {
+                    asm.push2(data.len());
+                    asm.op(CALLDATASIZE);
+                    asm.op(EQ);
+                    asm.op(ISZERO);
+                    asm.jumpi(next);
+                    // `CALLDATALOAD` pads with zeros, and so does the last expected word.
+                    for (index, chunk) in data.chunks(32).enumerate() {
+                        let mut word = [0u8; 32];
+                        word[..chunk.len()].copy_from_slice(chunk);
+                        asm.op(PUSH32);
+                        asm.bytes(&word);
+                        asm.push2(index * 32);
+                        asm.op(CALLDATALOAD);
+                        asm.op(EQ);
+                        asm.op(ISZERO);
+                        asm.jumpi(next);
+                    }
+                }
+            }
+
+            let (data, exit) = match *response {
+                Response::Return(ref data) => (data, RETURN),
+                Response::Revert(ref data) => (data, REVERT),
+            };
+            // CODECOPY(0, data, len), then RETURN(0, len) or REVERT(0, len)
+            asm.push2(data.len());
+            asm.push_data(data.clone());
+            asm.push1(0);
+            asm.op(CODECOPY);
+            asm.push2(data.len());
+            asm.push1(0);
+            asm.op(exit);
+            asm.set_label(next);
+        }
+
+        asm.push1(0);
+        asm.op(DUP1);
+        asm.op(REVERT);
+        asm.assemble()
+    }
+}
+
+/// Adds expectations to the mock at a single address, see `Evm::mock`.
+pub struct MockBuilder<'a> {
+    evm: &'a mut Evm,
+    address: Address,
+}
+
+impl<'a> MockBuilder<'a> {
+    pub(crate) fn new(evm: &'a mut Evm, address: Address) -> Self {
+        MockBuilder { evm, address }
+    }
+
+    /// Address of the mock.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Expects calls with exactly the calldata of `f`.
+    pub fn on<F: ContractFunction>(self, f: F) -> Expectation<'a> {
+        self.on_calldata(f.encoded())
+    }
+
+    /// Expects calls of the function of `f`, with any arguments.
+    pub fn on_selector<F: ContractFunction>(self, f: F) -> Expectation<'a> {
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&f.encoded()[..4]);
+        Expectation {
+            builder: self,
+            matcher: Matcher::Selector(selector),
+        }
+    }
+
+    /// Expects calls with exactly given calldata.
+    pub fn on_calldata(self, data: Vec<u8>) -> Expectation<'a> {
+        Expectation {
+            builder: self,
+            matcher: Matcher::Calldata(data),
+        }
+    }
+}
+
+/// A call expectation waiting for its response.
+pub struct Expectation<'a> {
+    builder: MockBuilder<'a>,
+    matcher: Matcher,
+}
+
+impl<'a> Expectation<'a> {
+    /// Returns ABI-encoded `tokens`.
+    pub fn returns(self, tokens: &[Token]) -> MockBuilder<'a> {
+        self.respond(Response::Return(ethabi::encode(tokens)))
+    }
+
+    /// Returns `data` as is.
+    pub fn returns_raw(self, data: Vec<u8>) -> MockBuilder<'a> {
+        self.respond(Response::Return(data))
+    }
+
+    /// Reverts with `reason`, as `revert(reason)` in Solidity.
+    pub fn reverts(self, reason: &str) -> MockBuilder<'a> {
+        self.respond(Response::Revert(revert_reason(reason)))
+    }
+
+    /// Reverts with `data` as is.
+    pub fn reverts_raw(self, data: Vec<u8>) -> MockBuilder<'a> {
+        self.respond(Response::Revert(data))
+    }
+
+    fn respond(self, response: Response) -> MockBuilder<'a> {
+        let builder = self.builder;
+        builder.evm.add_mock_rule(builder.address, self.matcher, response);
+        builder
+    }
+}
+
+/// Encodes `reason` as `Error(string)`.
+pub fn revert_reason(reason: &str) -> Vec<u8> {
+    let mut data = ERROR_SELECTOR.to_vec();
+    data.extend(ethabi::encode(&[Token::String(reason.into())]));
+    data
+}
+
+const DIV: u8 = 0x04;
+const LT: u8 = 0x10;
+const EQ: u8 = 0x14;
+const ISZERO: u8 = 0x15;
+const CALLDATALOAD: u8 = 0x35;
+const CALLDATASIZE: u8 = 0x36;
+const CODECOPY: u8 = 0x39;
+const JUMPI: u8 = 0x57;
+const JUMPDEST: u8 = 0x5b;
+const PUSH1: u8 = 0x60;
+const PUSH2: u8 = 0x61;
+const PUSH4: u8 = 0x63;
+const PUSH29: u8 = 0x7c;
+const PUSH32: u8 = 0x7f;
+const DUP1: u8 = 0x80;
+const RETURN: u8 = 0xf3;
+const REVERT: u8 = 0xfd;
+
+/// Two-byte immediate to be filled in once the code is laid out.
+enum Fixup {
+    Label(usize),
+    Data(usize),
+}
+
+/// Minimal assembler with forward jumps and data appended after the code.
+#[derive(Default)]
+struct Assembler {
+    code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    data: Vec<Vec<u8>>,
+    fixups: Vec<(usize, Fixup)>,
+}
+
+impl Assembler {
+    fn op(&mut self, op: u8) {
+        self.code.push(op);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn push1(&mut self, value: u8) {
+        self.code.extend_from_slice(&[PUSH1, value]);
+    }
+
+    fn push2(&mut self, value: usize) {
+        assert!(value <= 0xffff, "Mock calldata and responses must be shorter than 64kB");
+        self.code.extend_from_slice(&[PUSH2, (value >> 8) as u8, value as u8]);
+    }
+
+    fn push_fixup(&mut self, fixup: Fixup) {
+        self.code.push(PUSH2);
+        self.fixups.push((self.code.len(), fixup));
+        self.code.extend_from_slice(&[0, 0]);
+    }
+
+    fn push_data(&mut self, data: Vec<u8>) {
+        self.data.push(data);
+        let index = self.data.len() - 1;
+        self.push_fixup(Fixup::Data(index));
+    }
+
+    fn new_label(&mut self) -> usize {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn set_label(&mut self, label: usize) {
+        self.labels[label] = Some(self.code.len());
+        self.code.push(JUMPDEST);
+    }
+
+    fn jumpi(&mut self, label: usize) {
+        self.push_fixup(Fixup::Label(label));
+        self.code.push(JUMPI);
+    }
+
+    fn assemble(mut self) -> Vec<u8> {
+        let mut offsets = Vec::with_capacity(self.data.len());
+        let mut offset = self.code.len();
+        for data in &self.data {
+            offsets.push(offset);
+            offset += data.len();
+        }
+
+        for &(position, ref fixup) in &self.fixups {
+            let value = match *fixup {
+                Fixup::Label(label) => self.labels[label].expect("All labels are set; qed"),
+                Fixup::Data(index) => offsets[index],
+            };
+            assert!(value <= 0xffff, "Mock code must be shorter than 64kB");
+            self.code[position] = (value >> 8) as u8;
+            self.code[position + 1] = value as u8;
+        }
+
+        for data in self.data {
+            self.code.extend(data);
+        }
+        self.code
+    }
+}
+
+#[cfg(test)]
+fn test_mock() -> Mock {
+    let mut mock = Mock::default();
+    mock.add_rule(
+        Matcher::Selector([1, 2, 3, 4]),
+        Response::Return(vec![0xaa; 32]),
+    );
+    mock.add_rule(
+        Matcher::Calldata(vec![1, 2, 3, 4, 5]),
+        Response::Revert(revert_reason("Mocked")),
+    );
+    mock
+}
+
+#[test]
+fn test_respond() {
+    let mock = test_mock();
+    assert_eq!(mock.respond(&[1, 2, 3, 4, 6]), Response::Return(vec![0xaa; 32]));
+    assert_eq!(mock.respond(&[1, 2, 3, 4, 5]), Response::Revert(revert_reason("Mocked")));
+    assert_eq!(mock.respond(&[1, 2, 3]), Response::Revert(vec![]));
+}
+
+#[test]
+fn test_code_layout() {
+    let code = test_mock().code();
+
+    // every `PUSH2 x JUMPI` must target a `JUMPDEST`
+    let mut jumps = 0;
+    let mut pc = 0;
+    while pc < code.len() {
+        let op = code[pc];
+        if op == PUSH2 && code.get(pc + 3) == Some(&JUMPI) {
+            let target = ((code[pc + 1] as usize) << 8) | code[pc + 2] as usize;
+            assert_eq!(code[target], JUMPDEST);
+            jumps += 1;
+        }
+        if op == REVERT && pc > 0 && code[pc - 1] == DUP1 {
+            // fallback revert, followed only by the response data
+            let mut data = revert_reason("Mocked");
+            data.extend_from_slice(&[0xaa; 32]);
+            assert_eq!(&code[pc + 1..], &data[..]);
+            break;
+        }
+        pc += match op {
+            PUSH1...PUSH32 => (op - PUSH1) as usize + 2,
+            _ => 1,
+        };
+    }
+    assert_eq!(jumps, 4);
+}
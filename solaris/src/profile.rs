@@ -0,0 +1,288 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Opcode level gas profiling.
+//!
+//! Gas is attributed to call frames, to the function selected in each frame and
+//! to the individual opcodes. The collected profile can be exported as folded
+//! stacks (the input format of `flamegraph.pl` and `inferno`) or as a Chrome
+//! trace-event JSON timeline, where one unit of gas is one microsecond.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use ethereum_types::U256;
+
+/// Number of executions and total gas of a single opcode.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeGas {
+    pub count: u64,
+    pub gas: u64,
+}
+
+/// Gas profile of a single call frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameProfile {
+    /// Frame label, `address::function`.
+    pub label: String,
+    /// Gas the parent frame had used when this frame was entered.
+    pub offset: u64,
+    /// Gas used by the frame itself, sub-calls excluded.
+    pub self_gas: u64,
+    /// Gas used by the frame itself, grouped by opcode.
+    pub opcodes: BTreeMap<&'static str, OpcodeGas>,
+    /// Frames entered from this one.
+    pub children: Vec<FrameProfile>,
+}
+
+impl FrameProfile {
+    pub fn new(label: String, offset: u64) -> Self {
+        FrameProfile {
+            label,
+            offset,
+            self_gas: 0,
+            opcodes: BTreeMap::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Gas used by the frame including all sub-calls.
+    pub fn total_gas(&self) -> u64 {
+        self.self_gas + self.children.iter().map(FrameProfile::total_gas).sum::<u64>()
+    }
+
+    fn fold(&self, prefix: &str, out: &mut BTreeMap<String, u64>) {
+        let path = if prefix.is_empty() {
+            self.label.clone()
+        } else {
+            format!("{};{}", prefix, self.label)
+        };
+
+        for (name, opcode) in &self.opcodes {
+            if opcode.gas > 0 {
+                *out.entry(format!("{};{}", path, name)).or_insert(0) += opcode.gas;
+            }
+        }
+
+        for child in &self.children {
+            child.fold(&path, out);
+        }
+    }
+
+    fn chrome_events(&self, start: u64, tid: usize, out: &mut Vec<String>) {
+        out.push(format!(
+            r#"{{"name":"{name}","cat":"call","ph":"X","ts":{ts},"dur":{dur},"pid":1,"tid":{tid},"args":{{"self_gas":{self_gas}}}}}"#,
+            name = json_escape(&self.label),
+            ts = start,
+            dur = self.total_gas(),
+            tid = tid,
+            self_gas = self.self_gas,
+        ));
+
+        for child in &self.children {
+            child.chrome_events(start + child.offset, tid, out);
+        }
+    }
+}
+
+/// Gas profile of all profiled transactions and calls, in execution order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GasProfile {
+    pub frames: Vec<FrameProfile>,
+}
+
+impl GasProfile {
+    /// Appends frames of another profile.
+    pub fn extend(&mut self, other: GasProfile) {
+        self.frames.extend(other.frames);
+    }
+
+    /// Total gas used by each opcode.
+    pub fn opcodes(&self) -> BTreeMap<&'static str, OpcodeGas> {
+        fn collect(frame: &FrameProfile, out: &mut BTreeMap<&'static str, OpcodeGas>) {
+            for (name, opcode) in &frame.opcodes {
+                let entry = out.entry(*name).or_insert_with(Default::default);
+                entry.count += opcode.count;
+                entry.gas += opcode.gas;
+            }
+            for child in &frame.children {
+                collect(child, out);
+            }
+        }
+
+        let mut out = BTreeMap::new();
+        for frame in &self.frames {
+            collect(frame, &mut out);
+        }
+        out
+    }
+
+    /// Total gas used by each frame label (sub-calls excluded).
+    pub fn functions(&self) -> BTreeMap<String, u64> {
+        fn collect(frame: &FrameProfile, out: &mut BTreeMap<String, u64>) {
+            *out.entry(frame.label.clone()).or_insert(0) += frame.self_gas;
+            for child in &frame.children {
+                collect(child, out);
+            }
+        }
+
+        let mut out = BTreeMap::new();
+        for frame in &self.frames {
+            collect(frame, &mut out);
+        }
+        out
+    }
+
+    /// Returns the profile as folded stacks, one `frame;frame;OPCODE gas` line per stack.
+    pub fn folded(&self) -> String {
+        let mut stacks = BTreeMap::new();
+        for frame in &self.frames {
+            frame.fold("", &mut stacks);
+        }
+
+        stacks
+            .into_iter()
+            .map(|(stack, gas)| format!("{} {}\n", stack, gas))
+            .collect()
+    }
+
+    /// Returns the call tree as a Chrome trace-event JSON document.
+    ///
+    /// Every profiled transaction is a separate thread of the timeline.
+    pub fn chrome_trace(&self) -> String {
+        let mut events = Vec::new();
+        for (tid, frame) in self.frames.iter().enumerate() {
+            frame.chrome_events(0, tid, &mut events);
+        }
+        format!("[{}]", events.join(",\n"))
+    }
+
+    pub fn write_folded<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.folded().as_bytes())
+    }
+
+    pub fn write_chrome_trace<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.chrome_trace().as_bytes())
+    }
+}
+
+/// Collects the gas profile of a single call frame while it executes.
+#[derive(Debug)]
+pub struct Profiler {
+    frame: FrameProfile,
+    instruction: &'static str,
+    gas_before: u64,
+    sub_gas: u64,
+}
+
+impl Profiler {
+    pub fn new(label: String, offset: u64) -> Self {
+        Profiler {
+            frame: FrameProfile::new(label, offset),
+            instruction: "",
+            gas_before: 0,
+            sub_gas: 0,
+        }
+    }
+
+    /// Profiler for a frame entered from the currently executed instruction.
+    pub fn subprofiler(&self, label: String) -> Self {
+        Profiler::new(label, self.frame.total_gas())
+    }
+
+    pub fn next_instruction(&mut self, instruction: u8, current_gas: U256) {
+        self.instruction = ::ethcore_evm::INSTRUCTIONS[instruction as usize].name;
+        self.gas_before = current_gas.low_u64();
+    }
+
+    pub fn executed(&mut self, gas_left: U256) {
+        // the cost of CALL and CREATE includes the gas used by the sub-frame,
+        // which is already accounted for in the child profile.
+        let cost = self.gas_before
+            .saturating_sub(gas_left.low_u64())
+            .saturating_sub(self.sub_gas);
+        self.sub_gas = 0;
+
+        self.frame.self_gas += cost;
+        let opcode = self.frame
+            .opcodes
+            .entry(self.instruction)
+            .or_insert_with(Default::default);
+        opcode.count += 1;
+        opcode.gas += cost;
+    }
+
+    pub fn done_subprofiler(&mut self, sub: Profiler) {
+        let frame = sub.into_frame();
+        self.sub_gas += frame.total_gas();
+        self.frame.children.push(frame);
+    }
+
+    pub fn into_frame(self) -> FrameProfile {
+        self.frame
+    }
+}
+
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+fn test_profile() -> GasProfile {
+    let mut child = FrameProfile::new("B::g".into(), 30);
+    child.self_gas = 5;
+    child.opcodes.insert("SLOAD", OpcodeGas { count: 1, gas: 5 });
+
+    let mut root = FrameProfile::new("A::f".into(), 0);
+    root.self_gas = 40;
+    root.opcodes.insert("PUSH1", OpcodeGas { count: 3, gas: 9 });
+    root.opcodes.insert("CALL", OpcodeGas { count: 1, gas: 31 });
+    root.children.push(child);
+
+    GasProfile { frames: vec![root] }
+}
+
+#[test]
+fn test_folded() {
+    assert_eq!(
+        test_profile().folded(),
+        "A::f;B::g;SLOAD 5\nA::f;CALL 31\nA::f;PUSH1 9\n"
+    );
+}
+
+#[test]
+fn test_totals() {
+    let profile = test_profile();
+    assert_eq!(profile.frames[0].total_gas(), 45);
+    assert_eq!(profile.opcodes()["PUSH1"], OpcodeGas { count: 3, gas: 9 });
+    assert_eq!(profile.functions()["B::g"], 5);
+}
+
+#[test]
+fn test_chrome_trace() {
+    let trace = test_profile().chrome_trace();
+    assert!(trace.starts_with(r#"[{"name":"A::f","cat":"call","ph":"X","ts":0,"dur":45,"#));
+    assert!(trace.contains(r#"{"name":"B::g","cat":"call","ph":"X","ts":30,"dur":5,"#));
+}
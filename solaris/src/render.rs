@@ -0,0 +1,231 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tree rendering of collected call traces.
+//!
+//! ```text
+//! [31520] Token(0x..)::transfer(to=alice, amount=1e18)
+//! ├─ emit Transfer(from=0x.., to=alice, value=1e18)
+//! └─ ← true
+//! ```
+
+use ethcore::log_entry::LogEntry;
+
+use abi::{self, AbiRegistry};
+use console;
+use trace::{CallKind, CallTrace};
+
+const RESET: &str = "\x1b[0m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+const DIM: &str = "\x1b[2m";
+
+/// Whether rendered traces should be colourised, `false` if `NO_COLOR` is set.
+pub fn color_from_env() -> bool {
+    ::std::env::var("NO_COLOR").is_err()
+}
+
+struct Renderer<'a> {
+    abi: &'a AbiRegistry,
+    color: bool,
+    /// Logs assigned to frames, indexed by pre-order position of the frame.
+    logs: Vec<Vec<&'a LogEntry>>,
+    next_frame: usize,
+    out: String,
+}
+
+impl<'a> Renderer<'a> {
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.into()
+        }
+    }
+
+    fn frame(&mut self, trace: &CallTrace, prefix: &str, first: &str, rest: &str) {
+        let index = self.next_frame;
+        self.next_frame += 1;
+
+        if trace.kind == CallKind::SelfDestruct {
+            let line = format!(
+                "selfdestruct {} → {} {{value: {}}}",
+                self.abi.format_address(&trace.from),
+                self.abi.format_address(&trace.to),
+                abi::format_uint(&trace.value)
+            );
+            self.out.push_str(&format!("{}{}{}\n", prefix, first, self.paint(RED, &line)));
+            return;
+        }
+
+        let gas = match trace.gas_used {
+            Some(ref gas) => format!("[{}]", gas),
+            None => "[-]".into(),
+        };
+        let status = if trace.is_success() { GREEN } else { RED };
+        let mut header = format!(
+            "{}{}{} {}",
+            prefix,
+            first,
+            self.paint(DIM, &gas),
+            self.paint(status, &self.describe(trace))
+        );
+        if !trace.value.is_zero() {
+            let value = format!("{{value: {}}}", abi::format_uint(&trace.value));
+            header = format!("{} {}", header, self.paint(CYAN, &value));
+        }
+        self.out.push_str(&header);
+        self.out.push('\n');
+
+        let child_prefix = format!("{}{}", prefix, rest);
+        for sub in &trace.subtraces {
+            self.frame(sub, &child_prefix, "├─ ", "│  ");
+        }
+
+        let logs = ::std::mem::replace(&mut self.logs[index], Vec::new());
+        for log in logs {
+            let event = format!("emit {}", self.abi.format_log(log));
+            let line = format!("{}├─ {}", child_prefix, self.paint(YELLOW, &event));
+            self.out.push_str(&line);
+            self.out.push('\n');
+        }
+
+        let result = match trace.error {
+            Some(ref error) => self.paint(RED, &format!("✗ {}", error)),
+            None if trace.kind == CallKind::Create => {
+                format!("← {} bytes of code", trace.output.len())
+            }
+            None => format!(
+                "← {}",
+                self.abi.format_output(&trace.to, &trace.input, &trace.output)
+            ),
+        };
+        self.out.push_str(&format!("{}└─ {}\n", child_prefix, result));
+    }
+
+    fn describe(&self, trace: &CallTrace) -> String {
+        if trace.to == console::address() {
+            if let Some(message) = console::decode(self.abi, &trace.input) {
+                return format!("console.log({:?})", message);
+            }
+        }
+        match trace.kind {
+            CallKind::Create => format!(
+                "new {} ({} bytes of init code)",
+                self.abi.format_address(&trace.to),
+                trace.input.len()
+            ),
+            CallKind::Call | CallKind::SelfDestruct => self.abi.format_call(&trace.to, &trace.input),
+            kind => format!(
+                "{} [{:?}]",
+                self.abi.format_call(&trace.to, &trace.input),
+                kind
+            ),
+        }
+    }
+}
+
+fn flatten<'a>(trace: &'a CallTrace, out: &mut Vec<&'a CallTrace>) {
+    out.push(trace);
+    for sub in &trace.subtraces {
+        flatten(sub, out);
+    }
+}
+
+/// Renders call traces as a tree, with `logs` shown under the frames which emitted them.
+///
+/// Tracers don't see the logs, so each log is shown under the first successful
+/// frame executing in the context of the emitting address.
+pub fn render(traces: &[CallTrace], logs: &[LogEntry], abi: &AbiRegistry, color: bool) -> String {
+    let mut frames = Vec::new();
+    for trace in traces {
+        flatten(trace, &mut frames);
+    }
+
+    let mut assigned = vec![Vec::new(); frames.len()];
+    for log in logs {
+        let index = frames
+            .iter()
+            .position(|frame| frame.is_success() && frame.to == log.address)
+            .unwrap_or(0);
+        if let Some(frame_logs) = assigned.get_mut(index) {
+            frame_logs.push(log);
+        }
+    }
+
+    let mut renderer = Renderer {
+        abi,
+        color,
+        logs: assigned,
+        next_frame: 0,
+        out: String::new(),
+    };
+    for trace in traces {
+        renderer.frame(trace, "", "", "");
+    }
+    renderer.out
+}
+
+#[cfg(test)]
+fn test_trace() -> CallTrace {
+    let inner = CallTrace {
+        kind: CallKind::StaticCall,
+        from: 1.into(),
+        to: 2.into(),
+        value: 0.into(),
+        input: vec![],
+        output: vec![],
+        gas_used: None,
+        error: Some("Reverted".into()),
+        subtraces: vec![],
+    };
+
+    CallTrace {
+        kind: CallKind::Call,
+        from: 0.into(),
+        to: 1.into(),
+        value: 5.into(),
+        input: vec![],
+        output: vec![],
+        gas_used: Some(21000.into()),
+        error: None,
+        subtraces: vec![inner],
+    }
+}
+
+#[test]
+fn test_render() {
+    let mut abi = AbiRegistry::default();
+    abi.set_label(1.into(), "alice");
+    abi.set_label(2.into(), "bob");
+
+    assert_eq!(
+        render(&[test_trace()], &[], &abi, false),
+        "[21000] alice::fallback() {value: 5}\n\
+         ├─ [-] bob::fallback() [StaticCall]\n\
+         │  └─ ✗ Reverted\n\
+         └─ ← 0x\n"
+    );
+}
+
+#[test]
+fn test_render_color() {
+    let rendered = render(&[test_trace()], &[], &AbiRegistry::default(), true);
+    assert!(rendered.contains(GREEN));
+    assert!(rendered.contains(RED));
+}
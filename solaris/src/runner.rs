@@ -0,0 +1,364 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Runner of Solidity test contracts in the style of ds-test.
+//!
+//! The contract is deployed and `setUp()` is called once, then every public
+//! `test*` function without parameters is called from a snapshot taken right
+//! after. A test fails if it reverts or if `failed()` returns `true` afterwards,
+//! `testFail*` tests are expected to do either. Logs emitted with the ds-test
+//! `log*` events end up in the test result.
+//!
+//! The `solc` crate generates one Rust `#[test]` per Solidity test, see
+//! `solc::generate_tests`.
+
+use std::fmt;
+
+use ethabi::{self, Token};
+use ethereum_types::{Address, U256};
+use rustc_hex::FromHex;
+
+use abi;
+use error;
+use evm::Evm;
+
+/// Compiled test contract.
+#[derive(Debug, Clone)]
+pub struct TestContract {
+    name: String,
+    abi: ethabi::Contract,
+    code: Vec<u8>,
+}
+
+/// Outcome of a single Solidity test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    /// `Contract::testName`.
+    pub name: String,
+    pub passed: bool,
+    /// Why the test failed.
+    pub reason: Option<String>,
+    /// Messages logged with the ds-test `log*` events.
+    pub logs: Vec<String>,
+    pub gas_used: Option<U256>,
+}
+
+impl TestResult {
+    /// Panics with the failure reason and logs if the test failed.
+    pub fn assert(&self) {
+        if !self.passed {
+            panic!("{}", self);
+        }
+    }
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = if self.passed { "PASS" } else { "FAIL" };
+        write!(f, "[{}] {}", status, self.name)?;
+        if let Some(ref gas) = self.gas_used {
+            write!(f, " (gas: {})", gas)?;
+        }
+        if let Some(ref reason) = self.reason {
+            write!(f, "\n  {}", reason)?;
+        }
+        for log in &self.logs {
+            write!(f, "\n  {}", log)?;
+        }
+        Ok(())
+    }
+}
+
+impl TestContract {
+    pub fn new(name: &str, abi: &[u8], code: Vec<u8>) -> error::Result<Self> {
+        Ok(TestContract {
+            name: name.into(),
+            abi: ethabi::Contract::load(abi)?,
+            code,
+        })
+    }
+
+    /// Loads the `.abi` and hex-encoded `.bin` artifacts produced by `solc`.
+    pub fn from_artifacts(name: &str, abi: &[u8], code: &str) -> error::Result<Self> {
+        Self::new(name, abi, code.trim().from_hex()?)
+    }
+
+    /// Names of all test functions, sorted.
+    pub fn tests(&self) -> Vec<String> {
+        let mut tests: Vec<String> = self.abi
+            .functions()
+            .filter(|function| function.name.starts_with("test") && function.inputs.is_empty())
+            .map(|function| function.name.clone())
+            .collect();
+        tests.sort();
+        tests
+    }
+
+    /// Runs a single test in a fresh `Evm` with cheatcodes enabled.
+    pub fn run(&self, test: &str) -> TestResult {
+        let mut evm = Evm::default();
+        evm.enable_cheatcodes();
+        match self.set_up(&mut evm) {
+            Ok(address) => self.run_test(&mut evm, address, test),
+            Err(reason) => self.setup_failed(test, reason),
+        }
+    }
+
+    /// Runs all tests in a fresh `Evm` with cheatcodes enabled.
+    pub fn run_all(&self) -> Vec<TestResult> {
+        let mut evm = Evm::default();
+        evm.enable_cheatcodes();
+        self.run_all_in(&mut evm)
+    }
+
+    /// Runs all tests in given `evm`, each from the state right after `setUp()`.
+    pub fn run_all_in(&self, evm: &mut Evm) -> Vec<TestResult> {
+        let tests = self.tests();
+        let address = match self.set_up(evm) {
+            Ok(address) => address,
+            Err(reason) => {
+                return tests
+                    .iter()
+                    .map(|test| self.setup_failed(test, reason.clone()))
+                    .collect();
+            }
+        };
+
+        let snapshot = evm.snapshot();
+        tests
+            .iter()
+            .map(|test| {
+                evm.revert_to(&snapshot);
+                self.run_test(evm, address, test)
+            })
+            .collect()
+    }
+
+    fn set_up(&self, evm: &mut Evm) -> Result<Address, String> {
+        let address = evm.deploy(&self.code)
+            .map_err(|e| format!("Deployment failed: {}", e))?;
+        evm.register_abi(address, self.abi.clone());
+        evm.set_label(address, self.name.clone());
+
+        if self.abi.function("setUp").is_ok() {
+            let output = evm.transact_to(address, abi::selector("setUp()").to_vec())
+                .map_err(|e| format!("setUp() failed: {}", e))?;
+            if !output.is_success() {
+                return Err("setUp() reverted".into());
+            }
+        }
+        Ok(address)
+    }
+
+    fn setup_failed(&self, test: &str, reason: String) -> TestResult {
+        TestResult {
+            name: format!("{}::{}", self.name, test),
+            passed: false,
+            reason: Some(reason),
+            logs: vec![],
+            gas_used: None,
+        }
+    }
+
+    fn run_test(&self, evm: &mut Evm, address: Address, test: &str) -> TestResult {
+        let expect_fail = test.starts_with("testFail");
+        let data = abi::selector(&format!("{}()", test)).to_vec();
+
+        let (reverted, logs, gas_used) = match evm.transact_to(address, data) {
+            Ok(output) => {
+                let logs = output
+                    .logs()
+                    .iter()
+                    .filter(|log| log.address == address)
+                    .filter_map(|log| evm.abi().decode_log(log))
+                    .filter_map(|log| format_log(evm.abi(), &log))
+                    .collect();
+                let gas_used = output.trace().first().and_then(|trace| trace.gas_used);
+                (Ok(!output.is_success()), logs, gas_used)
+            }
+            Err(e) => (Err(e.to_string()), vec![], None),
+        };
+        let failed = reverted == Ok(false) && self.failed(evm, address);
+        let (passed, reason) = verdict(expect_fail, reverted, failed);
+
+        TestResult {
+            name: format!("{}::{}", self.name, test),
+            passed,
+            reason,
+            logs,
+            gas_used,
+        }
+    }
+
+    /// Reads the ds-test `failed()` flag, `false` if the contract has none.
+    fn failed(&self, evm: &mut Evm, address: Address) -> bool {
+        if self.abi.function("failed").is_err() {
+            return false;
+        }
+        evm.call_to(address, abi::selector("failed()").to_vec())
+            .ok()
+            .and_then(|output| ethabi::decode(&[ethabi::ParamType::Bool], &output).ok())
+            .map_or(false, |tokens| tokens == vec![Token::Bool(true)])
+    }
+}
+
+/// Whether a test passed, given whether its transaction reverted and `failed()` is set.
+///
+/// Only a revert satisfies `testFail*`, errors of the harness itself always fail the test.
+fn verdict(expect_fail: bool, reverted: Result<bool, String>, failed: bool) -> (bool, Option<String>) {
+    let reverted = match reverted {
+        Ok(reverted) => reverted,
+        Err(error) => return (false, Some(error)),
+    };
+
+    match (expect_fail, reverted || failed) {
+        (false, false) | (true, true) => (true, None),
+        (false, true) if failed => (false, Some("failed() returned true".to_owned())),
+        (false, true) => (false, Some("Reverted".to_owned())),
+        (true, false) => (false, Some("Expected the test to fail".to_owned())),
+    }
+}
+
+#[test]
+fn test_verdict() {
+    let reason = |reason: &str| Some(reason.to_owned());
+    assert_eq!(verdict(false, Ok(false), false), (true, None));
+    assert_eq!(verdict(false, Ok(true), false), (false, reason("Reverted")));
+    assert_eq!(verdict(false, Ok(false), true), (false, reason("failed() returned true")));
+    assert_eq!(verdict(true, Ok(true), false), (true, None));
+    assert_eq!(verdict(true, Ok(false), true), (true, None));
+    assert_eq!(verdict(true, Ok(false), false), (false, reason("Expected the test to fail")));
+
+    let error = || Err("Invalid transaction nonce".to_owned());
+    assert_eq!(verdict(false, error(), false), (false, reason("Invalid transaction nonce")));
+    assert_eq!(verdict(true, error(), false), (false, reason("Invalid transaction nonce")));
+}
+
+/// Formats a ds-test `log*` event, e.g. `log_named_uint("balance", 5)` as `balance: 5`.
+pub fn format_log(registry: &abi::AbiRegistry, log: &abi::DecodedLog) -> Option<String> {
+    let name = &log.event[..];
+    if !name.starts_with("log") {
+        return None;
+    }
+
+    let tokens: Vec<&Token> = log.params.iter().map(|param| &param.value).collect();
+    let message = if name.starts_with("log_named_") && !tokens.is_empty() {
+        let rest = &tokens[1..];
+        let value = match (name, rest.get(0), rest.get(1)) {
+            ("log_named_decimal_uint", Some(&&Token::Uint(ref value)), Some(&&Token::Uint(ref decimals))) => {
+                let decimals = if *decimals > U256::from(MAX_DECIMALS) {
+                    MAX_DECIMALS
+                } else {
+                    decimals.low_u64() as usize
+                };
+                format_decimal(value, decimals)
+            }
+            _ => format_tokens(registry, rest),
+        };
+        format!("{}: {}", format_text(registry, tokens[0]), value)
+    } else {
+        format_tokens(registry, &tokens)
+    };
+    Some(message)
+}
+
+fn format_tokens(registry: &abi::AbiRegistry, tokens: &[&Token]) -> String {
+    tokens
+        .iter()
+        .map(|token| format_text(registry, token))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Formats strings and text stored in `bytes32` as plain text.
+fn format_text(registry: &abi::AbiRegistry, token: &Token) -> String {
+    match *token {
+        Token::String(ref text) => text.clone(),
+        Token::FixedBytes(ref bytes) => {
+            let end = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+            match ::std::str::from_utf8(&bytes[..end]) {
+                Ok(text) if !text.is_empty() && !text.contains('\0') => text.into(),
+                _ => registry.format_token(token),
+            }
+        }
+        _ => registry.format_token(token),
+    }
+}
+
+/// Most decimals `log_named_decimal_uint` is formatted with, a `U256` has at most 78 digits.
+const MAX_DECIMALS: usize = 77;
+
+fn format_decimal(value: &U256, decimals: usize) -> String {
+    let digits = value.to_string();
+    if decimals == 0 {
+        return digits;
+    }
+    let digits = format!("{:0>width$}", digits, width = decimals + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_right_matches('0');
+    if fraction.is_empty() {
+        integer.into()
+    } else {
+        format!("{}.{}", integer, fraction)
+    }
+}
+
+#[test]
+fn test_format_decimal() {
+    assert_eq!(format_decimal(&1_500_000.into(), 6), "1.5");
+    assert_eq!(format_decimal(&5.into(), 3), "0.005");
+    assert_eq!(format_decimal(&2_000.into(), 3), "2");
+    assert_eq!(format_decimal(&42.into(), 0), "42");
+}
+
+#[test]
+fn test_format_log() {
+    let registry = abi::AbiRegistry::default();
+    let mut key = vec![0u8; 32];
+    key[..7].copy_from_slice(b"balance");
+    let log = abi::DecodedLog {
+        address: 1.into(),
+        event: "log_named_uint".into(),
+        params: vec![
+            ethabi::LogParam {
+                name: "key".into(),
+                value: Token::FixedBytes(key),
+            },
+            ethabi::LogParam {
+                name: "val".into(),
+                value: Token::Uint(5.into()),
+            },
+        ],
+    };
+    assert_eq!(format_log(&registry, &log), Some("balance: 5".into()));
+
+    let mut decimal = log.clone();
+    decimal.event = "log_named_decimal_uint".into();
+    decimal.params.push(ethabi::LogParam {
+        name: "decimals".into(),
+        value: Token::Uint(U256::max_value()),
+    });
+    assert_eq!(
+        format_log(&registry, &decimal),
+        Some(format!("balance: 0.{}5", "0".repeat(MAX_DECIMALS - 1)))
+    );
+
+    let log = abi::DecodedLog {
+        event: "Transfer".into(),
+        ..log
+    };
+    assert_eq!(format_log(&registry, &log), None);
+}
@@ -0,0 +1,214 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Local database of function and event signatures.
+//!
+//! Used to resolve 4-byte selectors and event topics of contracts without a
+//! registered ABI. The database file is plain text with one canonical
+//! signature per line, e.g. `approve(address,uint256)`. Lines starting with
+//! `#` are ignored, and so is a leading selector (`0x095ea7b3 approve(..)`)
+//! which lets you reuse dumps of public signature databases.
+//!
+//! The `solc` crate adds the signatures of every contract it compiles in a
+//! build script to `target/<profile>/solaris-signatures.txt`, which every
+//! `Evm` loads, see `compiled`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use ethabi;
+use ethereum_types::H256;
+use keccak_hash::keccak;
+
+use abi;
+use error;
+
+/// Name of the signature database written by `solc::compile`.
+pub const COMPILED_FILE: &str = "solaris-signatures.txt";
+
+/// Loads the signatures of the contracts compiled by `solc::compile`.
+///
+/// The database is looked up in the profile directory of the running
+/// executable, test binaries run from `target/<profile>/deps`. Returns
+/// `None` if nothing was compiled.
+pub fn compiled() -> Option<SignatureDb> {
+    let exe = env::current_exe().ok()?;
+    let path = exe.parent()?.parent()?.join(COMPILED_FILE);
+    SignatureDb::load(path).ok()
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SignatureDb {
+    signatures: BTreeSet<String>,
+    functions: BTreeMap<[u8; 4], String>,
+    events: BTreeMap<H256, String>,
+}
+
+impl SignatureDb {
+    /// Loads a signature database file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut db = SignatureDb::default();
+        db.load_file(path)?;
+        Ok(db)
+    }
+
+    /// Adds all signatures from the `*.abi` files in given directory,
+    /// e.g. the contracts directory compiled by the `solc` crate.
+    pub fn from_abi_dir<P: AsRef<Path>>(path: P) -> error::Result<Self> {
+        let mut db = SignatureDb::default();
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |ext| ext == "abi") {
+                let contract = ethabi::Contract::load(fs::File::open(path)?)?;
+                db.add_abi(&contract);
+            }
+        }
+        Ok(db)
+    }
+
+    /// Adds signatures from a signature database file.
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let file = io::BufReader::new(fs::File::open(path)?);
+        for line in file.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(signature) = line.split_whitespace().last() {
+                self.insert(signature);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes all known signatures to a signature database file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        for signature in &self.signatures {
+            writeln!(file, "{}", signature)?;
+        }
+        Ok(())
+    }
+
+    /// Adds a canonical function or event signature.
+    pub fn insert(&mut self, signature: &str) {
+        let hash = keccak(signature.as_bytes());
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&hash[..4]);
+
+        self.signatures.insert(signature.into());
+        self.functions.entry(selector).or_insert_with(|| signature.into());
+        self.events.entry(hash).or_insert_with(|| signature.into());
+    }
+
+    /// Adds signatures of all functions and events of a contract.
+    pub fn add_abi(&mut self, contract: &ethabi::Contract) {
+        for function in contract.functions() {
+            let kinds = function.inputs.iter().map(|param| &param.kind);
+            self.insert(&abi::signature(&function.name, kinds));
+        }
+        for event in contract.events() {
+            let kinds = event.inputs.iter().map(|param| &param.kind);
+            self.insert(&abi::signature(&event.name, kinds));
+        }
+    }
+
+    pub fn extend(&mut self, other: SignatureDb) {
+        for signature in other.signatures {
+            self.insert(&signature);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty()
+    }
+
+    /// Returns the signature of a function with given 4-byte `selector`.
+    pub fn function(&self, selector: &[u8]) -> Option<&str> {
+        if selector.len() < 4 {
+            return None;
+        }
+        let mut key = [0u8; 4];
+        key.copy_from_slice(&selector[..4]);
+        self.functions.get(&key).map(|signature| signature.as_str())
+    }
+
+    /// Returns the signature of an event with given `topic`.
+    pub fn event(&self, topic: &H256) -> Option<&str> {
+        self.events.get(topic).map(|signature| signature.as_str())
+    }
+}
+
+/// Splits a canonical signature into its name and parameter types.
+///
+/// Returns `None` if any of the parameter types is not understood by `ethabi`.
+pub fn parse(signature: &str) -> Option<(&str, Vec<ethabi::ParamType>)> {
+    let open = signature.find('(')?;
+    if !signature.ends_with(')') {
+        return None;
+    }
+
+    let name = &signature[..open];
+    let params = &signature[open + 1..signature.len() - 1];
+    if params.is_empty() {
+        return Some((name, vec![]));
+    }
+
+    params
+        .split(',')
+        .map(|kind| ethabi::param_type::Reader::read(kind).ok())
+        .collect::<Option<Vec<_>>>()
+        .map(|kinds| (name, kinds))
+}
+
+#[test]
+fn test_lookup() {
+    let mut db = SignatureDb::default();
+    db.insert("approve(address,uint256)");
+    db.insert("Transfer(address,address,uint256)");
+
+    assert_eq!(
+        db.function(&[0x09, 0x5e, 0xa7, 0xb3, 0xff]),
+        Some("approve(address,uint256)")
+    );
+    assert_eq!(
+        db.event(&keccak("Transfer(address,address,uint256)")),
+        Some("Transfer(address,address,uint256)")
+    );
+    assert_eq!(db.function(&[0x09, 0x5e]), None);
+    assert_eq!(db.len(), 2);
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(
+        parse("approve(address,uint256)"),
+        Some((
+            "approve",
+            vec![ethabi::ParamType::Address, ethabi::ParamType::Uint(256)]
+        ))
+    );
+    assert_eq!(parse("fallback()"), Some(("fallback", vec![])));
+    assert_eq!(parse("broken(address"), None);
+}
@@ -0,0 +1,451 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Signing of hashes, `eth_sign` messages and EIP-712 typed data,
+//! for testing contracts which verify signatures with `ecrecover`.
+//!
+//! Struct types are defined the way they are encoded:
+//!
+//! ```ignore
+//! let mut types = Eip712Types::default();
+//! types
+//!     .with_type("Mail(Person from,Person to,string contents)")?
+//!     .with_type("Person(string name,address wallet)")?;
+//! let domain = Domain::default().with_name("Ether Mail").with_chain_id(1).clone();
+//! let hash = types.signing_hash(&domain, "Mail", &mail)?;
+//! let signature = evm.test_account("alice").unwrap().sign(&hash);
+//! contract.functions().verify(mail, signature.v, signature.r, signature.s);
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ethabi::{self, Token};
+use ethereum_types::{Address, H256, U256};
+use ethkey::{self, Secret};
+use keccak_hash::keccak;
+
+use error::{self, ErrorKind};
+
+/// ECDSA signature as expected by `ecrecover`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    /// Recovery id, 27 or 28.
+    pub v: u8,
+    pub r: H256,
+    pub s: H256,
+}
+
+impl Signature {
+    /// `r`, `s` and `v` concatenated, 65 bytes.
+    pub fn packed(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(65);
+        out.extend_from_slice(&self.r);
+        out.extend_from_slice(&self.s);
+        out.push(self.v);
+        out
+    }
+
+    /// `v`, `r` and `s` as `uint8`, `bytes32` and `bytes32` arguments.
+    pub fn tokens(&self) -> Vec<Token> {
+        vec![
+            Token::Uint(self.v.into()),
+            Token::FixedBytes(self.r.to_vec()),
+            Token::FixedBytes(self.s.to_vec()),
+        ]
+    }
+
+    /// The packed signature as a `bytes` argument.
+    pub fn packed_token(&self) -> Token {
+        Token::Bytes(self.packed())
+    }
+}
+
+/// Signs `hash` as is.
+pub fn sign(secret: &Secret, hash: &H256) -> Signature {
+    let signature = ethkey::sign(secret, hash).expect("Test account secrets are valid; qed");
+    Signature {
+        v: signature.v() + 27,
+        r: H256::from_slice(signature.r()),
+        s: H256::from_slice(signature.s()),
+    }
+}
+
+/// Recovers the address which signed `hash`.
+pub fn recover(signature: &Signature, hash: &H256) -> error::Result<Address> {
+    if signature.v != 27 && signature.v != 28 {
+        bail!(ErrorKind::Signing(format!("Invalid recovery id {}", signature.v)));
+    }
+    let signature = ethkey::Signature::from_rsv(&signature.r, &signature.s, signature.v - 27);
+    let public = ethkey::recover(&signature, hash)?;
+    Ok(ethkey::public_to_address(&public))
+}
+
+/// Hash of `message` prefixed with `"\x19Ethereum Signed Message:\n" + len(message)`.
+pub fn eth_message_hash(message: &[u8]) -> H256 {
+    let mut prefixed = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    prefixed.extend_from_slice(message);
+    keccak(&prefixed)
+}
+
+/// Signs `message` like the `eth_sign` RPC.
+pub fn eth_sign(secret: &Secret, message: &[u8]) -> Signature {
+    sign(secret, &eth_message_hash(message))
+}
+
+/// Hash of a typed message, `keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(message))`.
+pub fn typed_data_hash(domain_separator: &H256, struct_hash: &H256) -> H256 {
+    let mut data = vec![0x19, 0x01];
+    data.extend_from_slice(domain_separator);
+    data.extend_from_slice(struct_hash);
+    keccak(&data)
+}
+
+/// Value of an EIP-712 struct member.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// Atomic value, `string` or `bytes`.
+    Token(Token),
+    /// Struct with values of all members, in declaration order.
+    Struct(Vec<Value>),
+    /// Fixed or dynamic array.
+    Array(Vec<Value>),
+}
+
+impl From<Token> for Value {
+    fn from(token: Token) -> Self {
+        Value::Token(token)
+    }
+}
+
+/// EIP-712 domain, only the fields which are set are part of the separator.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Domain {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub chain_id: Option<U256>,
+    pub verifying_contract: Option<Address>,
+    pub salt: Option<H256>,
+}
+
+impl Domain {
+    pub fn with_name<S: Into<String>>(&mut self, name: S) -> &mut Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_version<S: Into<String>>(&mut self, version: S) -> &mut Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn with_chain_id<T: Into<U256>>(&mut self, chain_id: T) -> &mut Self {
+        self.chain_id = Some(chain_id.into());
+        self
+    }
+
+    pub fn with_verifying_contract(&mut self, address: Address) -> &mut Self {
+        self.verifying_contract = Some(address);
+        self
+    }
+
+    pub fn with_salt(&mut self, salt: H256) -> &mut Self {
+        self.salt = Some(salt);
+        self
+    }
+
+    /// `hashStruct(EIP712Domain)` of the set fields.
+    pub fn separator(&self) -> H256 {
+        let mut members = Vec::new();
+        let mut values = Vec::new();
+        if let Some(ref name) = self.name {
+            members.push("string name");
+            values.push(Value::Token(Token::String(name.clone())));
+        }
+        if let Some(ref version) = self.version {
+            members.push("string version");
+            values.push(Value::Token(Token::String(version.clone())));
+        }
+        if let Some(chain_id) = self.chain_id {
+            members.push("uint256 chainId");
+            values.push(Value::Token(Token::Uint(chain_id)));
+        }
+        if let Some(address) = self.verifying_contract {
+            members.push("address verifyingContract");
+            values.push(Value::Token(Token::Address(address)));
+        }
+        if let Some(salt) = self.salt {
+            members.push("bytes32 salt");
+            values.push(Value::Token(Token::FixedBytes(salt.to_vec())));
+        }
+
+        let mut types = Eip712Types::default();
+        types
+            .with_type(&format!("EIP712Domain({})", members.join(",")))
+            .expect("Domain type is valid; qed");
+        types
+            .hash_struct("EIP712Domain", &Value::Struct(values))
+            .expect("Domain values match the type; qed")
+    }
+}
+
+/// Struct types of EIP-712 typed data.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Eip712Types {
+    /// Members of every struct as `(type, name)`.
+    types: BTreeMap<String, Vec<(String, String)>>,
+}
+
+impl Eip712Types {
+    /// Adds a struct type, e.g. `Person(string name,address wallet)`.
+    pub fn with_type(&mut self, definition: &str) -> error::Result<&mut Self> {
+        let invalid = || ErrorKind::Signing(format!("Invalid type definition: {}", definition));
+        let open = definition.find('(').ok_or_else(invalid)?;
+        if !definition.ends_with(')') || open == 0 {
+            bail!(invalid());
+        }
+
+        let members = &definition[open + 1..definition.len() - 1];
+        let members = if members.is_empty() {
+            vec![]
+        } else {
+            members
+                .split(',')
+                .map(|member| {
+                    let mut parts = member.trim().split_whitespace();
+                    match (parts.next(), parts.next(), parts.next()) {
+                        (Some(kind), Some(name), None) => Ok((kind.to_owned(), name.to_owned())),
+                        _ => Err(invalid()),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        self.types.insert(definition[..open].to_owned(), members);
+        Ok(self)
+    }
+
+    /// `encodeType`, the struct followed by all referenced structs sorted by name.
+    pub fn encode_type(&self, primary: &str) -> error::Result<String> {
+        let mut referenced = BTreeSet::new();
+        self.collect_references(primary, &mut referenced)?;
+        referenced.remove(primary);
+
+        let mut out = self.definition(primary)?;
+        for name in referenced {
+            out.push_str(&self.definition(&name)?);
+        }
+        Ok(out)
+    }
+
+    pub fn type_hash(&self, primary: &str) -> error::Result<H256> {
+        Ok(keccak(self.encode_type(primary)?.as_bytes()))
+    }
+
+    /// `hashStruct`, the type hash and encoded members of `value` hashed together.
+    pub fn hash_struct(&self, primary: &str, value: &Value) -> error::Result<H256> {
+        let members = self.members(primary)?;
+        let values = match *value {
+            Value::Struct(ref values) if values.len() == members.len() => values,
+            _ => bail!(ErrorKind::Signing(format!(
+                "Expected {} values of {}",
+                members.len(),
+                primary
+            ))),
+        };
+
+        let mut data = self.type_hash(primary)?.to_vec();
+        for (&(ref kind, _), value) in members.iter().zip(values) {
+            self.encode_value(kind, value, &mut data)?;
+        }
+        Ok(keccak(&data))
+    }
+
+    /// Hash to sign for `value` of type `primary` in `domain`.
+    pub fn signing_hash(&self, domain: &Domain, primary: &str, value: &Value) -> error::Result<H256> {
+        Ok(typed_data_hash(&domain.separator(), &self.hash_struct(primary, value)?))
+    }
+
+    fn members(&self, name: &str) -> error::Result<&[(String, String)]> {
+        self.types
+            .get(name)
+            .map(|members| &members[..])
+            .ok_or_else(|| ErrorKind::Signing(format!("Unknown type {}", name)).into())
+    }
+
+    fn definition(&self, name: &str) -> error::Result<String> {
+        let members: Vec<String> = self.members(name)?
+            .iter()
+            .map(|&(ref kind, ref member)| format!("{} {}", kind, member))
+            .collect();
+        Ok(format!("{}({})", name, members.join(",")))
+    }
+
+    fn collect_references(&self, name: &str, out: &mut BTreeSet<String>) -> error::Result<()> {
+        if !out.insert(name.to_owned()) {
+            return Ok(());
+        }
+        for &(ref kind, _) in self.members(name)? {
+            let base = element_type(kind).0;
+            if self.types.contains_key(base) {
+                self.collect_references(base, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_value(&self, kind: &str, value: &Value, out: &mut Vec<u8>) -> error::Result<()> {
+        let mismatch = || ErrorKind::Signing(format!("Value {:?} doesn't match type {}", value, kind));
+        if let (inner, true) = element_type(kind) {
+            let elements = match *value {
+                Value::Array(ref elements) => elements,
+                _ => bail!(mismatch()),
+            };
+            let mut data = Vec::new();
+            for element in elements {
+                self.encode_value(inner, element, &mut data)?;
+            }
+            out.extend_from_slice(&keccak(&data));
+            return Ok(());
+        }
+
+        if self.types.contains_key(kind) {
+            out.extend_from_slice(&self.hash_struct(kind, value)?);
+            return Ok(());
+        }
+
+        let token = match *value {
+            Value::Token(ref token) => token,
+            _ => bail!(mismatch()),
+        };
+        let matches = match (kind, token) {
+            ("string", &Token::String(_)) | ("bytes", &Token::Bytes(_)) | ("bool", &Token::Bool(_)) => true,
+            ("address", &Token::Address(_)) => true,
+            (kind, &Token::Uint(_)) => kind.starts_with("uint"),
+            (kind, &Token::Int(_)) => kind.starts_with("int"),
+            (kind, &Token::FixedBytes(ref bytes)) => kind == format!("bytes{}", bytes.len()),
+            _ => false,
+        };
+        if !matches {
+            bail!(mismatch());
+        }
+
+        match *token {
+            Token::String(ref text) => out.extend_from_slice(&keccak(text.as_bytes())),
+            Token::Bytes(ref bytes) => out.extend_from_slice(&keccak(bytes)),
+            _ => out.extend(ethabi::encode(&[token.clone()])),
+        }
+        Ok(())
+    }
+}
+
+/// Splits off the last array suffix, `("Person", true)` for `Person[2]`.
+fn element_type(kind: &str) -> (&str, bool) {
+    match kind.rfind('[') {
+        Some(open) if kind.ends_with(']') => (&kind[..open], true),
+        _ => (kind, false),
+    }
+}
+
+#[cfg(test)]
+fn test_mail() -> (Eip712Types, Domain, Value) {
+    let mut types = Eip712Types::default();
+    types
+        .with_type("Mail(Person from,Person to,string contents)")
+        .unwrap()
+        .with_type("Person(string name,address wallet)")
+        .unwrap();
+
+    let mut domain = Domain::default();
+    domain
+        .with_name("Ether Mail")
+        .with_version("1")
+        .with_chain_id(1)
+        .with_verifying_contract("CcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC".parse().unwrap());
+
+    let person = |name: &str, wallet: &str| {
+        Value::Struct(vec![
+            Token::String(name.into()).into(),
+            Token::Address(wallet.parse().unwrap()).into(),
+        ])
+    };
+    let mail = Value::Struct(vec![
+        person("Cow", "CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"),
+        person("Bob", "bBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"),
+        Token::String("Hello, Bob!".into()).into(),
+    ]);
+    (types, domain, mail)
+}
+
+#[test]
+fn test_eip712_example() {
+    let (types, domain, mail) = test_mail();
+    assert_eq!(
+        types.encode_type("Mail").unwrap(),
+        "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+    );
+    assert_eq!(
+        types.type_hash("Mail").unwrap(),
+        "a0cedeb2dc280ba39b857546d74f5549c3a1d7bdc2dd96bf881f76108e23dac2".parse().unwrap()
+    );
+    assert_eq!(
+        types.hash_struct("Mail", &mail).unwrap(),
+        "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e".parse().unwrap()
+    );
+    assert_eq!(
+        domain.separator(),
+        "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f".parse().unwrap()
+    );
+
+    let hash = types.signing_hash(&domain, "Mail", &mail).unwrap();
+    assert_eq!(
+        hash,
+        "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2".parse().unwrap()
+    );
+
+    let secret = Secret::from_unsafe_slice(&keccak("cow")[..]).unwrap();
+    let signature = sign(&secret, &hash);
+    assert_eq!(signature.v, 28);
+    assert_eq!(
+        signature.r,
+        "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d".parse().unwrap()
+    );
+    assert_eq!(
+        signature.s,
+        "07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562".parse().unwrap()
+    );
+    assert_eq!(
+        recover(&signature, &hash).unwrap(),
+        "CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826".parse().unwrap()
+    );
+}
+
+#[test]
+fn test_type_mismatch() {
+    let (types, _, _) = test_mail();
+    let person = Value::Struct(vec![Token::Uint(1.into()).into(), Token::Address(2.into()).into()]);
+    assert!(types.hash_struct("Person", &person).is_err());
+    assert!(types.hash_struct("Unknown", &person).is_err());
+}
+
+#[test]
+fn test_eth_sign() {
+    let secret = Secret::from_unsafe_slice(&keccak("cow")[..]).unwrap();
+    let signature = eth_sign(&secret, b"hello");
+    assert_eq!(signature.packed().len(), 65);
+    assert_eq!(
+        recover(&signature, &eth_message_hash(b"hello")).unwrap(),
+        "CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826".parse().unwrap()
+    );
+}
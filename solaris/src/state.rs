@@ -0,0 +1,213 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Plain account state.
+//!
+//! The state trie can't be enumerated, so the `Evm` keeps track of every
+//! account and storage slot touched by execution. That's enough to dump the
+//! state into plain accounts and to load them into another `Evm`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ethcore::pod_state::PodState;
+use ethereum_types::{Address, H256, U256};
+use ethjson;
+use serde_json;
+
+use evm::BlockEnv;
+
+/// Plain state of a single account.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    pub balance: U256,
+    pub nonce: U256,
+    #[serde(with = "hex_bytes")]
+    pub code: Vec<u8>,
+    /// Non-zero storage slots.
+    pub storage: BTreeMap<H256, H256>,
+}
+
+impl Account {
+    /// Sets a storage slot, zero values are removed.
+    pub fn set_storage(&mut self, key: H256, value: H256) {
+        if value.is_zero() {
+            self.storage.remove(&key);
+        } else {
+            self.storage.insert(key, value);
+        }
+    }
+}
+
+/// Accounts and storage slots touched so far.
+#[derive(Debug, Default, Clone)]
+pub struct Touched(BTreeMap<Address, BTreeSet<H256>>);
+
+impl Touched {
+    pub fn account(&mut self, address: Address) {
+        self.0.entry(address).or_insert_with(BTreeSet::new);
+    }
+
+    pub fn storage(&mut self, address: Address, key: H256) {
+        self.0.entry(address).or_insert_with(BTreeSet::new).insert(key);
+    }
+
+    pub fn extend(&mut self, other: Touched) {
+        for (address, keys) in other.0 {
+            self.0.entry(address).or_insert_with(BTreeSet::new).extend(keys);
+        }
+    }
+
+    pub fn contains(&self, address: &Address) -> bool {
+        self.0.contains_key(address)
+    }
+
+    /// Storage slots of `address` touched so far.
+    pub fn storage_keys<'a>(&'a self, address: &Address) -> Box<Iterator<Item = &'a H256> + 'a> {
+        match self.0.get(address) {
+            Some(keys) => Box::new(keys.iter()),
+            None => Box::new(::std::iter::empty()),
+        }
+    }
+
+    pub fn iter(&self) -> ::std::collections::btree_map::Iter<Address, BTreeSet<H256>> {
+        self.0.iter()
+    }
+}
+
+/// Converts plain accounts into the state `backend::Backend` is populated with.
+///
+/// Goes through the chain spec `accounts` format, which is the public way
+/// of building a `PodState`.
+pub fn to_pod(accounts: &BTreeMap<Address, Account>) -> PodState {
+    let json = serde_json::to_value(accounts).expect("Accounts are always serializable; qed");
+    let state: ethjson::spec::State =
+        serde_json::from_value(json).expect("Account fields match the chain spec format; qed");
+    state.into()
+}
+
+/// Everything needed to set up an `Evm` again, see `Evm::dump`.
+///
+/// Saved as JSON, so expensive fixtures can be cached between test runs
+/// or shared. Executed transactions and the keys of test accounts are not
+/// included, the latter are derived again with `Evm::with_accounts`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateDump {
+    pub block: BlockEnv,
+    pub chain_id: u64,
+    pub accounts: BTreeMap<Address, Account>,
+    pub labels: BTreeMap<Address, String>,
+}
+
+impl StateDump {
+    /// Loads a dump saved with `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = io::BufReader::new(fs::File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Writes the dump as JSON.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        Ok(serde_json::to_writer_pretty(file, self)?)
+    }
+
+    /// Accounts in the format of the `accounts` section of a Parity chain spec.
+    pub fn chain_spec_accounts(&self) -> serde_json::Value {
+        serde_json::to_value(&self.accounts).expect("Accounts are always serializable; qed")
+    }
+}
+
+/// Accounts which differ between two dumps, with their state before and after.
+pub fn diff(
+    before: &BTreeMap<Address, Account>,
+    after: &BTreeMap<Address, Account>,
+) -> BTreeMap<Address, (Option<Account>, Option<Account>)> {
+    before
+        .keys()
+        .chain(after.keys())
+        .filter(|address| before.get(address) != after.get(address))
+        .map(|address| (*address, (before.get(address).cloned(), after.get(address).cloned())))
+        .collect()
+}
+
+mod hex_bytes {
+    use rustc_hex::{FromHex, ToHex};
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", bytes.to_hex()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let hex = if s.starts_with("0x") { &s[2..] } else { &s[..] };
+        hex.from_hex()
+            .map_err(|e| D::Error::custom(format!("Invalid hex bytes {}: {}", s, e)))
+    }
+}
+
+#[test]
+fn test_state_dump_json() {
+    let mut account = Account::default();
+    account.balance = 16.into();
+    account.storage.insert(1.into(), 2.into());
+    let mut dump = StateDump {
+        block: BlockEnv::default(),
+        chain_id: 1,
+        accounts: BTreeMap::new(),
+        labels: BTreeMap::new(),
+    };
+    dump.accounts.insert(3.into(), account);
+    dump.labels.insert(3.into(), "alice".into());
+
+    let json = serde_json::to_string(&dump).unwrap();
+    assert!(json.contains(r#""chainId":1"#));
+    assert_eq!(serde_json::from_str::<StateDump>(&json).unwrap(), dump);
+
+    let spec: ethjson::spec::State = serde_json::from_value(dump.chain_spec_accounts()).unwrap();
+    assert_eq!(PodState::from(spec), to_pod(&dump.accounts));
+}
+
+#[test]
+fn test_account_json() {
+    let mut account = Account::default();
+    account.balance = 16.into();
+    account.code = vec![0x60, 0x00];
+    account.storage.insert(1.into(), 2.into());
+
+    let json = serde_json::to_string(&account).unwrap();
+    assert!(json.contains(r#""code":"0x6000""#));
+    assert_eq!(serde_json::from_str::<Account>(&json).unwrap(), account);
+}
+
+#[test]
+fn test_diff() {
+    let mut before = BTreeMap::new();
+    before.insert(Address::from(1), Account::default());
+    before.insert(Address::from(2), Account::default());
+    let mut after = before.clone();
+    after.get_mut(&Address::from(2)).unwrap().balance = 5.into();
+    after.insert(Address::from(3), Account::default());
+
+    let diff = diff(&before, &after);
+    assert_eq!(diff.keys().cloned().collect::<Vec<_>>(), vec![Address::from(2), Address::from(3)]);
+    assert_eq!(diff[&Address::from(3)], (None, Some(Account::default())));
+}
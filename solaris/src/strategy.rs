@@ -0,0 +1,81 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! `proptest` strategies for addresses, integers and ABI tokens,
+//! available with the `proptest` feature.
+
+use ethabi::{ParamType, Token};
+use ethereum_types::{Address, U256};
+use proptest::collection;
+use proptest::prelude::*;
+
+use fuzz::{self, MAX_ARRAY, MAX_BYTES};
+
+pub fn address() -> BoxedStrategy<Address> {
+    any::<[u8; 20]>()
+        .prop_map(|bytes| Address::from(&bytes[..]))
+        .boxed()
+}
+
+/// Unsigned integers of given bit size, biased towards edge values.
+pub fn uint(bits: usize) -> BoxedStrategy<U256> {
+    let max = fuzz::max_uint(bits);
+    prop_oneof![
+        Just(U256::zero()),
+        Just(U256::one()),
+        Just(max),
+        any::<u64>().prop_map(move |value| U256::from(value) & max),
+        any::<[u8; 32]>().prop_map(move |bytes| U256::from(&bytes[..]) & max),
+    ].boxed()
+}
+
+/// Signed integers of given bit size as two's complement, biased towards edge values.
+pub fn int(bits: usize) -> BoxedStrategy<U256> {
+    let max = fuzz::max_uint(bits);
+    uint(bits)
+        .prop_map(move |value| {
+            // sign-extend to 256 bits
+            if value.bit(bits - 1) {
+                value | !max
+            } else {
+                value
+            }
+        })
+        .boxed()
+}
+
+/// Tokens of given type.
+pub fn token(kind: &ParamType) -> BoxedStrategy<Token> {
+    match *kind {
+        ParamType::Address => address().prop_map(Token::Address).boxed(),
+        ParamType::Uint(bits) => uint(bits).prop_map(Token::Uint).boxed(),
+        ParamType::Int(bits) => int(bits).prop_map(Token::Int).boxed(),
+        ParamType::Bool => any::<bool>().prop_map(Token::Bool).boxed(),
+        ParamType::FixedBytes(len) => collection::vec(any::<u8>(), len..len + 1)
+            .prop_map(Token::FixedBytes)
+            .boxed(),
+        ParamType::Bytes => collection::vec(any::<u8>(), 0..MAX_BYTES + 1)
+            .prop_map(Token::Bytes)
+            .boxed(),
+        ParamType::String => "[ -~]{0,64}".prop_map(Token::String).boxed(),
+        ParamType::Array(ref inner) => collection::vec(token(inner), 0..MAX_ARRAY + 1)
+            .prop_map(Token::Array)
+            .boxed(),
+        ParamType::FixedArray(ref inner, len) => collection::vec(token(inner), len..len + 1)
+            .prop_map(Token::FixedArray)
+            .boxed(),
+    }
+}
@@ -14,30 +14,517 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use ethcore::log_entry::LogEntry;
 use ethcore::trace;
 use ethcore::trace::trace::{Call, Create};
 use ethcore_bytes::{Bytes, ToPretty};
-use ethereum_types::{H160, U256};
+use ethereum_types::{Address, H160, U256};
 use vm;
 
+use abi::AbiRegistry;
+use profile;
+use render;
+use state;
+
+/// How much of the execution trace is written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Nothing is written.
+    Silent,
+    /// Call tree of failed transactions and calls only.
+    Failed,
+    /// Call tree of every transaction and call.
+    Calls,
+    /// Call tree and every executed opcode.
+    Opcodes,
+}
+
+impl Verbosity {
+    /// Reads verbosity from `SOLARIS_TRACE` (`silent`, `failed`, `calls` or `opcodes`).
+    ///
+    /// `SOLARIS_VM_TRACES` is still accepted and implies `opcodes`.
+    /// Defaults to `failed`, also when the value is invalid.
+    pub fn from_env() -> Self {
+        if ::std::env::var("SOLARIS_VM_TRACES").is_ok() {
+            return Verbosity::Opcodes;
+        }
+
+        match ::std::env::var("SOLARIS_TRACE") {
+            Ok(value) => Self::parse_or_default(&value),
+            Err(_) => Verbosity::default(),
+        }
+    }
+
+    fn parse_or_default(value: &str) -> Self {
+        value.parse().unwrap_or_else(|err| {
+            warn!("Ignoring invalid SOLARIS_TRACE value: {}", err);
+            Verbosity::default()
+        })
+    }
+}
+
+impl Default for Verbosity {
+    fn default() -> Self {
+        Verbosity::Failed
+    }
+}
+
+impl FromStr for Verbosity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "silent" | "none" | "0" => Ok(Verbosity::Silent),
+            "failed" | "1" => Ok(Verbosity::Failed),
+            "calls" | "2" => Ok(Verbosity::Calls),
+            "opcodes" | "3" => Ok(Verbosity::Opcodes),
+            other => Err(format!("unknown verbosity: {}", other)),
+        }
+    }
+}
+
+#[test]
+fn test_verbosity_from_str() {
+    assert_eq!("silent".parse(), Ok(Verbosity::Silent));
+    assert_eq!("Failed".parse(), Ok(Verbosity::Failed));
+    assert_eq!("2".parse(), Ok(Verbosity::Calls));
+    assert_eq!("opcodes".parse(), Ok(Verbosity::Opcodes));
+    assert!("everything".parse::<Verbosity>().is_err());
+    assert_eq!(Verbosity::parse_or_default("everything"), Verbosity::Failed);
+    assert_eq!(Verbosity::parse_or_default("calls"), Verbosity::Calls);
+    assert!(Verbosity::Failed < Verbosity::Calls);
+}
+
+/// Where the execution trace is written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sink {
+    /// Standard output, captured by the test harness like any `println!`.
+    Stdout,
+    /// The `log` facade, at `info` level with `solaris::trace` target.
+    Log,
+}
+
+impl Sink {
+    /// `Log` if `SOLARIS_TRACE_LOG` is set, `Stdout` otherwise.
+    pub fn from_env() -> Self {
+        if ::std::env::var("SOLARIS_TRACE_LOG").is_ok() {
+            Sink::Log
+        } else {
+            Sink::Stdout
+        }
+    }
+
+    /// Whether rendered traces should be colourised, see `render::color_from_env`.
+    ///
+    /// Never for `Log`, escape codes don't belong in log files.
+    pub fn color(self) -> bool {
+        match self {
+            Sink::Stdout => render::color_from_env(),
+            Sink::Log => false,
+        }
+    }
+
+    /// Writes out a single line.
+    pub fn write(self, line: &str) {
+        match self {
+            Sink::Stdout => println!("{}", line),
+            Sink::Log => info!(target: "solaris::trace", "{}", line),
+        }
+    }
+}
+
+/// State shared by all tracers of a single transaction.
+#[derive(Debug, Default)]
+struct Shared {
+    /// The frame which is about to be entered.
+    ///
+    /// It's recorded by the call tracer, which gets to see the `ActionParams`,
+    /// and picked up by the vm tracer, which only gets to see the code.
+    next_frame: RefCell<Option<Frame>>,
+    /// Trace lines written so far.
+    lines: RefCell<Vec<String>>,
+    /// Whether the outermost call or create failed.
+    failed: Cell<bool>,
+    /// Accounts and storage slots touched by execution.
+    touched: RefCell<state::Touched>,
+}
+
+/// Trace output of a single transaction, available after the tracers are consumed.
+#[derive(Debug)]
+pub struct Report {
+    shared: Rc<Shared>,
+}
+
+impl Report {
+    /// Whether the outermost call or create of the transaction failed.
+    pub fn failed(&self) -> bool {
+        self.shared.failed.get()
+    }
+
+    /// Accounts and storage slots touched by the transaction.
+    pub fn touched(&self) -> state::Touched {
+        self.shared.touched.borrow().clone()
+    }
+
+    /// Appends logs emitted by the transaction to the trace.
+    pub fn logs(&self, abi: &AbiRegistry, logs: &[LogEntry]) {
+        let mut lines = self.shared.lines.borrow_mut();
+        lines.extend(logs.iter().map(|log| format!("LOG {}", abi.format_log(log))));
+    }
+
+    /// Writes the trace out according to `verbosity`.
+    ///
+    /// `failed` marks transactions rejected before execution. The call tree
+    /// is only rendered if it's going to be written out.
+    pub fn finish<F>(self, verbosity: Verbosity, sink: Sink, failed: bool, render: F)
+    where
+        F: FnOnce() -> String,
+    {
+        for line in self.lines(verbosity, failed, render) {
+            sink.write(&line);
+        }
+    }
+
+    fn lines<F>(&self, verbosity: Verbosity, failed: bool, render: F) -> Vec<String>
+    where
+        F: FnOnce() -> String,
+    {
+        let failed = failed || self.failed();
+        if verbosity == Verbosity::Silent || (verbosity == Verbosity::Failed && !failed) {
+            return Vec::new();
+        }
+
+        if verbosity == Verbosity::Opcodes {
+            self.shared.lines.borrow().clone()
+        } else {
+            render().lines().map(Into::into).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_report(failed: bool) -> Report {
+    let report = Report {
+        shared: Default::default(),
+    };
+    report.shared.failed.set(failed);
+    report.shared.lines.borrow_mut().push("[0] SSTORE".into());
+    report
+}
+
+#[test]
+fn test_report_lines() {
+    let render = || "CALL Token::transfer\n  CALL Token::balanceOf".to_owned();
+    let calls = vec!["CALL Token::transfer", "  CALL Token::balanceOf"];
+    let lines = |verbosity, failed| test_report(failed).lines(verbosity, false, render);
+
+    assert!(lines(Verbosity::Silent, true).is_empty());
+    assert!(lines(Verbosity::Failed, false).is_empty());
+    assert_eq!(lines(Verbosity::Failed, true), calls);
+    assert_eq!(lines(Verbosity::Calls, false), calls);
+    assert_eq!(lines(Verbosity::Opcodes, false), vec!["[0] SSTORE"]);
+
+    // Transactions rejected before execution count as failed.
+    assert_eq!(test_report(false).lines(Verbosity::Failed, true, render), calls);
+}
+
+#[cfg(test)]
+lazy_static! {
+    static ref LOGGED: ::std::sync::Mutex<Vec<String>> = Default::default();
+}
+
+#[cfg(test)]
+struct TestLogger;
+
+#[cfg(test)]
+impl ::log::Log for TestLogger {
+    fn enabled(&self, metadata: &::log::Metadata) -> bool {
+        metadata.target() == "solaris::trace"
+    }
+
+    fn log(&self, record: &::log::Record) {
+        if self.enabled(record.metadata()) {
+            LOGGED.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn test_log_sink() {
+    assert!(!Sink::Log.color());
+
+    static LOGGER: TestLogger = TestLogger;
+    let _ = ::log::set_logger(&LOGGER);
+    ::log::set_max_level(::log::LevelFilter::Info);
+
+    test_report(false).finish(Verbosity::Failed, Sink::Log, false, || "CALL Token::mint".into());
+    test_report(true).finish(Verbosity::Failed, Sink::Log, false, || "CALL Token::burn".into());
+
+    let logged = LOGGED.lock().unwrap();
+    assert!(!logged.contains(&"CALL Token::mint".to_owned()));
+    assert!(logged.contains(&"CALL Token::burn".to_owned()));
+}
+
+/// Kind of a traced call frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Call,
+    CallCode,
+    DelegateCall,
+    StaticCall,
+    Create,
+    /// `SELFDESTRUCT` of `from`, sending `value` to `to`.
+    SelfDestruct,
+}
+
+impl From<vm::CallType> for CallKind {
+    fn from(call_type: vm::CallType) -> Self {
+        match call_type {
+            vm::CallType::CallCode => CallKind::CallCode,
+            vm::CallType::DelegateCall => CallKind::DelegateCall,
+            vm::CallType::StaticCall => CallKind::StaticCall,
+            vm::CallType::None | vm::CallType::Call => CallKind::Call,
+        }
+    }
+}
+
+/// A call or create frame collected during execution.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallTrace {
+    pub kind: CallKind,
+    pub from: Address,
+    /// Called address, or the address of the created contract.
+    pub to: Address,
+    pub value: U256,
+    /// Call data, or init code of the created contract.
+    pub input: Bytes,
+    /// Return data, or code of the created contract.
+    pub output: Bytes,
+    /// Gas used by the frame, `None` if it failed.
+    pub gas_used: Option<U256>,
+    /// Reason of the failure, `None` if the frame succeeded.
+    pub error: Option<String>,
+    /// Frames entered from this one.
+    pub subtraces: Vec<CallTrace>,
+}
+
+impl CallTrace {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn from_call(call: Call, subtraces: Vec<CallTrace>) -> Self {
+        CallTrace {
+            kind: call.call_type.into(),
+            from: call.from,
+            to: call.to,
+            value: call.value,
+            input: call.input,
+            output: Vec::new(),
+            gas_used: None,
+            error: None,
+            subtraces,
+        }
+    }
+
+    /// Whether the frame moves `value` from `from` to `to`.
+    fn transfers_value(&self) -> bool {
+        match self.kind {
+            CallKind::Call | CallKind::Create | CallKind::SelfDestruct => !self.value.is_zero(),
+            // `CALLCODE` sends value to itself, `DELEGATECALL` only passes it along.
+            CallKind::CallCode | CallKind::DelegateCall | CallKind::StaticCall => false,
+        }
+    }
+
+    fn from_create(create: Create, address: Address, subtraces: Vec<CallTrace>) -> Self {
+        CallTrace {
+            kind: CallKind::Create,
+            from: create.from,
+            to: address,
+            value: create.value,
+            input: create.init,
+            output: Vec::new(),
+            gas_used: None,
+            error: None,
+            subtraces,
+        }
+    }
+}
+
+/// `SELFDESTRUCT` executed during a transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfDestruct {
+    /// The destroyed contract.
+    pub address: Address,
+    /// Recipient of the contract balance.
+    pub beneficiary: Address,
+    /// Balance sent to the beneficiary.
+    pub value: U256,
+}
+
+/// Ether moved between accounts during a transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub from: Address,
+    pub to: Address,
+    pub value: U256,
+}
+
+/// Visits every frame whose effects were applied, i.e. skips failed frames
+/// together with everything they called.
+fn visit_applied<F: FnMut(&CallTrace, usize)>(traces: &[CallTrace], depth: usize, f: &mut F) {
+    for trace in traces.iter().filter(|trace| trace.is_success()) {
+        f(trace, depth);
+        visit_applied(&trace.subtraces, depth + 1, f);
+    }
+}
+
+/// Every applied `SELFDESTRUCT`.
+pub fn self_destructs(traces: &[CallTrace]) -> Vec<SelfDestruct> {
+    let mut out = Vec::new();
+    visit_applied(traces, 0, &mut |trace, _| {
+        if trace.kind == CallKind::SelfDestruct {
+            out.push(SelfDestruct {
+                address: trace.from,
+                beneficiary: trace.to,
+                value: trace.value,
+            });
+        }
+    });
+    out
+}
+
+/// Every applied value transfer made by contracts, i.e. value sent with internal
+/// calls and creates and balances sent by `SELFDESTRUCT`.
+///
+/// The value sent by the transaction itself is not included.
+pub fn internal_transfers(traces: &[CallTrace]) -> Vec<Transfer> {
+    let mut out = Vec::new();
+    visit_applied(traces, 0, &mut |trace, depth| {
+        if depth > 0 && trace.transfers_value() {
+            out.push(Transfer {
+                from: trace.from,
+                to: trace.to,
+                value: trace.value,
+            });
+        }
+    });
+    out
+}
+
+/// Addresses of all contracts created, including the one created by the transaction itself.
+pub fn created_contracts(traces: &[CallTrace]) -> Vec<Address> {
+    let mut out = Vec::new();
+    visit_applied(traces, 0, &mut |trace, _| {
+        if trace.kind == CallKind::Create {
+            out.push(trace.to);
+        }
+    });
+    out
+}
+
+#[cfg(test)]
+fn test_frame(kind: CallKind, to: u64, value: u64, subtraces: Vec<CallTrace>) -> CallTrace {
+    CallTrace {
+        kind,
+        from: 1.into(),
+        to: to.into(),
+        value: value.into(),
+        input: vec![],
+        output: vec![],
+        gas_used: Some(0.into()),
+        error: None,
+        subtraces,
+    }
+}
+
+#[test]
+fn test_applied_effects() {
+    let mut reverted = test_frame(CallKind::Call, 9, 0, vec![
+        test_frame(CallKind::Create, 10, 0, vec![]),
+        test_frame(CallKind::SelfDestruct, 11, 7, vec![]),
+    ]);
+    reverted.error = Some("Reverted".into());
+
+    let traces = vec![test_frame(CallKind::Call, 2, 100, vec![
+        test_frame(CallKind::Call, 3, 5, vec![]),
+        test_frame(CallKind::DelegateCall, 4, 5, vec![]),
+        test_frame(CallKind::Create, 5, 1, vec![
+            test_frame(CallKind::SelfDestruct, 6, 1, vec![]),
+        ]),
+        reverted,
+    ])];
+
+    assert_eq!(created_contracts(&traces), vec![5.into()]);
+    assert_eq!(
+        self_destructs(&traces),
+        vec![SelfDestruct { address: 1.into(), beneficiary: 6.into(), value: 1.into() }]
+    );
+    let transfers = internal_transfers(&traces)
+        .into_iter()
+        .map(|transfer| (transfer.to, transfer.value))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        transfers,
+        vec![(3.into(), 5.into()), (5.into(), 1.into()), (6.into(), 1.into())]
+    );
+}
+
+/// The call frame which is about to be entered.
+#[derive(Debug, Clone)]
+struct Frame {
+    address: Address,
+    input: Option<Bytes>,
+    create: bool,
+}
+
+impl Frame {
+    fn label(&self, abi: &AbiRegistry) -> String {
+        if self.create {
+            return format!("{}::constructor", abi.format_address(&self.address));
+        }
+
+        let input = self.input.as_ref().map(|input| &input[..]).unwrap_or(&[]);
+        abi.format_function(&self.address, input)
+    }
+}
+
 #[derive(Debug)]
 pub struct PrintingTracer {
-    vm_enabled: bool,
+    verbosity: Verbosity,
     depth: usize,
     pc: usize,
     instruction: u8,
     stack: Vec<U256>,
+    shared: Rc<Shared>,
+    profiler: Option<profile::Profiler>,
+    abi: Arc<AbiRegistry>,
+    /// Address of the last frame entered from this one (call tracer),
+    /// or the address the frame executes in (vm tracer).
+    address: Cell<Address>,
+    traces: Vec<CallTrace>,
 }
 
 impl Default for PrintingTracer {
     fn default() -> Self {
-        let vm_enabled = ::std::env::var("SOLARIS_VM_TRACES").is_ok();
         PrintingTracer {
-            vm_enabled,
+            verbosity: Verbosity::from_env(),
             depth: 0,
             pc: 0,
             instruction: 0,
             stack: Vec::new(),
+            shared: Default::default(),
+            profiler: None,
+            abi: Default::default(),
+            address: Default::default(),
+            traces: Vec::new(),
         }
     }
 }
@@ -57,7 +544,85 @@ fn bytes_as_str(v: &Option<Bytes>) -> String {
     }
 }
 
+fn value_as_str(v: &vm::ActionValue) -> String {
+    match *v {
+        vm::ActionValue::Transfer(ref value) | vm::ActionValue::Apparent(ref value) => {
+            ::abi::format_uint(value)
+        }
+    }
+}
+
 impl PrintingTracer {
+    /// Returns a call tracer and a vm tracer for a single transaction.
+    ///
+    /// Addresses, calls and return values are printed using `abi`.
+    /// If `profiling` is set the vm tracer collects a gas profile.
+    pub fn pair(abi: Arc<AbiRegistry>, verbosity: Verbosity, profiling: bool) -> (Self, Self) {
+        let tracer = PrintingTracer {
+            verbosity,
+            abi,
+            ..Default::default()
+        };
+        let mut vm_tracer = tracer.child();
+        vm_tracer.depth = 0;
+        if profiling {
+            vm_tracer.profiler = Some(profile::Profiler::new(String::new(), 0));
+        }
+        (tracer, vm_tracer)
+    }
+
+    /// Returns a handle to the trace output, which outlives the tracers.
+    pub fn report(&self) -> Report {
+        Report {
+            shared: self.shared.clone(),
+        }
+    }
+
+    fn child(&self) -> Self {
+        PrintingTracer {
+            verbosity: self.verbosity,
+            depth: self.depth + 1,
+            pc: 0,
+            instruction: 0,
+            stack: Vec::new(),
+            shared: self.shared.clone(),
+            profiler: None,
+            abi: self.abi.clone(),
+            address: Default::default(),
+            traces: Vec::new(),
+        }
+    }
+
+    /// Calls are written line by line only when interleaved with opcodes,
+    /// otherwise the collected call tree is rendered once execution finishes.
+    fn calls_enabled(&self) -> bool {
+        self.verbosity == Verbosity::Opcodes
+    }
+
+    fn line(&self, line: String) {
+        self.shared.lines.borrow_mut().push(line);
+    }
+
+    fn failed(&self) {
+        if self.depth == 0 {
+            self.shared.failed.set(true);
+        }
+    }
+
+    fn enter(&self, params: &vm::ActionParams, create: bool) {
+        self.address.set(params.address);
+        {
+            let mut touched = self.shared.touched.borrow_mut();
+            touched.account(params.sender);
+            touched.account(params.address);
+        }
+        *self.shared.next_frame.borrow_mut() = Some(Frame {
+            address: params.address,
+            input: params.data.clone(),
+            create,
+        });
+    }
+
     fn stack(&self) -> String {
         let items = self.stack.iter().map(u256_as_str).collect::<Vec<_>>();
         format!("[{}]", items.join(","))
@@ -73,100 +638,165 @@ impl PrintingTracer {
 }
 
 impl trace::Tracer for PrintingTracer {
-    type Output = ();
+    type Output = CallTrace;
 
     fn prepare_trace_call(&self, params: &vm::ActionParams) -> Option<Call> {
-        println!(
-            "{d}CALL ({from} --{value:?}--> {to}), data: {data}",
-            d = self.depth(),
-            from = params.sender,
-            value = params.value,
-            to = params.address,
-            data = bytes_as_str(&params.data),
-        );
-        None
+        self.enter(params, false);
+        if self.calls_enabled() {
+            let input = params.data.as_ref().map(|data| &data[..]).unwrap_or(&[]);
+            self.line(format!(
+                "{d}CALL ({from} --{value}--> {call})",
+                d = self.depth(),
+                from = self.abi.format_address(&params.sender),
+                value = value_as_str(&params.value),
+                call = self.abi.format_call(&params.address, input),
+            ));
+        }
+        Some(Call::from(params.clone()))
     }
 
     fn prepare_trace_create(&self, params: &vm::ActionParams) -> Option<Create> {
-        println!(
-            "{d}CREATE ({from} --{value:?}--> NEW), data: {data}",
-            d = self.depth(),
-            from = params.sender,
-            value = params.value,
-            data = bytes_as_str(&params.data),
-        );
-        None
+        self.enter(params, true);
+        if self.calls_enabled() {
+            self.line(format!(
+                "{d}CREATE ({from} --{value}--> NEW), data: {data}",
+                d = self.depth(),
+                from = self.abi.format_address(&params.sender),
+                value = value_as_str(&params.value),
+                data = bytes_as_str(&params.data),
+            ));
+        }
+        Some(Create::from(params.clone()))
     }
 
     fn prepare_trace_output(&self) -> Option<Bytes> {
-        None
+        Some(Vec::new())
     }
 
     fn trace_call(
         &mut self,
-        _call: Option<Call>,
-        _gas_used: U256,
+        call: Option<Call>,
+        gas_used: U256,
         output: Option<Bytes>,
-        _subs: Vec<Self::Output>,
+        subs: Vec<Self::Output>,
     ) {
-        println!("{}<--Output: {} ", self.depth(), bytes_as_str(&output));
+        let call = call.expect("prepare_trace_call always returns call info; qed");
+        let output = output.unwrap_or_default();
+        if self.calls_enabled() {
+            let formatted = self.abi.format_output(&call.to, &call.input, &output);
+            self.line(format!("{}<--Output: {} ", self.depth(), formatted));
+        }
+
+        let mut trace = CallTrace::from_call(call, subs);
+        trace.output = output;
+        trace.gas_used = Some(gas_used);
+        self.traces.push(trace);
     }
 
     /// Stores trace create info.
     fn trace_create(
         &mut self,
-        _create: Option<Create>,
-        _gas_used: U256,
-        _code: Option<Bytes>,
+        create: Option<Create>,
+        gas_used: U256,
+        code: Option<Bytes>,
         address: H160,
-        _subs: Vec<Self::Output>,
+        subs: Vec<Self::Output>,
     ) {
-        println!("{}<--At: {}", self.depth(), address);
+        if self.calls_enabled() {
+            self.line(format!("{}<--At: {}", self.depth(), self.abi.format_address(&address)));
+        }
+
+        let create = create.expect("prepare_trace_create always returns create info; qed");
+        let mut trace = CallTrace::from_create(create, address, subs);
+        trace.output = code.unwrap_or_default();
+        trace.gas_used = Some(gas_used);
+        self.traces.push(trace);
     }
 
     fn trace_failed_call(
         &mut self,
-        _call: Option<Call>,
-        _subs: Vec<Self::Output>,
+        call: Option<Call>,
+        subs: Vec<Self::Output>,
         error: trace::TraceError,
     ) {
-        println!("{}CALL FAILED: {:?}", self.depth(), error);
+        self.failed();
+        let call = call.expect("prepare_trace_call always returns call info; qed");
+        if self.calls_enabled() {
+            let function = self.abi.format_function(&call.to, &call.input);
+            self.line(format!("{}CALL FAILED: {} {:?}", self.depth(), function, error));
+        }
+
+        let mut trace = CallTrace::from_call(call, subs);
+        trace.error = Some(format!("{:?}", error));
+        self.traces.push(trace);
     }
 
     fn trace_failed_create(
         &mut self,
-        _create: Option<Create>,
-        _subs: Vec<Self::Output>,
+        create: Option<Create>,
+        subs: Vec<Self::Output>,
         error: trace::TraceError,
     ) {
-        println!("{}CREATE FAILED: {:?}", self.depth(), error);
+        self.failed();
+        if self.calls_enabled() {
+            self.line(format!("{}CREATE FAILED: {:?}", self.depth(), error));
+        }
+
+        let create = create.expect("prepare_trace_create always returns create info; qed");
+        let mut trace = CallTrace::from_create(create, self.address.get(), subs);
+        trace.error = Some(format!("{:?}", error));
+        self.traces.push(trace);
     }
 
-    fn trace_suicide(&mut self, _address: H160, _balance: U256, _refund_address: H160) {}
+    fn trace_suicide(&mut self, address: H160, balance: U256, refund_address: H160) {
+        self.shared.touched.borrow_mut().account(refund_address);
+        if self.calls_enabled() {
+            self.line(format!(
+                "{}SELFDESTRUCT ({} --{}--> {})",
+                self.depth(),
+                self.abi.format_address(&address),
+                ::abi::format_uint(&balance),
+                self.abi.format_address(&refund_address),
+            ));
+        }
 
+        self.traces.push(CallTrace {
+            kind: CallKind::SelfDestruct,
+            from: address,
+            to: refund_address,
+            value: balance,
+            input: Vec::new(),
+            output: Vec::new(),
+            gas_used: None,
+            error: None,
+            subtraces: Vec::new(),
+        });
+    }
+
+    // rewards are only traced when closing blocks, never for transactions.
     fn trace_reward(&mut self, _author: H160, _value: U256, _reward_type: trace::RewardType) {}
 
     fn subtracer(&self) -> Self
     where
         Self: Sized,
     {
-        let mut vm = PrintingTracer::default();
-        vm.vm_enabled = self.vm_enabled;
-        vm.depth = self.depth + 1;
-        vm
+        self.child()
     }
 
     fn drain(self) -> Vec<Self::Output> {
-        vec![]
+        self.traces
     }
 }
 
 impl trace::VMTracer for PrintingTracer {
-    type Output = ();
+    type Output = profile::GasProfile;
 
     fn trace_next_instruction(&mut self, pc: usize, instruction: u8, current_gas: U256) -> bool {
         self.pc = pc;
         self.instruction = instruction;
+        if let Some(ref mut profiler) = self.profiler {
+            profiler.next_instruction(instruction, current_gas);
+        }
         true
     }
 
@@ -175,9 +805,20 @@ impl trace::VMTracer for PrintingTracer {
         gas_used: U256,
         stack_push: &[U256],
         _mem_diff: Option<(usize, &[u8])>,
-        _store_diff: Option<(U256, U256)>,
+        store_diff: Option<(U256, U256)>,
     ) {
-        if !self.vm_enabled {
+        if let Some((key, _)) = store_diff {
+            self.shared
+                .touched
+                .borrow_mut()
+                .storage(self.address.get(), key.into());
+        }
+
+        if let Some(ref mut profiler) = self.profiler {
+            profiler.executed(gas_used);
+        }
+
+        if self.verbosity < Verbosity::Opcodes {
             return;
         }
 
@@ -188,7 +829,7 @@ impl trace::VMTracer for PrintingTracer {
             .truncate(if len > info.args { len - info.args } else { 0 });
         self.stack.extend_from_slice(stack_push);
 
-        println!(
+        let line = format!(
             "{}[{}] {}({:x}) stack_after: {}, gas_left: {}",
             self.depth(),
             self.pc,
@@ -197,21 +838,36 @@ impl trace::VMTracer for PrintingTracer {
             self.stack(),
             gas_used,
         );
+        self.line(line);
     }
 
     fn prepare_subtrace(&self, _code: &[u8]) -> Self
     where
         Self: Sized,
     {
-        let mut vm = PrintingTracer::default();
-        vm.vm_enabled = self.vm_enabled;
-        vm.depth = self.depth + 1;
+        let mut vm = self.child();
+        let frame = self.shared.next_frame.borrow_mut().take();
+        if let Some(ref frame) = frame {
+            vm.address.set(frame.address);
+        }
+        if let Some(ref profiler) = self.profiler {
+            let label = frame
+                .map(|frame| frame.label(&self.abi))
+                .unwrap_or_else(|| "unknown".into());
+            vm.profiler = Some(profiler.subprofiler(label));
+        }
         vm
     }
 
-    fn done_subtrace(&mut self, _sub: Self) {}
+    fn done_subtrace(&mut self, sub: Self) {
+        if let (Some(profiler), Some(sub)) = (self.profiler.as_mut(), sub.profiler) {
+            profiler.done_subprofiler(sub);
+        }
+    }
 
     fn drain(self) -> Option<Self::Output> {
-        None
+        self.profiler.map(|profiler| profile::GasProfile {
+            frames: profiler.into_frame().children,
+        })
     }
 }
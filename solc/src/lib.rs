@@ -34,10 +34,22 @@ mod platform {
     }
 }
 
+extern crate serde_json;
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::io::{BufRead, Write as IoWrite};
 use std::path::Path;
-use std::{fs, io};
+use std::{env, fs, io};
+
+/// Name of the signature database written by `compile` in build scripts,
+/// `solaris` loads it by default.
+pub const SIGNATURES_FILE: &str = "solaris-signatures.txt";
 
 /// Compiles all solidity files in given directory.
+///
+/// When run from a build script, the signatures of the compiled contracts are
+/// added to the signature database in `target/<profile>`, see `write_signatures`.
 pub fn compile<T: AsRef<Path>>(path: T) {
     let mut command = platform::solc();
     command
@@ -55,13 +67,160 @@ pub fn compile<T: AsRef<Path>>(path: T) {
     }
 
     let child = command
-        .current_dir(path)
+        .current_dir(&path)
         .status()
         .unwrap_or_else(|e| panic!("Error compiling solidity contracts: {}", e));
     assert!(
         child.success(),
         "There was an error while compiling contracts code."
     );
+
+    if let Ok(out_dir) = env::var("OUT_DIR") {
+        // `OUT_DIR` is `target/<profile>/build/<crate>/out`,
+        // test binaries run from `target/<profile>/deps`
+        let profile_dir = Path::new(&out_dir)
+            .parent()
+            .and_then(Path::parent)
+            .and_then(Path::parent);
+        if let Some(profile_dir) = profile_dir {
+            write_signatures(&path, profile_dir.join(SIGNATURES_FILE));
+        }
+    }
+}
+
+/// Generates a Rust `#[test]` for every Solidity test compiled into given directory,
+/// to be included in an integration test:
+///
+/// ```ignore
+/// include!(concat!(env!("OUT_DIR"), "/solidity_tests.rs"));
+/// ```
+///
+/// Test functions are the public `test*` functions without parameters, see
+/// `solaris::runner`. Must be called after `compile`.
+pub fn generate_tests<T: AsRef<Path>, U: AsRef<Path>>(path: T, out: U) {
+    let mut code = String::new();
+    for (name, abi, bin) in artifacts(&path).expect("Contracts directory is not readable.") {
+        let contract = name.rsplit("_sol_").next().expect("rsplit always yields an item; qed");
+        let tests = test_functions(&abi)
+            .unwrap_or_else(|e| panic!("Invalid ABI of {}: {}", name, e));
+        for test in tests {
+            writeln!(
+                code,
+                "#[test]\n\
+                 #[allow(non_snake_case)]\n\
+                 fn {name}__{test}() {{\n    \
+                     ::solaris::runner::TestContract::from_artifacts({contract:?}, include_bytes!({abi:?}), include_str!({bin:?}))\n        \
+                         .expect(\"Valid test contract artifacts\")\n        \
+                         .run({test:?})\n        \
+                         .assert();\n\
+                 }}\n",
+                name = name,
+                test = test,
+                contract = contract,
+                abi = abi,
+                bin = bin,
+            ).expect("Writing to a string never fails; qed");
+        }
+    }
+    fs::write(out, code).expect("Unable to write generated tests.");
+}
+
+/// Adds the signatures of all functions and events compiled into given directory
+/// to the signature database file `out`, one canonical signature per line.
+/// Signatures already in the file are kept. Must be called after `compile`.
+pub fn write_signatures<T: AsRef<Path>, U: AsRef<Path>>(path: T, out: U) {
+    let mut signatures = BTreeSet::new();
+    if let Ok(file) = fs::File::open(&out) {
+        for line in io::BufReader::new(file).lines() {
+            let line = line.expect("Unable to read signatures.");
+            if !line.trim().is_empty() {
+                signatures.insert(line.trim().to_owned());
+            }
+        }
+    }
+
+    for entry in fs::read_dir(&path).expect("Contracts directory is not readable.") {
+        let path = entry.expect("Contracts directory is not readable.").path();
+        if path.extension().map_or(true, |ext| ext != "abi") {
+            continue;
+        }
+        let abi = fs::read(&path).expect("Unable to read ABI.");
+        let abi: serde_json::Value = serde_json::from_slice(&abi)
+            .unwrap_or_else(|e| panic!("Invalid ABI {}: {}", path.display(), e));
+        for entry in abi.as_array().into_iter().flat_map(|entries| entries) {
+            if entry["type"] == "function" || entry["type"] == "event" {
+                signatures.extend(signature(entry));
+            }
+        }
+    }
+
+    let mut file = io::BufWriter::new(fs::File::create(out).expect("Unable to write signatures."));
+    for signature in signatures {
+        writeln!(file, "{}", signature).expect("Unable to write signatures.");
+    }
+}
+
+/// Canonical signature of an ABI entry, e.g. `approve(address,uint256)`.
+fn signature(entry: &serde_json::Value) -> Option<String> {
+    let name = entry["name"].as_str()?;
+    let kinds = canonical_types(&entry["inputs"])?;
+    Some(format!("{}({})", name, kinds))
+}
+
+fn canonical_types(params: &serde_json::Value) -> Option<String> {
+    let kinds = params
+        .as_array()?
+        .iter()
+        .map(|param| {
+            let kind = param["type"].as_str()?;
+            if kind.starts_with("tuple") {
+                // `tuple[]` becomes `(...)[]`
+                Some(format!("({}){}", canonical_types(&param["components"])?, &kind["tuple".len()..]))
+            } else {
+                Some(kind.to_owned())
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(kinds.join(","))
+}
+
+/// Compiled contracts with code, as `(name, abi path, bin path)`.
+fn artifacts<T: AsRef<Path>>(path: T) -> io::Result<Vec<(String, String, String)>> {
+    let mut artifacts = Vec::new();
+
+    for entry in fs::read_dir(&path)? {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "abi") {
+            continue;
+        }
+        let bin = path.with_extension("bin");
+        // abstract contracts and interfaces have no code
+        if fs::metadata(&bin).map(|meta| meta.len() == 0).unwrap_or(true) {
+            continue;
+        }
+        let name = path.file_stem().and_then(|stem| stem.to_str());
+        if let (Some(name), Some(abi), Some(bin)) = (name, path.to_str(), bin.to_str()) {
+            artifacts.push((name.into(), abi.into(), bin.into()));
+        }
+    }
+
+    artifacts.sort();
+    Ok(artifacts)
+}
+
+fn test_functions(abi_path: &str) -> Result<Vec<String>, String> {
+    let abi = fs::read(abi_path).map_err(|e| e.to_string())?;
+    let abi: serde_json::Value = serde_json::from_slice(&abi).map_err(|e| e.to_string())?;
+    let entries = abi.as_array().ok_or("ABI is not an array")?;
+
+    Ok(entries
+        .iter()
+        .filter(|entry| entry["type"] == "function")
+        .filter(|entry| entry["inputs"].as_array().map_or(true, |inputs| inputs.is_empty()))
+        .filter_map(|entry| entry["name"].as_str())
+        .filter(|name| name.starts_with("test"))
+        .map(Into::into)
+        .collect())
 }
 
 fn sol_files<T: AsRef<Path>>(path: T) -> io::Result<Vec<String>> {
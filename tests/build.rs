@@ -16,6 +16,13 @@
 
 extern crate solc;
 
+use std::env;
+use std::path::Path;
+
 fn main() {
-    solc::compile(concat!(env!("CARGO_MANIFEST_DIR"), "/contracts/"));
+    let contracts = concat!(env!("CARGO_MANIFEST_DIR"), "/contracts/");
+    solc::compile(contracts);
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    solc::generate_tests(contracts, Path::new(&out_dir).join("solidity_tests.rs"));
 }
@@ -23,3 +23,250 @@ contract EventLogTest {
         Bar(value);
     }
 }
+
+contract Child {
+    function Child() payable public {}
+
+    function destroy(address beneficiary) public {
+        selfdestruct(beneficiary);
+    }
+}
+
+contract FactoryTest {
+    event Created(address child);
+
+    function create() payable public returns(address) {
+        Child child = (new Child).value(msg.value)();
+        Created(child);
+        return child;
+    }
+
+    function createAndDestroy(address beneficiary) payable public {
+        Child child = (new Child).value(msg.value)();
+        child.destroy(beneficiary);
+    }
+}
+
+contract MockUser {
+    function senderOf(address target) public returns(address) {
+        return GetSenderTest(target).getSender();
+    }
+}
+
+contract Hevm {
+    function warp(uint256 timestamp) public;
+    function roll(uint256 number) public;
+    function deal(address who, uint256 balance) public;
+    function store(address who, bytes32 slot, bytes32 value) public;
+    function prank(address sender) public;
+    function expectRevert() public;
+    function recordLogs() public;
+}
+
+contract CheatcodeTest {
+    uint public stored;
+    address public lastSender;
+    Hevm hevm = Hevm(0x7109709ECfa91a80626fF3989D68f67F5b1DD12D);
+
+    event Recorded(address sender);
+
+    function cheat(address who) public {
+        hevm.warp(1000);
+        require(block.timestamp == 1000);
+        uint number = block.number;
+        hevm.roll(number + 10);
+        require(block.number == number + 10);
+        hevm.deal(who, 5 ether);
+        require(who.balance == 5 ether);
+        hevm.store(this, bytes32(0), bytes32(42));
+        require(stored == 42);
+
+        hevm.prank(who);
+        this.record();
+        require(lastSender == who);
+        this.record();
+        require(lastSender == address(this));
+    }
+
+    function record() public {
+        lastSender = msg.sender;
+        Recorded(msg.sender);
+    }
+
+    function fail() public {
+        revert();
+    }
+
+    function expectRevertOf(bytes4 selector) public {
+        hevm.expectRevert();
+        require(this.call(selector));
+    }
+
+    function recordSecond() public {
+        this.record();
+        hevm.recordLogs();
+        this.record();
+    }
+
+    function undoneWarp() public {
+        hevm.warp(2000);
+        revert();
+    }
+
+    function warpInFailedCall() public {
+        this.call(bytes4(keccak256("undoneWarp()")));
+        require(block.timestamp != 2000);
+    }
+}
+
+contract DSTestLike {
+    bool public failed;
+
+    event log_named_uint(bytes32 key, uint val);
+
+    function assertEq(uint a, uint b) internal {
+        if (a != b) {
+            log_named_uint("  Expected", b);
+            log_named_uint("    Actual", a);
+            failed = true;
+        }
+    }
+}
+
+contract RunnerTest is DSTestLike {
+    uint value;
+
+    function setUp() public {
+        value = 1;
+    }
+
+    function testSetUpRan() public {
+        assertEq(value, 1);
+    }
+
+    function testIsolated() public {
+        value = 2;
+        assertEq(value, 2);
+    }
+
+    function testLogs() public {
+        log_named_uint("value", value);
+    }
+
+    function testFailRevert() public {
+        revert();
+    }
+
+    function testFailAssertion() public {
+        assertEq(value, 2);
+    }
+}
+
+contract ConsoleTest {
+    address constant console = 0x000000000000000000636F6e736F6c652e6c6f67;
+
+    function logValue(uint value) public {
+        console.call(bytes4(keccak256("log(uint256)")), value);
+    }
+}
+
+contract Counter {
+    uint public count;
+
+    function increment() public {
+        count += 1;
+    }
+
+    function add(uint value) public {
+        require(value < 10);
+        count += value;
+    }
+
+    function reset() public {
+        count = 0;
+    }
+}
+
+contract CounterV2 {
+    uint public count;
+
+    function increment() public {
+        count += 1;
+    }
+
+    function add(uint value) public {
+        require(value < 1000);
+        count += value;
+    }
+
+    function reset() public {
+        count = 0;
+    }
+}
+
+contract SignatureTest {
+    function recover(bytes32 hash, uint8 v, bytes32 r, bytes32 s) public returns(address) {
+        return ecrecover(hash, v, r, s);
+    }
+}
+
+contract GasTest {
+    uint[] public values;
+
+    function fill(uint count) public {
+        for (uint i = 0; i < count; i++) {
+            values.push(i);
+        }
+    }
+}
+
+contract GasCaller {
+    function callFill(GasTest target, uint count) public {
+        target.fill(count);
+    }
+}
+
+contract ViewTest {
+    uint public counter;
+
+    event Touched(uint number);
+
+    function sneaky() public returns(uint) {
+        counter += 1;
+        return counter;
+    }
+
+    function touch() public returns(uint) {
+        Touched(block.number);
+        return block.number;
+    }
+
+    function number() public returns(uint) {
+        return block.number;
+    }
+}
+
+contract FirstComeTest {
+    address public winner;
+
+    function claim() public {
+        if (winner == 0) {
+            winner = msg.sender;
+        }
+    }
+}
+
+contract TokenTest {
+    mapping(address => uint) public balanceOf;
+
+    function TokenTest() public {
+        balanceOf[msg.sender] = 1000;
+    }
+
+    function transfer(address to, uint value) public returns(bool) {
+        require(balanceOf[msg.sender] >= value);
+        balanceOf[msg.sender] -= value;
+        balanceOf[to] += value;
+        return true;
+    }
+}
@@ -23,6 +23,7 @@ extern crate ethereum_types as types;
 extern crate rustc_hex;
 extern crate solaris;
 
+use ethabi::ContractFunction;
 use rustc_hex::FromHex;
 use types::{Address, U256};
 
@@ -140,3 +141,946 @@ fn logs_should_get_collected_and_retrieved_correctly() {
     let baz_logs = evm.logs_for_event(contract.events().baz());
     assert_eq!(baz_logs.len(), 0);
 }
+
+#[test]
+fn profiler_should_attribute_gas_to_opcodes() {
+    let contract = event_log_test::EventLogTest::default();
+    let code_hex = include_str!("../contracts/test_sol_EventLogTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+
+    let mut evm = solaris::evm();
+    evm.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+
+    evm.enable_profiler()
+        .transact(contract.functions().emit_bar(100))
+        .unwrap();
+
+    let profile = evm.profile().expect("profiler is enabled");
+    assert_eq!(profile.frames.len(), 1);
+    assert!(profile.frames[0].total_gas() > 0);
+    assert_eq!(profile.opcodes()["LOG1"].count, 1);
+    assert!(profile.folded().contains(";LOG1 "));
+}
+
+#[test]
+fn logs_of_registered_contracts_should_be_decoded() {
+    let contract = event_log_test::EventLogTest::default();
+    let code_hex = include_str!("../contracts/test_sol_EventLogTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+    let abi = include_bytes!("../contracts/test_sol_EventLogTest.abi");
+
+    let mut evm = solaris::evm();
+    let contract_address = evm.deploy_artifact("EventLogTest", abi, &code_bytes)
+        .expect("contract deployment should succeed");
+
+    let alice = 10.into();
+    evm.set_label(alice, "alice");
+    evm.with_sender(alice)
+        .transact(contract.functions().emit_foo())
+        .unwrap();
+
+    let logs = evm.decoded_logs();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].address, contract_address);
+    assert_eq!(logs[0].event, "Foo");
+    assert_eq!(logs[0].params[0].name, "sender");
+
+    assert_eq!(
+        evm.abi().format_address(&contract_address),
+        format!("EventLogTest({:?})", contract_address)
+    );
+    assert_eq!(evm.abi().format_address(&alice), "alice");
+}
+
+#[test]
+fn signature_database_should_be_seeded_from_compiled_abis() {
+    let signatures = solaris::signatures::SignatureDb::from_abi_dir(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/contracts"
+    )).unwrap();
+
+    let contract = get_sender_test::GetSenderTest::default();
+    let input = contract.functions().get_sender().encoded();
+    assert_eq!(signatures.function(&input), Some("getSender()"));
+
+    let unknown: Address = 0x42.into();
+    let mut evm = solaris::evm();
+    evm.add_signatures(signatures);
+    assert_eq!(
+        evm.abi().format_call(&unknown, &input),
+        format!("{:?}::getSender()", unknown)
+    );
+
+    // the build script compiled the contracts, so every `Evm` knows them
+    let compiled = solaris::signatures::compiled().expect("contracts are compiled by the build script");
+    assert_eq!(compiled.function(&input), Some("getSender()"));
+    assert_eq!(
+        solaris::evm().abi().format_call(&unknown, &input),
+        format!("{:?}::getSender()", unknown)
+    );
+}
+
+#[test]
+fn transaction_trace_should_be_rendered_as_tree() {
+    let contract = event_log_test::EventLogTest::default();
+    let code_hex = include_str!("../contracts/test_sol_EventLogTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+    let abi = include_bytes!("../contracts/test_sol_EventLogTest.abi");
+
+    let mut evm = solaris::evm();
+    evm.deploy_artifact("EventLogTest", abi, &code_bytes)
+        .expect("contract deployment should succeed");
+
+    let output = evm.transact(contract.functions().emit_bar(100)).unwrap();
+    assert_eq!(output.trace().len(), 1);
+    assert!(output.trace()[0].is_success());
+
+    let rendered = evm.render_trace(&output);
+    assert!(rendered.contains("EventLogTest("));
+    assert!(rendered.contains("::emitBar(value=100)"));
+    assert!(rendered.contains("emit "));
+    assert!(rendered.contains("::Bar(value=100)"));
+}
+
+use_contract!(factory_test, "FactoryTest", "contracts/test_sol_FactoryTest.abi");
+
+#[test]
+fn internal_creates_transfers_and_self_destructs_should_be_recorded() {
+    let contract = factory_test::FactoryTest::default();
+    let code_hex = include_str!("../contracts/test_sol_FactoryTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+
+    let mut evm = solaris::evm();
+    let factory = evm.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+
+    let sender = 5.into();
+    let beneficiary: Address = 6.into();
+    let value = solaris::wei::from_ether(1);
+    let output = evm.with_sender(sender)
+        .with_value(value)
+        .ensure_funds()
+        .transact(contract.functions().create_and_destroy(beneficiary))
+        .unwrap();
+
+    let created = output.created_contracts();
+    assert_eq!(created.len(), 1);
+
+    let self_destructs = output.self_destructs();
+    assert_eq!(self_destructs.len(), 1);
+    assert_eq!(self_destructs[0].address, created[0]);
+    assert_eq!(self_destructs[0].beneficiary, beneficiary);
+    assert_eq!(self_destructs[0].value, value);
+
+    let transfers = output.internal_transfers();
+    assert_eq!(transfers.len(), 2);
+    assert_eq!((transfers[0].from, transfers[0].to), (factory, created[0]));
+    assert_eq!((transfers[1].from, transfers[1].to), (created[0], beneficiary));
+}
+
+use_contract!(mock_user, "MockUser", "contracts/test_sol_MockUser.abi");
+
+#[test]
+fn mocks_should_return_programmed_responses_and_record_calls() {
+    let contract = mock_user::MockUser::default();
+    let get_sender = get_sender_test::GetSenderTest::default();
+    let code_hex = include_str!("../contracts/test_sol_MockUser.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+
+    let mut evm = solaris::evm();
+    let user = evm.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+
+    let target: Address = 0x10.into();
+    let mocked: Address = 0x20.into();
+    evm.mock(target)
+        .on(get_sender.functions().get_sender())
+        .returns(&[ethabi::Token::Address(mocked)]);
+
+    let output: Address = evm.call(contract.functions().sender_of(target)).unwrap();
+    assert_eq!(output, mocked);
+
+    let calls = evm.mock_calls(&target).to_vec();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].from, user);
+    assert!(calls[0].matches(&get_sender.functions().get_sender()));
+
+    evm.mock(target)
+        .on_selector(get_sender.functions().get_sender())
+        .reverts("Mocked");
+    match *evm.call(contract.functions().sender_of(target)).unwrap_err().kind() {
+        solaris::error::ErrorKind::Reverted(_) => {}
+        ref kind => panic!("Unexpected error {}", kind),
+    }
+    let get_sender_call = solaris::abi::selector("getSender()").to_vec();
+    match *evm.call_to(target, get_sender_call).unwrap_err().kind() {
+        solaris::error::ErrorKind::Reverted(ref data) => {
+            assert_eq!(data[..4], solaris::abi::selector("Error(string)"));
+            assert_eq!(
+                ethabi::decode(&[ethabi::ParamType::String], &data[4..]).unwrap(),
+                vec![ethabi::Token::String("Mocked".into())]
+            );
+        }
+        ref kind => panic!("Unexpected error {}", kind),
+    }
+    assert_eq!(evm.mock_calls(&target).len(), 3);
+    assert_eq!(evm.account(&user).map(|account| account.code.is_empty()), Some(false));
+}
+
+use_contract!(cheatcode_test, "CheatcodeTest", "contracts/test_sol_CheatcodeTest.abi");
+
+#[test]
+fn cheatcodes_should_take_effect_within_the_calling_transaction() {
+    let contract = cheatcode_test::CheatcodeTest::default();
+    let code_hex = include_str!("../contracts/test_sol_CheatcodeTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+
+    let mut evm = solaris::evm();
+    evm.enable_cheatcodes();
+    evm.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+
+    // the contract checks each effect right after the cheatcode call
+    let who: Address = 7.into();
+    let number = evm.block_env().number;
+    let output = evm.transact(contract.functions().cheat(who)).unwrap();
+    assert!(output.is_success());
+    assert_eq!(
+        evm.account(&who).map(|account| account.balance),
+        Some(solaris::wei::from_ether(5))
+    );
+    let stored: U256 = evm.call(contract.functions().stored()).unwrap();
+    assert_eq!(stored, 42.into());
+    assert_eq!(evm.block_env().timestamp, 1000);
+    assert_eq!(evm.block_env().number, number + 10);
+
+    // cheatcodes called from a failed call are undone with it
+    let output = evm.transact(contract.functions().warp_in_failed_call()).unwrap();
+    assert!(output.is_success());
+    assert_eq!(evm.block_env().timestamp, 1000);
+
+    let fail = solaris::abi::selector("fail()");
+    let record = solaris::abi::selector("record()");
+    let output = evm.transact(contract.functions().expect_revert_of(fail)).unwrap();
+    assert!(output.is_success());
+    let transactions = evm.transactions().len();
+    assert!(evm.transact(contract.functions().expect_revert_of(record)).is_err());
+    assert_eq!(evm.transactions().len(), transactions);
+
+    evm.transact(contract.functions().record_second()).unwrap();
+    assert_eq!(evm.recorded_logs().len(), 1);
+}
+
+#[test]
+fn solidity_tests_should_run_from_snapshot_after_set_up() {
+    let contract = solaris::runner::TestContract::from_artifacts(
+        "RunnerTest",
+        include_bytes!("../contracts/test_sol_RunnerTest.abi"),
+        include_str!("../contracts/test_sol_RunnerTest.bin"),
+    ).unwrap();
+
+    let results = contract.run_all();
+    assert_eq!(results.len(), 5);
+    for result in &results {
+        assert!(result.passed, "{}", result);
+    }
+
+    let logs = results
+        .iter()
+        .find(|result| result.name == "RunnerTest::testLogs")
+        .map(|result| result.logs.clone());
+    assert_eq!(logs, Some(vec!["value: 1".to_owned()]));
+}
+
+// one `#[test]` per Solidity test, generated by `solc::generate_tests`
+include!(concat!(env!("OUT_DIR"), "/solidity_tests.rs"));
+
+use_contract!(console_test, "ConsoleTest", "contracts/test_sol_ConsoleTest.abi");
+
+#[test]
+fn console_log_messages_should_be_collected_and_rendered() {
+    let contract = console_test::ConsoleTest::default();
+    let code_hex = include_str!("../contracts/test_sol_ConsoleTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+
+    let mut evm = solaris::evm();
+    evm.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+
+    let output = evm.transact(contract.functions().log_value(5)).unwrap();
+    assert_eq!(evm.console_logs(), &["5".to_owned()]);
+    assert!(evm.render_trace(&output).contains("console.log(\"5\")"));
+}
+
+#[test]
+fn fuzzer_should_shrink_failing_arguments() {
+    let abi = ethabi::Contract::load(&include_bytes!("../contracts/test_sol_EventLogTest.abi")[..]).unwrap();
+    let code_hex = include_str!("../contracts/test_sol_EventLogTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+
+    let mut evm = solaris::evm();
+    let address = evm.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+    let emit_bar = abi.function("emitBar").unwrap();
+
+    let mut fuzzer = solaris::fuzz::Fuzzer::default();
+    fuzzer.with_runs(64).with_seed(7);
+
+    fuzzer.assert(&mut evm, address, emit_bar, |_, _, output| {
+        let output = output.as_ref().map_err(|e| e.to_string())?;
+        if output.logs().len() == 1 {
+            Ok(())
+        } else {
+            Err("Bar was not emitted".into())
+        }
+    });
+
+    let counterexample = fuzzer
+        .check(&mut evm, address, emit_bar, |_, tokens, _| match tokens[0] {
+            ethabi::Token::Uint(value) if value >= 1000.into() => Err("too large".into()),
+            _ => Ok(()),
+        })
+        .expect_err("large values are generated");
+    assert_eq!(counterexample.tokens.len(), 1);
+    match counterexample.tokens[0] {
+        ethabi::Token::Uint(value) => assert!(value >= 1000.into() && value < 2000.into()),
+        ref token => panic!("Unexpected token {:?}", token),
+    }
+    assert!(counterexample.reproducer().contains("ethabi::Token::Uint("));
+}
+
+#[test]
+fn invariant_failures_should_be_shrunk_and_replayable() {
+    let abi = ethabi::Contract::load(&include_bytes!("../contracts/test_sol_Counter.abi")[..]).unwrap();
+    let code_hex = include_str!("../contracts/test_sol_Counter.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+
+    let mut evm = solaris::evm();
+    let counter = evm.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+
+    let mut tester = solaris::invariant::InvariantTester::default();
+    tester
+        .with_runs(32)
+        .with_depth(8)
+        .with_seed(3)
+        .with_actors(vec![10.into(), 11.into()])
+        .with_target(counter, abi);
+
+    let count_below_five = |evm: &mut solaris::evm::Evm| -> Result<(), String> {
+        let output = evm.call_to(counter, solaris::abi::selector("count()").to_vec())
+            .map_err(|e| e.to_string())?;
+        if U256::from(&output[..]) < 5.into() {
+            Ok(())
+        } else {
+            Err("count reached 5".to_owned())
+        }
+    };
+
+    let failure = tester
+        .check(&mut evm, count_below_five)
+        .expect_err("count can be increased to 5");
+    assert!(!failure.steps.is_empty() && failure.steps.len() <= 5);
+    assert_eq!(failure.reason, "count reached 5");
+
+    // the same seed finds the same sequence
+    assert_eq!(tester.check(&mut evm, count_below_five), Err(failure));
+}
+
+#[test]
+fn differential_testing_should_report_the_first_divergence() {
+    let abi = ethabi::Contract::load(&include_bytes!("../contracts/test_sol_Counter.abi")[..]).unwrap();
+    let old = include_str!("../contracts/test_sol_Counter.bin").from_hex().unwrap();
+    let new = include_str!("../contracts/test_sol_CounterV2.bin").from_hex().unwrap();
+    let get_sender = get_sender_test::GetSenderTest::default();
+
+    let mut evm = solaris::evm();
+    evm.deploy(&include_str!("../contracts/test_sol_GetSenderTest.bin").from_hex().unwrap())
+        .expect("contract deployment should succeed");
+    let mut tester = solaris::differential::DifferentialTester::new(abi);
+    tester
+        .with_runs(64)
+        .with_depth(4)
+        .with_seed(5)
+        .with_storage(vec![0.into()]);
+
+    tester.assert(&mut evm, &old, &old);
+
+    let divergence = tester
+        .check(&mut evm, &old, &new)
+        .expect_err("values from 10 to 999 are accepted by the new version only");
+    assert_eq!(divergence.what, "status");
+    assert!(divergence.calls.last().unwrap().contains("add("));
+    assert_eq!(tester.check(&mut evm, &old, &new), Err(divergence));
+
+    // the current contract is kept
+    let sender: Address = evm.call(get_sender.functions().get_sender()).unwrap();
+    assert_eq!(sender, evm.sender());
+}
+
+#[test]
+fn test_accounts_should_send_signed_transactions() {
+    let mut evm = solaris::evm();
+    evm.with_accounts(solaris::accounts::DEFAULT_SEED, 2);
+    let alice = evm.test_account("alice").unwrap().address();
+    let bob = evm.test_account("bob").unwrap().address();
+    assert_eq!(evm.accounts()[0].address(), alice);
+    assert_eq!(evm.abi().label(&bob), Some("bob"));
+
+    let mut more = solaris::evm();
+    more.with_accounts(solaris::accounts::DEFAULT_SEED, 1)
+        .with_accounts(solaris::accounts::DEFAULT_SEED, 1);
+    let names: Vec<&str> = more.accounts().iter().map(|account| account.name()).collect();
+    assert_eq!(names, vec!["alice", "bob"]);
+    assert_eq!(more.accounts()[1].address(), bob);
+
+    let code_hex = include_str!("../contracts/test_sol_EventLogTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+    evm.with_sender(alice)
+        .with_gas_price(solaris::wei::from_gwei(1))
+        .deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+    assert!(evm.account(&alice).unwrap().balance < solaris::wei::from_mether(1));
+
+    let contract = event_log_test::EventLogTest::default();
+    evm.with_sender(bob)
+        .transact(contract.functions().emit_foo())
+        .unwrap();
+
+    let foo_logs = evm.logs_for_event(contract.events().foo());
+    assert_eq!(Address::from(foo_logs[0].sender), bob);
+    assert_eq!(evm.account(&bob).unwrap().nonce, 1.into());
+}
+
+#[test]
+fn signatures_of_test_accounts_should_be_recovered_by_ecrecover() {
+    let abi = ethabi::Contract::load(&include_bytes!("../contracts/test_sol_SignatureTest.abi")[..]).unwrap();
+    let code_hex = include_str!("../contracts/test_sol_SignatureTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+
+    let mut evm = solaris::evm();
+    evm.with_accounts(solaris::accounts::DEFAULT_SEED, 1);
+    let alice = evm.accounts()[0].clone();
+    let address = evm.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+
+    let recover = |evm: &mut solaris::evm::Evm, hash: types::H256, signature: solaris::signing::Signature| {
+        let mut tokens = vec![ethabi::Token::FixedBytes(hash.to_vec())];
+        tokens.extend(signature.tokens());
+        let data = abi.function("recover").unwrap().encode_input(&tokens).unwrap();
+        let output = evm.call_to(address, data).unwrap();
+        Address::from(&output[12..])
+    };
+
+    let hash = solaris::signing::eth_message_hash(b"hello");
+    assert_eq!(recover(&mut evm, hash, alice.eth_sign(b"hello")), alice.address());
+
+    let mut types = solaris::signing::Eip712Types::default();
+    types.with_type("Transfer(address to,uint256 amount)").unwrap();
+    let mut domain = solaris::signing::Domain::default();
+    domain.with_name("Test").with_chain_id(1).with_verifying_contract(address);
+    let transfer = solaris::signing::Value::Struct(vec![
+        ethabi::Token::Address(2.into()).into(),
+        ethabi::Token::Uint(100.into()).into(),
+    ]);
+    let hash = types.signing_hash(&domain, "Transfer", &transfer).unwrap();
+    let signature = alice
+        .sign_typed_data(&types, &domain, "Transfer", &transfer)
+        .unwrap();
+    assert_eq!(recover(&mut evm, hash, signature), alice.address());
+    assert_eq!(signature.packed().len(), 65);
+}
+
+#[test]
+fn invalid_transactions_should_be_rejected_with_distinct_errors() {
+    use solaris::error::ErrorKind;
+    use solaris::evm::TxRequest;
+
+    let mut evm = solaris::evm();
+    evm.with_accounts(solaris::accounts::DEFAULT_SEED, 1);
+    let alice = evm.accounts()[0].address();
+
+    let code_hex = include_str!("../contracts/test_sol_EventLogTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+    let output = evm.send_transaction(TxRequest::create(alice, code_bytes).with_nonce(0.into()))
+        .expect("contract deployment should succeed");
+    let address = output.created_contracts()[0];
+
+    let contract = event_log_test::EventLogTest::default();
+    let emit_foo = TxRequest::call(alice, address, contract.functions().emit_foo().encoded());
+
+    match *evm.send_transaction(emit_foo.clone().with_nonce(5.into())).unwrap_err().kind() {
+        ErrorKind::InvalidNonce(expected, got) => assert_eq!((expected, got), (1.into(), 5.into())),
+        ref kind => panic!("Unexpected error {}", kind),
+    }
+    match *evm.send_transaction(emit_foo.clone().with_nonce(0.into())).unwrap_err().kind() {
+        ErrorKind::InvalidNonce(expected, got) => assert_eq!((expected, got), (1.into(), 0.into())),
+        ref kind => panic!("Unexpected error {}", kind),
+    }
+    match *evm.send_transaction(emit_foo.clone().with_chain_id(42)).unwrap_err().kind() {
+        ErrorKind::WrongChain(expected, got) => assert_eq!((expected, got), (evm.chain_id(), 42)),
+        ref kind => panic!("Unexpected error {}", kind),
+    }
+    match *evm.send_transaction(emit_foo.clone().with_gas(20_000.into())).unwrap_err().kind() {
+        ErrorKind::IntrinsicGas(required, got) => assert!(required > 21_000.into() && got == 20_000.into()),
+        ref kind => panic!("Unexpected error {}", kind),
+    }
+    let too_much = solaris::wei::from_mether(2);
+    match *evm.send_transaction(emit_foo.clone().with_value(too_much)).unwrap_err().kind() {
+        ErrorKind::InsufficientBalance(required, _) => assert_eq!(required, too_much),
+        ref kind => panic!("Unexpected error {}", kind),
+    }
+    match *evm.send_transaction(&TxRequest::call(3.into(), address, vec![])).unwrap_err().kind() {
+        ErrorKind::MissingKey(address) => assert_eq!(address, 3.into()),
+        ref kind => panic!("Unexpected error {}", kind),
+    }
+
+    evm.send_transaction(emit_foo.clone().with_chain_id(42).with_replay_protection(false))
+        .expect("unprotected transactions are valid on any chain");
+    evm.send_transaction(emit_foo.clone().with_nonce(2.into()))
+        .expect("the next nonce is valid");
+    assert_eq!(evm.logs_for_event(contract.events().foo()).len(), 2);
+
+    // replay-protected transactions are signed for the chain with EIP-155
+    let hash = evm.queue_transaction(&emit_foo).unwrap();
+    assert_eq!(evm.pending_transactions()[0].chain_id(), Some(evm.chain_id()));
+    evm.mine();
+    assert!(evm.receipt(&hash).unwrap().status);
+}
+
+#[test]
+fn estimated_gas_should_be_the_lowest_sufficient_limit() {
+    let gas_test = ethabi::Contract::load(&include_bytes!("../contracts/test_sol_GasTest.abi")[..]).unwrap();
+    let gas_caller = ethabi::Contract::load(&include_bytes!("../contracts/test_sol_GasCaller.abi")[..]).unwrap();
+    let gas_test_code = include_str!("../contracts/test_sol_GasTest.bin").from_hex().unwrap();
+    let gas_caller_code = include_str!("../contracts/test_sol_GasCaller.bin").from_hex().unwrap();
+
+    let mut evm = solaris::evm();
+    let deploy_gas = evm.estimate_deploy_gas(&gas_test_code).unwrap();
+    let target = evm.with_gas(deploy_gas)
+        .deploy(&gas_test_code)
+        .expect("contract deployment should succeed");
+    assert!(evm.account(&target).map_or(false, |account| !account.code.is_empty()));
+    let caller = evm.with_gas(4_000_000.into())
+        .deploy(&gas_caller_code)
+        .unwrap();
+
+    // the sub-call gets only 63/64 of the remaining gas
+    let data = gas_caller
+        .function("callFill")
+        .unwrap()
+        .encode_input(&[ethabi::Token::Address(target), ethabi::Token::Uint(5.into())])
+        .unwrap();
+    let estimate = evm.estimate_gas_to(caller, data.clone()).unwrap();
+    assert!(!evm.with_gas(estimate - U256::one())
+        .transact_to(caller, data.clone())
+        .unwrap()
+        .is_success());
+    assert!(evm.with_gas(estimate).transact_to(caller, data).unwrap().is_success());
+
+    let fill = gas_test
+        .function("fill")
+        .unwrap()
+        .encode_input(&[ethabi::Token::Uint(1000.into())])
+        .unwrap();
+    match *evm.estimate_gas_to(target, fill).unwrap_err().kind() {
+        solaris::error::ErrorKind::Reverted(_) => {}
+        ref kind => panic!("Unexpected error {}", kind),
+    }
+}
+
+#[test]
+fn estimated_gas_should_be_capped_by_the_sender_balance() {
+    let mut evm = solaris::evm();
+    let gas_price = solaris::wei::from_gwei(1);
+    let sender = evm.sender();
+    evm.set_balance(sender, gas_price * U256::from(25_000));
+
+    let receiver: Address = 0x42.into();
+    let estimate = evm.with_gas_price(gas_price)
+        .estimate_gas_to(receiver, vec![])
+        .unwrap();
+    assert_eq!(estimate, 21_000.into());
+}
+
+#[test]
+fn static_calls_should_fail_on_state_changes_and_simulations_keep_logs() {
+    let code_hex = include_str!("../contracts/test_sol_ViewTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+    let mut evm = solaris::evm();
+    let address = evm.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+
+    let selector = |signature: &str| solaris::abi::selector(signature).to_vec();
+    assert!(evm.static_call_to(address, selector("sneaky()")).is_err());
+    assert_eq!(U256::from(&evm.static_call_to(address, selector("counter()")).unwrap()[..]), 0.into());
+    assert_eq!(U256::from(&evm.call_to(address, selector("sneaky()")).unwrap()[..]), 1.into());
+
+    let output = evm.simulate_to(address, selector("touch()")).unwrap();
+    assert_eq!(U256::from(output.output()), 5_000_000.into());
+    assert_eq!(output.logs().len(), 1);
+    assert_eq!(output.trace().len(), 1);
+
+    let mut env = evm.block_env();
+    env.number = 42;
+    let output = evm.simulate_at(&env, address, selector("touch()")).unwrap();
+    assert_eq!(U256::from(output.output()), 42.into());
+
+    let output = evm.static_call_at(&env, address, selector("number()")).unwrap();
+    assert!(output.is_success());
+    assert_eq!(U256::from(output.output()), 42.into());
+    let output = evm.static_call_at(&env, address, selector("sneaky()")).unwrap();
+    assert!(!output.is_success());
+    assert!(output.trace()[0].error.is_some());
+
+    // nothing of the simulations is committed
+    assert!(evm.raw_logs().is_empty());
+    assert_eq!(evm.account(&0.into()).unwrap().nonce, 1.into());
+}
+
+#[test]
+fn state_of_past_blocks_should_be_queryable() {
+    let code_hex = include_str!("../contracts/test_sol_Counter.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+    let count = || solaris::abi::selector("count()").to_vec();
+    let increment = || solaris::abi::selector("increment()").to_vec();
+
+    let mut evm = solaris::evm();
+    evm.with_block_number(100);
+    let counter = evm.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+    evm.transact_to(counter, increment()).unwrap();
+    let root_100 = evm.state_root_at(100).unwrap();
+
+    evm.with_block_number(101);
+    evm.transact_to(counter, increment()).unwrap();
+    evm.with_block_number(103);
+    evm.transact_to(counter, increment()).unwrap();
+
+    let count_at = |evm: &mut solaris::evm::Evm, number| {
+        U256::from(&evm.call_at_block_to(number, counter, count()).unwrap()[..])
+    };
+    assert_eq!(count_at(&mut evm, 100), 1.into());
+    assert_eq!(count_at(&mut evm, 101), 2.into());
+    assert_eq!(count_at(&mut evm, 102), 2.into());
+    assert_eq!(count_at(&mut evm, 103), 3.into());
+    assert!(evm.call_at_block_to(104, counter, count()).is_err());
+    assert!(evm.state_at(99).is_err());
+
+    assert_eq!(evm.state_root_at(100).unwrap(), root_100);
+    assert_eq!(evm.storage_at(101, &counter, &0.into()).unwrap(), 2.into());
+    assert_eq!(evm.balance_at(100, &counter).unwrap(), 0.into());
+
+    let changed = solaris::state::diff(&evm.state_at(100).unwrap(), &evm.state_at(103).unwrap());
+    assert!(changed.contains_key(&counter));
+
+    // querying the past doesn't change the present
+    assert_eq!(U256::from(&evm.call_to(counter, count()).unwrap()[..]), 3.into());
+}
+
+#[test]
+fn mined_blocks_should_order_transactions_by_gas_price() {
+    use solaris::evm::TxRequest;
+
+    let code_hex = include_str!("../contracts/test_sol_FirstComeTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+    let mut evm = solaris::evm();
+    evm.with_accounts(solaris::accounts::DEFAULT_SEED, 2);
+    let alice = evm.accounts()[0].address();
+    let bob = evm.accounts()[1].address();
+    let address = evm.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+
+    let claim = solaris::abi::selector("claim()").to_vec();
+    let alice_claim = evm.queue_transaction(TxRequest::call(alice, address, claim.clone())
+        .with_gas(100_000.into())
+        .with_gas_price(solaris::wei::from_gwei(1)))
+        .unwrap();
+    let bob_claim = evm.queue_transaction(TxRequest::call(bob, address, claim.clone())
+        .with_gas(100_000.into())
+        .with_gas_price(solaris::wei::from_gwei(2)))
+        .unwrap();
+    assert_eq!(evm.pending_transactions().len(), 2);
+
+    let number = evm.block_env().number;
+    let timestamp = evm.block_env().timestamp;
+    let block = evm.mine();
+    assert_eq!(block.number, number);
+    assert_eq!(evm.block_env().number, number + 1);
+    assert_eq!(evm.block_env().timestamp, timestamp + 15);
+    assert!(evm.pending_transactions().is_empty());
+
+    let hashes: Vec<_> = block.receipts.iter().map(|receipt| receipt.transaction_hash).collect();
+    assert_eq!(hashes, vec![bob_claim, alice_claim]);
+    assert_eq!(
+        block.receipts[1].cumulative_gas_used,
+        block.receipts[0].gas_used + block.receipts[1].gas_used
+    );
+    assert_eq!(block.gas_used, block.receipts[1].cumulative_gas_used);
+    let winner = evm.call_to(address, solaris::abi::selector("winner()").to_vec()).unwrap();
+    assert_eq!(Address::from(&winner[12..]), bob);
+
+    // only one transaction fits in a block
+    evm.with_block_gas_limit(150_000.into());
+    evm.queue_transaction(TxRequest::call(alice, address, claim.clone()).with_gas(100_000.into()))
+        .unwrap();
+    evm.queue_transaction(TxRequest::call(alice, address, claim.clone()).with_gas(100_000.into()))
+        .unwrap();
+    assert_eq!(evm.mine().receipts.len(), 1);
+    assert_eq!(evm.pending_transactions().len(), 1);
+    let block = evm.mine();
+    assert_eq!(block.receipts.len(), 1);
+    assert!(block.rejected.is_empty());
+
+    // replays are rejected without the receipt of the original transaction
+    let nonce = evm.transaction(&block.receipts[0].transaction_hash).unwrap().nonce;
+    let replay = evm.queue_transaction(
+        TxRequest::call(alice, address, claim.clone())
+            .with_gas(100_000.into())
+            .with_nonce(nonce),
+    ).unwrap();
+    assert_eq!(replay, block.receipts[0].transaction_hash);
+    let block = evm.mine();
+    assert!(block.receipts.is_empty());
+    assert_eq!(block.rejected.len(), 1);
+
+    // transactions which can't fit in any block are rejected
+    evm.queue_transaction(TxRequest::call(alice, address, claim).with_gas(200_000.into()))
+        .unwrap();
+    let block = evm.mine();
+    assert!(block.receipts.is_empty());
+    assert_eq!(block.rejected.len(), 1);
+    assert!(evm.pending_transactions().is_empty());
+}
+
+#[test]
+fn executed_transactions_should_be_kept_with_receipts() {
+    let contract = event_log_test::EventLogTest::default();
+    let code_hex = include_str!("../contracts/test_sol_EventLogTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+
+    let mut evm = solaris::evm();
+    let sender: Address = 7.into();
+    let address = evm.with_sender(sender)
+        .deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+    let output = evm.transact(contract.functions().emit_bar(5)).unwrap();
+
+    {
+        let transactions = evm.transactions();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].receipt.contract_address, Some(address));
+        assert_eq!(transactions[0].to, None);
+
+        let transaction = evm.transaction(&output.hash()).unwrap();
+        assert_eq!(transaction.from, sender);
+        assert_eq!(transaction.nonce, 1.into());
+        assert_eq!(transaction.to, Some(address));
+        assert_eq!(transaction.trace.len(), 1);
+
+        let receipt = evm.receipt(&output.hash()).unwrap();
+        assert!(receipt.status);
+        assert_eq!(receipt.logs.len(), 1);
+        assert!(receipt.logs_bloom.contains_input(types::BloomInput::Raw(&address[..])));
+        assert_eq!(
+            receipt.cumulative_gas_used,
+            transactions[0].receipt.gas_used + receipt.gas_used
+        );
+    }
+
+    // simulated transactions are not kept
+    evm.simulate(contract.functions().emit_bar(6)).unwrap();
+    assert_eq!(evm.transactions().len(), 2);
+}
+
+#[test]
+fn deployed_addresses_should_be_predicted() {
+    let contract = factory_test::FactoryTest::default();
+    let code_hex = include_str!("../contracts/test_sol_FactoryTest.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+
+    let mut evm = solaris::evm();
+    let sender: Address = 8.into();
+    let predicted = evm.next_deploy_address(&sender);
+    assert_eq!(predicted, solaris::address::create_address(&sender, &0.into()));
+
+    let factory = evm.with_sender(sender)
+        .deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+    assert_eq!(factory, predicted);
+    assert_eq!(evm.next_deploy_address(&sender), solaris::address::create_address(&sender, &1.into()));
+
+    // contracts start with nonce 1
+    let output = evm.transact(contract.functions().create()).unwrap();
+    assert_eq!(
+        output.created_contracts(),
+        vec![solaris::address::create_address(&factory, &1.into())]
+    );
+}
+
+use_contract!(token_test, "TokenTest", "contracts/test_sol_TokenTest.abi");
+
+#[test]
+fn balance_changes_should_separate_gas_fees() {
+    use solaris::balances::Delta;
+
+    let value_contract = get_value_test::GetValueTest::default();
+    let token_contract = token_test::TokenTest::default();
+
+    let mut evm = solaris::evm();
+    evm.with_accounts(solaris::accounts::DEFAULT_SEED, 2);
+    let alice = evm.accounts()[0].address();
+    let bob = evm.accounts()[1].address();
+
+    let token = evm.with_sender(alice)
+        .deploy(&include_str!("../contracts/test_sol_TokenTest.bin").from_hex().unwrap())
+        .expect("contract deployment should succeed");
+    let receiver = evm.deploy(&include_str!("../contracts/test_sol_GetValueTest.bin").from_hex().unwrap())
+        .expect("contract deployment should succeed");
+
+    let mut tracker = evm.track_balances(&[alice, receiver]);
+    tracker.track_token(&mut evm, token, &[alice, bob]).unwrap();
+
+    let value = solaris::wei::from_ether(1);
+    let output = evm.with_gas_price(solaris::wei::from_gwei(1))
+        .with_value(value)
+        .transact(value_contract.functions().get_value())
+        .unwrap();
+    assert!(output.is_success());
+    evm.with_value(0.into())
+        .transact_to(token, token_contract.functions().transfer(bob, 30).encoded())
+        .unwrap();
+
+    tracker.assert_change(&evm, alice, Delta::decrease(value));
+    tracker.assert_change(&evm, receiver, Delta::increase(value));
+    let change = tracker.change(&evm, &alice).unwrap();
+    assert!(change.fees > 0.into());
+    tracker.assert_change_with_fees(&evm, alice, Delta::decrease(value + change.fees));
+
+    tracker.assert_token_change(&mut evm, token, alice, Delta::decrease(30));
+    tracker.assert_token_change(&mut evm, token, bob, Delta::increase(30));
+}
+
+#[test]
+fn saved_state_should_be_loaded_into_a_new_evm() {
+    let code_hex = include_str!("../contracts/test_sol_Counter.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+    let count = || solaris::abi::selector("count()").to_vec();
+
+    let mut evm = solaris::evm();
+    evm.with_block_number(200).with_chain_id(17);
+    let counter = evm.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+    evm.transact_to(counter, solaris::abi::selector("increment()").to_vec())
+        .unwrap();
+    evm.set_label(counter, "counter");
+
+    let path = std::env::temp_dir().join(format!("solaris-state-{}.json", std::process::id()));
+    evm.save_state(&path).unwrap();
+
+    let mut loaded = solaris::evm();
+    loaded.load_state(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.account(&counter), evm.account(&counter));
+    assert_eq!(loaded.block_env().number, 200);
+    assert_eq!(loaded.chain_id(), 17);
+    assert_eq!(loaded.abi().label(&counter), Some("counter"));
+    assert_eq!(U256::from(&loaded.call_to(counter, count()).unwrap()[..]), 1.into());
+
+    let accounts = evm.dump().chain_spec_accounts();
+    assert_eq!(
+        accounts[format!("{:?}", counter)]["storage"][format!("{:?}", types::H256::zero())],
+        format!("{:?}", types::H256::from(1))
+    );
+}
+
+#[test]
+fn forked_accounts_should_be_materialised_when_touched() {
+    use rustc_hex::ToHex;
+
+    let code_hex = include_str!("../contracts/test_sol_Counter.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+    let count = || solaris::abi::selector("count()").to_vec();
+    let increment = || solaris::abi::selector("increment()").to_vec();
+
+    // state of the node to fork from
+    let mut remote = solaris::evm();
+    let counter = remote.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+    remote.transact_to(counter, increment()).unwrap();
+    let runtime_code = remote.account(&counter).unwrap().code;
+
+    let mut source = solaris::fork::ForkSource::default();
+    let dump_path = std::env::temp_dir().join(format!("solaris-fork-dump-{}.json", std::process::id()));
+    remote.save_state(&dump_path).unwrap();
+    source.load_state_dump(&dump_path).unwrap();
+    std::fs::remove_file(&dump_path).unwrap();
+
+    let recorded: Address = 0x1234.into();
+    let recordings = format!(
+        r#"[
+            {{
+                "request": {{ "method": "eth_getCode", "params": ["{:?}", "latest"] }},
+                "response": {{ "jsonrpc": "2.0", "id": 1, "result": "0x{}" }}
+            }},
+            {{
+                "request": {{ "method": "eth_getStorageAt", "params": ["{:?}", "0x0", "latest"] }},
+                "response": {{ "jsonrpc": "2.0", "id": 2, "result": "0x29" }}
+            }}
+        ]"#,
+        recorded,
+        runtime_code.to_hex(),
+        recorded
+    );
+    let recordings_path = std::env::temp_dir().join(format!("solaris-fork-rpc-{}.json", std::process::id()));
+    std::fs::write(&recordings_path, recordings).unwrap();
+    source.load_recordings(&recordings_path).unwrap();
+    std::fs::remove_file(&recordings_path).unwrap();
+
+    let mut evm = solaris::evm();
+    evm.with_fork(source);
+    assert_eq!(evm.account(&counter), None);
+
+    assert_eq!(U256::from(&evm.call_to(counter, count()).unwrap()[..]), 1.into());
+    assert!(evm.transact_to(counter, increment()).unwrap().is_success());
+    assert_eq!(U256::from(&evm.call_to(counter, count()).unwrap()[..]), 2.into());
+    assert_eq!(U256::from(&evm.call_to(recorded, count()).unwrap()[..]), 41.into());
+}
+
+#[test]
+fn forked_senders_should_transact_with_their_forked_nonce() {
+    let code_hex = include_str!("../contracts/test_sol_Counter.bin");
+    let code_bytes = code_hex.from_hex().unwrap();
+    let count = || solaris::abi::selector("count()").to_vec();
+    let increment = || solaris::abi::selector("increment()").to_vec();
+
+    let mut remote = solaris::evm();
+    let counter = remote.deploy(&code_bytes)
+        .expect("contract deployment should succeed");
+    let runtime_code = remote.account(&counter).unwrap().code;
+
+    let sender: Address = 0x5e4d.into();
+    let mut source = solaris::fork::ForkSource::default();
+    source
+        .insert(sender, solaris::state::Account {
+            balance: solaris::wei::from_ether(1),
+            nonce: 5.into(),
+            ..Default::default()
+        })
+        .insert(counter, solaris::state::Account {
+            code: runtime_code,
+            ..Default::default()
+        });
+
+    let mut evm = solaris::evm();
+    evm.with_fork(source);
+    assert_eq!(evm.next_deploy_address(&sender), solaris::address::create_address(&sender, &5.into()));
+
+    evm.with_sender(sender).with_gas_price(solaris::wei::from_gwei(1));
+    assert!(evm.transact_to(counter, increment()).unwrap().is_success());
+    assert_eq!(evm.account(&sender).unwrap().nonce, 6.into());
+    assert!(evm.account(&sender).unwrap().balance < solaris::wei::from_ether(1));
+    assert_eq!(U256::from(&evm.call_to(counter, count()).unwrap()[..]), 1.into());
+}